@@ -0,0 +1,133 @@
+// Authors: Victor Vu
+// File: lib.rs
+// Description: Fixed size system state for no_std targets, sized by const generics and free of heap allocation
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+// Only core is used here so the crate builds on targets without std or alloc, e.g. thumbv7em-none-eabihf.
+#![cfg_attr(not(test), no_std)] // the test harness itself needs std
+#![allow(clippy::needless_range_loop)] // index loops mirror the textbook matrix notation
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct ConstSystemState<const N: usize, const R: usize> { // Struct to hold the state of N processes and R resources in arrays
+    pub available: [i32; R], // available resources
+    pub max: [[i32; R]; N], // max resources each process may request
+    pub allocated: [[i32; R]; N], // resources allocated to each process
+    pub need: [[i32; R]; N], // resources each process still needs
+    pub completed: [bool; N], // processes that released everything and finished
+}
+
+impl<const N: usize, const R: usize> ConstSystemState<N, R> {
+    pub fn new(available: [i32; R], max: [[i32; R]; N], allocated: [[i32; R]; N]) -> Option<Self> { // Build a state with need = max - allocated, None if an amount is negative or above max
+        let mut need = [[0; R]; N];
+        for i in 0..N {
+            for j in 0..R {
+                if allocated[i][j] < 0 || allocated[i][j] > max[i][j] {
+                    return None;
+                }
+                need[i][j] = max[i][j] - allocated[i][j];
+            }
+        }
+        if available.iter().any(|&a| a < 0) {
+            return None;
+        }
+        Some(ConstSystemState { available, max, allocated, need, completed: [false; N] })
+    }
+}
+
+pub fn safe_check_const<const N: usize, const R: usize>(state: &ConstSystemState<N, R>) -> bool { // Function to see if the system is in a safe state
+    let mut work = state.available; // resources available as processes finish
+    let mut done = state.completed;
+    loop {
+        let mut found = false;
+        for i in 0..N {
+            if !done[i] && (0..R).all(|j| state.need[i][j] <= work[j]) { // the process can finish with what is available
+                for j in 0..R {
+                    work[j] += state.allocated[i][j];
+                }
+                done[i] = true;
+                found = true;
+            }
+        }
+        if !found {
+            return done.iter().all(|&d| d); // safe if every process could finish
+        }
+    }
+}
+
+pub fn request_resource_const<const N: usize, const R: usize>(state: &mut ConstSystemState<N, R>, process_id: usize, request: &[i32; R]) -> bool { // Function to grant a request if it keeps the system safe
+    if process_id >= N || state.completed[process_id] {
+        return false;
+    }
+    if (0..R).any(|j| request[j] < 0 || request[j] > state.need[process_id][j] || request[j] > state.available[j]) {
+        return false; // negative, more than needed or more than available
+    }
+    for j in 0..R { // grant tentatively
+        state.available[j] -= request[j];
+        state.allocated[process_id][j] += request[j];
+        state.need[process_id][j] -= request[j];
+    }
+    if safe_check_const(state) {
+        return true;
+    }
+    for j in 0..R { // roll back, the grant would be unsafe
+        state.available[j] += request[j];
+        state.allocated[process_id][j] -= request[j];
+        state.need[process_id][j] += request[j];
+    }
+    false
+}
+
+pub fn release_resource_const<const N: usize, const R: usize>(state: &mut ConstSystemState<N, R>, process_id: usize) { // Function to return everything a process holds and mark it completed
+    if process_id >= N {
+        return;
+    }
+    for j in 0..R {
+        state.available[j] += state.allocated[process_id][j];
+        state.allocated[process_id][j] = 0;
+        state.need[process_id][j] = state.max[process_id][j]; // like release_resource, a new run starts from the max claim
+    }
+    state.completed[process_id] = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classic() -> ConstSystemState<5, 3> { // the textbook state with 5 processes and 3 resources
+        ConstSystemState::new(
+            [3, 3, 2],
+            [[7, 5, 3], [3, 2, 2], [9, 0, 2], [2, 2, 2], [4, 3, 3]],
+            [[0, 1, 0], [2, 0, 0], [3, 0, 2], [2, 1, 1], [0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn textbook_state_is_safe_and_runs_to_completion() {
+        let mut state = classic();
+        assert!(safe_check_const(&state));
+        for pid in [1, 3, 4, 0, 2] { // the textbook safe sequence
+            let need = state.need[pid];
+            assert!(request_resource_const(&mut state, pid, &need), "P{} can't get {:?}", pid, need);
+            release_resource_const(&mut state, pid);
+        }
+        assert_eq!(state.completed, [true; 5]);
+        assert_eq!(state.available, [10, 5, 7]);
+    }
+
+    #[test]
+    fn unsafe_and_malformed_requests_leave_the_state_unchanged() {
+        let mut state = classic();
+        assert!(request_resource_const(&mut state, 1, &[1, 0, 2]));
+        let before = state;
+        assert!(!request_resource_const(&mut state, 0, &[0, 2, 0])); // the textbook unsafe request
+        assert!(!request_resource_const(&mut state, 4, &[3, 0, 0])); // more than available
+        assert!(!request_resource_const(&mut state, 2, &[-1, 0, 0]));
+        assert!(!request_resource_const(&mut state, 5, &[0, 0, 0])); // no such process
+        assert_eq!(state, before);
+        release_resource_const(&mut state, 1);
+        assert!(!request_resource_const(&mut state, 1, &[0, 0, 0])); // completed
+        assert!(ConstSystemState::<1, 1>::new([0], [[1]], [[2]]).is_none()); // allocated above max
+        assert!(ConstSystemState::<1, 1>::new([-1], [[1]], [[0]]).is_none());
+    }
+}
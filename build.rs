@@ -0,0 +1,29 @@
+// Authors: Victor Vu
+// File: build.rs
+// Description: Writes the C header for the ffi feature with cbindgen
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    write_header();
+}
+
+#[cfg(feature = "ffi")]
+fn write_header() { // Function to generate bankers.h in OUT_DIR from the extern "C" functions in src/ffi.rs, and copy it to BANKERS_HEADER_DIR if set
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-env-changed=BANKERS_HEADER_DIR");
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+    let out_dir = std::env::var("OUT_DIR").expect("cargo sets OUT_DIR");
+    let mut config = cbindgen::Config { language: cbindgen::Language::C, include_guard: Some("BANKERS_H".to_string()), ..Default::default() };
+    config.export.item_types = vec![cbindgen::ItemType::Functions, cbindgen::ItemType::OpaqueItems]; // the crate's constants aren't part of the interface
+    let header = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("cbindgen can't read src/ffi.rs");
+    header.write_to_file(std::path::Path::new(&out_dir).join("bankers.h")); // the build never writes outside target unless asked to
+    if let Ok(dir) = std::env::var("BANKERS_HEADER_DIR") {
+        header.write_to_file(std::path::Path::new(&crate_dir).join(dir).join("bankers.h")); // relative to the crate, e.g. include
+    }
+}
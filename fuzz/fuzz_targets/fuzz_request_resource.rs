@@ -0,0 +1,65 @@
+// Authors: Victor Vu
+// File: fuzz_request_resource.rs
+// Description: Fuzz target checking request_resource always leaves a consistent state
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vu_bankers_algo::{is_consistent, request_resource, safe_check, SystemState};
+
+struct Bytes<'a> { // Struct to read small numbers from the fuzzer input, zeros once it runs out
+    data: &'a [u8],
+}
+
+impl Bytes<'_> {
+    fn next(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    fn below(&mut self, bound: u8) -> i32 { // number in 0..bound
+        (self.next() % bound) as i32
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut bytes = Bytes { data };
+    let process_amount = 1 + bytes.below(4) as usize;
+    let resource_amount = 1 + bytes.below(3) as usize;
+    let available: Vec<i32> = (0..resource_amount).map(|_| bytes.below(8)).collect();
+    let max: Vec<Vec<i32>> = (0..process_amount).map(|_| (0..resource_amount).map(|_| bytes.below(8)).collect()).collect();
+    let allocated: Vec<Vec<i32>> = max.iter().map(|row| row.iter().map(|&m| bytes.below(m as u8 + 1)).collect()).collect(); // never above max
+    let mut state = match SystemState::from_matrices(available, max, allocated) {
+        Ok(state) => state,
+        Err(_) => return,
+    };
+    let process_id = bytes.below(process_amount as u8 + 1) as usize; // one past the end to exercise invalid ids
+    let request: Vec<i32> = (0..resource_amount).map(|_| bytes.below(10) - 1).collect(); // -1 to exercise negative amounts
+
+    let before = state.clone();
+    let was_safe = safe_check(&state);
+    let granted = request_resource(&mut state, process_id, &request);
+
+    assert!(is_consistent(&state), "inconsistent after P{} requested {:?}:\n{}", process_id, request, state);
+    for i in 0..state.process_count() {
+        let expected: Vec<i32> = state.max(i).iter().zip(state.allocated(i)).map(|(m, a)| m - a).collect();
+        assert_eq!(state.need(i), &expected[..], "P{} need doesn't match max - allocated", i);
+        assert!(state.need(i).iter().all(|&n| n >= 0), "P{} has negative need", i);
+    }
+    if was_safe && !granted { // a denial only records the request, the matrices stay as they were
+        assert_eq!(state.available(), before.available());
+        for i in 0..state.process_count() {
+            assert_eq!(state.allocated(i), before.allocated(i));
+            assert_eq!(state.need(i), before.need(i));
+        }
+    }
+    if granted {
+        assert!(safe_check(&state), "granted P{} {:?} into an unsafe state", process_id, request);
+    }
+});
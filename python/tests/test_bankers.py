@@ -0,0 +1,56 @@
+# Authors: Victor Vu
+# File: test_bankers.py
+# Description: Tests of the Python module, run with maturin develop && pytest python/tests/
+# Copyright (C) 2024 Victor V. Vu
+# License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+import pytest
+from vu_bankers_algo import SystemState
+
+
+def classic():  # the textbook state with 5 processes and 3 resources
+    return SystemState(
+        [3, 3, 2],
+        [[7, 5, 3], [3, 2, 2], [9, 0, 2], [2, 2, 2], [4, 3, 3]],
+        [[0, 1, 0], [2, 0, 0], [3, 0, 2], [2, 1, 1], [0, 0, 2]],
+    )
+
+
+def test_safe_check_returns_the_safe_sequence():
+    assert classic().safe_check() == [1, 3, 4, 0, 2]
+
+
+def test_safe_check_is_none_for_an_unsafe_state():
+    assert SystemState([0], [[5], [2]], [[1], [1]]).safe_check() is None
+
+
+def test_safe_request_is_granted_and_unsafe_one_denied():
+    state = classic()
+    assert state.request_resource(1, [1, 0, 2])
+    assert not state.request_resource(0, [0, 2, 0])  # the textbook unsafe request
+    assert state.safe_check() is not None
+
+
+def test_release_lets_every_process_finish():
+    state = classic()
+    assert state.request_resource(1, [1, 2, 2])
+    state.release_resource(1)
+    assert state.safe_check() == [3, 4, 0, 2]  # P1 already completed
+
+
+def test_unknown_process_raises_value_error():
+    state = classic()
+    with pytest.raises(ValueError, match="P5 doesn't exist"):
+        state.request_resource(5, [0, 0, 0])
+    with pytest.raises(ValueError):
+        state.release_resource(9)
+
+
+def test_wrong_dimensions_raise_value_error():
+    with pytest.raises(ValueError):
+        classic().request_resource(0, [1, 0])
+    with pytest.raises(ValueError):
+        SystemState([1, 1], [[1, 1]], [[1]])
+
+
+def test_repr_names_the_class():
+    assert repr(classic()).startswith("SystemState(")
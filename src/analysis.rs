@@ -0,0 +1,1075 @@
+// Authors: Victor Vu
+// File: analysis.rs
+// Description: Analysis tools for capacity planning on top of the safety check
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::cache::state_hash;
+use crate::error::{BankersError, VerifyError};
+use crate::state::{detect_deadlock, find_safe_sequence, request_without_avoidance, safe_check, ResourceKind, SystemState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum StateClass { // Enum of how a state relates to deadlock
+    Safe, // some order lets every process finish
+    UnsafeButNotDeadlocked, // no order is guaranteed to work, but the processes can still make progress
+    Deadlocked, // some processes can never make progress again
+}
+
+pub const PATH_SEARCH_PROCESS_LIMIT: usize = 6; // most processes shortest_path_to_unsafe searches, the state space grows too fast beyond
+const PATH_SEARCH_REQUEST_LIMIT: usize = 4096; // requests of one process tried from each state
+pub const PATH_SEARCH_NODE_LIMIT: usize = 100_000; // states shortest_path_to_unsafe queues before it gives up
+
+pub type GrantPath = Vec<(usize, Vec<i32>)>; // grants in order, each a process and what it was given
+pub const AUGMENTATION_SEARCH_LIMIT: usize = 100_000; // safety checks minimal_augmentation tries before giving up on minimality
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum Augmentation { // Enum of the outcomes of the search for extra available
+    AlreadySafe, // nothing has to be added
+    Minimal(Vec<i32>), // smallest by sum, ties broken lexicographically
+    Capped { reached: i32, suggestion: Vec<i32> }, // every total below reached is unsafe, suggestion is safe but may not be minimal
+}
+
+impl fmt::Display for StateClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateClass::Safe => write!(f, "Safe"),
+            StateClass::UnsafeButNotDeadlocked => write!(f, "Unsafe but not deadlocked"),
+            StateClass::Deadlocked => write!(f, "Deadlocked"),
+        }
+    }
+}
+
+pub fn min_additional_for_safety(state: &SystemState) -> Option<Vec<i32>> { // Function to find extra available that makes an unsafe state safe, None if already safe
+    if safe_check(state) {
+        return None;
+    }
+    let mut work = state.available.clone(); // resources available as processes finish
+    let mut extra = vec![0; state.resource_count()]; // units added so far
+    let mut done: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i)).collect();
+
+    while done.iter().any(|&d| !d) { // Loop until every process finished
+        let shortfall = |i: usize, work: &[i32]| -> i32 { state.need[i].iter().zip(work).map(|(n, w)| (n - w).max(0)).sum() };
+        let next = (0..state.process_count()) // greedily pick the process that is cheapest to unblock, one that fits costs nothing
+            .filter(|&i| !done[i])
+            .min_by_key(|&i| shortfall(i, &work))
+            .unwrap();
+        for j in 0..work.len() {
+            let missing = (state.need[next][j] - work[j]).max(0);
+            extra[j] += missing; // add what the process still lacks
+            work[j] += missing + state.held(next, j); // then it finishes and releases its allocation
+        }
+        done[next] = true;
+    }
+
+    let mut trial = state.clone(); // the greedy pass can overshoot, trim each resource while the state stays safe
+    for j in 0..extra.len() {
+        while extra[j] > 0 {
+            extra[j] -= 1;
+            for r in 0..extra.len() {
+                trial.available[r] = state.available[r] + extra[r];
+            }
+            if !safe_check(&trial) {
+                extra[j] += 1; // one less is unsafe, keep this amount
+                break;
+            }
+        }
+    }
+    Some(extra)
+}
+
+pub fn verify_sequence(state: &SystemState, sequence: &[usize]) -> Result<(), VerifyError> { // Function to check that running the processes in this order lets every one finish
+    let mut work = state.available.clone(); // resources available as processes finish
+    let mut ran = vec![false; state.process_count()];
+    for (index, &process) in sequence.iter().enumerate() {
+        let step = index + 1;
+        if process >= state.process_count() {
+            return Err(VerifyError::Invalid(BankersError::InvalidProcess(process)));
+        }
+        if state.is_finished(process) {
+            return Err(VerifyError::Invalid(BankersError::AlreadyFinished(process)));
+        }
+        if ran[process] {
+            return Err(VerifyError::Repeated { step, process });
+        }
+        if let Some(resource) = (0..work.len()).find(|&j| state.need[process][j] > work[j]) { // the process takes its whole remaining need
+            return Err(VerifyError::CannotRun { step, process, resource, short_by: state.need[process][resource] - work[resource] });
+        }
+        for j in 0..work.len() {
+            work[j] += state.held(process, j); // then finishes and releases its allocation
+        }
+        ran[process] = true;
+    }
+    let missing: Vec<usize> = (0..state.process_count()).filter(|&i| !ran[i] && !state.is_finished(i)).collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(VerifyError::Incomplete { missing })
+    }
+}
+
+pub fn sequence_table(state: &SystemState, sequence: &[usize]) -> String { // Function to print the work vector before and after each step, up to the first step that can't run
+    let mut work = state.available.clone();
+    let mut ran = vec![false; state.process_count()];
+    let mut rows = vec![["Step".to_string(), "Process".to_string(), "Need".to_string(), "Work before".to_string(), "Work after".to_string()]];
+    for (index, &process) in sequence.iter().enumerate() {
+        if process >= state.process_count() {
+            break; // verify_sequence explains the invalid id
+        }
+        let mut row = [(index + 1).to_string(), format!("P{}", process), format!("{:?}", state.need[process]), format!("{:?}", work), String::new()];
+        match (0..work.len()).find(|&j| state.need[process][j] > work[j]) {
+            _ if ran[process] => {
+                row[4] = "already ran".to_string();
+                rows.push(row);
+                break;
+            }
+            Some(j) => {
+                row[4] = format!("can't run, R{} short by {}", j, state.need[process][j] - work[j]);
+                rows.push(row);
+                break;
+            }
+            None => {
+                for j in 0..work.len() {
+                    work[j] += state.held(process, j);
+                }
+                ran[process] = true;
+                row[4] = format!("{:?}", work);
+                rows.push(row);
+            }
+        }
+    }
+    format_table(&rows)
+}
+
+fn format_table<const C: usize>(rows: &[[String; C]]) -> String { // Function to print rows as left aligned columns separated by " | "
+    let widths: Vec<usize> = (0..C).map(|c| rows.iter().map(|row| row[c].len()).max().unwrap_or(0)).collect();
+    rows.iter()
+        .map(|row| (0..C).map(|c| format!("{:<w$}", row[c], w = widths[c])).collect::<Vec<_>>().join(" | ").trim_end().to_string() + "\n")
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)] // Derive traits for printing, copy and JSON output of struct
+pub struct SafetyStep { // Struct to hold one process the safety check let finish
+    pub process: usize, // process selected in this iteration
+    pub work: Vec<i32>, // work after the process released what it holds
+    pub finish: Vec<bool>, // finish flag of every process after this iteration
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)] // Derive traits for printing, copy and JSON output of struct
+pub struct SafetyTrace { // Struct to hold every step of one run of the safety check
+    pub initial_work: Vec<i32>, // work before the first iteration, the available resources
+    pub initial_finish: Vec<bool>, // finish flags before the first iteration, true for completed and crashed processes
+    pub steps: Vec<SafetyStep>, // the processes in the order they were selected
+    pub safe: bool, // the verdict, every finish flag ended up true
+}
+
+impl fmt::Display for SafetyTrace { // Print as a table with one row per step and the verdict
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = |finish: &[bool]| finish.iter().map(|&d| if d { 'T' } else { 'F' }).collect::<String>();
+        let mut rows = vec![["Step".to_string(), "Process".to_string(), "Work".to_string(), "Finish".to_string()]];
+        rows.push(["0".to_string(), "-".to_string(), format!("{:?}", self.initial_work), flags(&self.initial_finish)]);
+        for (index, step) in self.steps.iter().enumerate() {
+            rows.push([(index + 1).to_string(), format!("P{}", step.process), format!("{:?}", step.work), flags(&step.finish)]);
+        }
+        write!(f, "{}", format_table(&rows))?;
+        writeln!(f, "{}", if self.safe { "Safe: every process can finish." } else { "Unsafe: no remaining process can finish with the work left." })
+    }
+}
+
+pub fn safe_check_traced(state: &SystemState) -> SafetyTrace { // Function to run the safety check like find_safe_sequence, recording every step
+    let mut work = state.available.clone(); // resources available as processes finish
+    let mut finish: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i)).collect();
+    let mut trace = SafetyTrace { initial_work: work.clone(), initial_finish: finish.clone(), steps: Vec::new(), safe: false };
+    loop {
+        let mut found = false;
+        for i in 0..state.process_count() { // same scan order as find_safe_sequence, so the steps follow its sequence
+            if !finish[i] && state.need[i].iter().zip(&work).all(|(n, w)| n <= w) {
+                for j in 0..work.len() {
+                    work[j] += state.held(i, j);
+                }
+                finish[i] = true;
+                found = true;
+                trace.steps.push(SafetyStep { process: i, work: work.clone(), finish: finish.clone() });
+            }
+        }
+        if !found {
+            break;
+        }
+    }
+    trace.safe = finish.iter().all(|&d| d);
+    trace
+}
+
+pub fn all_possible_requests(state: &SystemState, process: usize, limit: usize) -> Result<Vec<Vec<i32>>, BankersError> { // Function to list every request the process could make right now, at most limit of them
+    if process >= state.process_count() {
+        return Err(BankersError::InvalidProcess(process));
+    }
+    let caps: Vec<i32> = (0..state.resource_count()).map(|j| state.need[process][j].min(state.available[j]).max(0)).collect(); // neither more than needed nor than available
+    let count = caps.iter().fold(1u128, |count, &cap| count.saturating_mul(cap as u128 + 1)); // one choice of 0..=cap per resource
+    if count > limit as u128 {
+        return Err(BankersError::TooMany { what: "requests".to_string(), count, limit });
+    }
+    let mut requests = vec![vec![0; caps.len()]];
+    for (j, &cap) in caps.iter().enumerate() { // extend every request with each amount of the next resource
+        requests = requests
+            .into_iter()
+            .flat_map(|request| {
+                (0..=cap).map(move |amount| {
+                    let mut next = request.clone();
+                    next[j] = amount;
+                    next
+                })
+            })
+            .collect();
+    }
+    Ok(requests)
+}
+
+pub fn visualize_safety_boundary(state: &SystemState, r_a: usize, r_b: usize) -> String { // Function to map which amounts of two resources are safe, the others stay at their current values
+    if r_a >= state.resource_count() || r_b >= state.resource_count() || r_a == r_b {
+        return format!("Can't map R{} against R{}: the state has {} resources.\n", r_a, r_b, state.resource_count());
+    }
+    let (width, height) = (state.available[r_a].max(0), state.available[r_b].max(0));
+    let label = width.max(height).to_string().len(); // every column is as wide as the largest amount
+    let mut trial = state.clone();
+    let mut map = format!("R{} (available amount)\n", r_b);
+    for y in (0..=height).rev() { // largest amount at the top like a plot
+        trial.available[r_b] = y;
+        let cells: Vec<String> = (0..=width)
+            .map(|x| {
+                trial.available[r_a] = x;
+                format!("{:>label$}", if safe_check(&trial) { "S" } else { "U" })
+            })
+            .collect();
+        map += &format!("{:>label$} | {}\n", y, cells.join(" "));
+    }
+    let ticks: Vec<String> = (0..=width).map(|x| format!("{:>label$}", x)).collect();
+    map += &format!("{:>label$} +-{}\n", "", "-".repeat(ticks.join(" ").len()));
+    map += &format!("{:>label$}   {}  R{}\n", "", ticks.join(" "), r_a);
+    map
+}
+
+pub fn shortest_path_to_unsafe(initial: &SystemState) -> Result<Option<GrantPath>, BankersError> { // Function to find the fewest grants without the safety check that make the state unsafe, empty if it already is, an error rather than None when the search was too large so None always means no path exists
+    shortest_path_to_unsafe_within(initial, PATH_SEARCH_NODE_LIMIT)
+}
+
+pub fn shortest_path_to_unsafe_within(initial: &SystemState, node_limit: usize) -> Result<Option<GrantPath>, BankersError> { // Function to search for the path like shortest_path_to_unsafe, with an error once node_limit states are queued
+    if initial.process_count() > PATH_SEARCH_PROCESS_LIMIT {
+        return Err(BankersError::TooMany { what: "processes".to_string(), count: initial.process_count() as u128, limit: PATH_SEARCH_PROCESS_LIMIT });
+    }
+    if !safe_check(initial) {
+        return Ok(Some(Vec::new()));
+    }
+    let mut seen = HashSet::from([state_hash(initial)]); // states already queued, reached first by a path at most as long
+    let mut queue = VecDeque::from([(initial.clone(), Vec::new())]);
+    while let Some((state, path)) = queue.pop_front() { // breadth first, so the first unsafe state found is the closest
+        for process in (0..state.process_count()).filter(|&i| !state.is_finished(i)) {
+            let requests = match all_possible_requests(&state, process, PATH_SEARCH_REQUEST_LIMIT) {
+                Ok(requests) => requests,
+                Err(_) => continue, // too many to try from here
+            };
+            for request in requests.into_iter().filter(|r| r.iter().any(|&amount| amount > 0)) {
+                let mut next = state.clone();
+                for j in 0..request.len() { // granted only because it fits, like --no-avoidance (prerequisites are ignored)
+                    next.available[j] -= next.consumed(j, request[j]);
+                    next.allocated[process][j] += request[j];
+                    next.need[process][j] -= request[j];
+                }
+                if next.need[process].iter().all(|&n| n == 0) {
+                    crate::state::release_resource(&mut next, process); // a satisfied process finishes like in the simulation
+                }
+                let mut steps = path.clone();
+                steps.push((process, request));
+                if !safe_check(&next) {
+                    return Ok(Some(steps));
+                }
+                if seen.insert(state_hash(&next)) {
+                    if seen.len() > node_limit { // the state space is too large to search to the end
+                        return Err(BankersError::TooMany { what: "states".to_string(), count: seen.len() as u128, limit: node_limit });
+                    }
+                    queue.push_back((next, steps));
+                }
+            }
+        }
+    }
+    Ok(None) // every reachable state is safe
+}
+
+pub fn unfinishable_processes(state: &SystemState) -> Vec<usize> { // Function to list the processes no order of completions can serve, empty if the state is safe
+    let (_, done) = finish_everything_possible(state);
+    (0..state.process_count()).filter(|&i| !done[i]).collect()
+}
+
+fn finish_everything_possible(state: &SystemState) -> (Vec<i32>, Vec<bool>) { // Function to run the safety scan to the end, returning the final work and which processes finished
+    let mut work = state.available.clone(); // resources available as processes finish
+    let mut done: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i)).collect();
+    while let Some(i) = (0..state.process_count()).find(|&i| !done[i] && state.need[i].iter().zip(&work).all(|(n, w)| n <= w)) {
+        for j in 0..work.len() {
+            work[j] += state.held(i, j); // finishing only adds work, so the order doesn't matter
+        }
+        done[i] = true;
+    }
+    (work, done)
+}
+
+pub fn resource_slack(state: &SystemState) -> Vec<i32> { // Function to measure how far available exceeds the largest remaining need of each resource, negative means some process can't be served
+    (0..state.resource_count())
+        .map(|j| {
+            let largest = (0..state.process_count()).filter(|&i| !state.is_finished(i)).map(|i| state.need[i][j]).max().unwrap_or(0);
+            state.available[j] - largest
+        })
+        .collect()
+}
+
+pub fn min_slack(state: &SystemState) -> i32 { // Function to get the smallest slack, 0 means a resource is at the safety boundary
+    resource_slack(state).into_iter().min().unwrap_or(i32::MAX) // no resources, nothing can run short
+}
+
+pub fn can_safely_add_process(state: &SystemState, candidate_max: &[i32]) -> bool { // Function for admission control, true if a new process with this max keeps the system safe
+    let mut trial = state.clone(); // the original state is never touched
+    trial.add_process(candidate_max.to_vec(), vec![0; candidate_max.len()]).is_ok() // add_process runs the safety check
+}
+
+pub fn optimal_request(state: &SystemState, process_id: usize) -> Vec<i32> { // Function to suggest the largest safe request, all zeros if nothing can be granted
+    let mut request = vec![0; state.resource_count()];
+    if process_id >= state.process_count() || state.is_finished(process_id) {
+        return request;
+    }
+    let is_safe = |trial: &[i32]| state.is_request_safe(process_id, trial).unwrap_or(false);
+    for j in 0..request.len() { // binary search every resource on its own, the others at 0
+        let (mut low, mut high) = (0, state.need[process_id][j].min(state.available[j]).max(0)); // low is always safe
+        while low < high {
+            let middle = low + (high - low + 1) / 2;
+            let mut trial = vec![0; request.len()];
+            trial[j] = middle;
+            if is_safe(&trial) {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        request[j] = low;
+    }
+    if is_safe(&request) { // each search assumes more of one resource is never safer, this check catches the rest
+        request
+    } else {
+        largest_safe_within(state, process_id, &state.need[process_id]) // the amounts aren't safe together, fall back to the resource by resource scan
+    }
+}
+
+impl SystemState {
+    pub fn verify_sequence(&self, order: &[usize]) -> Result<(), VerifyError> { // Function to check a claimed safe sequence, see the free verify_sequence
+        verify_sequence(self, order)
+    }
+
+    pub fn minimal_augmentation(&self) -> Option<Vec<i32>> { // Function to find the smallest vector to add to available that makes the state safe, None if it already is
+        match self.augmentation(AUGMENTATION_SEARCH_LIMIT) {
+            Augmentation::AlreadySafe => None,
+            Augmentation::Minimal(extra) | Augmentation::Capped { suggestion: extra, .. } => Some(extra), // greedy min_additional_for_safety once the limit is hit
+        }
+    }
+
+    pub fn augmentation(&self, limit: usize) -> Augmentation { // Function to try the vectors in order of their sum and lexicographically within a sum, so the first safe one is minimal
+        if safe_check(self) {
+            return Augmentation::AlreadySafe;
+        }
+        let bound: Vec<i32> = (0..self.resource_count()) // adding max need - available serves every process, no entry has to go beyond that
+            .map(|j| {
+                let largest = (0..self.process_count()).filter(|&i| !self.is_finished(i)).map(|i| self.need[i][j]).max().unwrap_or(0);
+                (largest - self.available[j]).max(0)
+            })
+            .collect();
+        let mut trial = self.clone();
+        let mut candidate = vec![0; bound.len()];
+        let mut checks = 0;
+        for total in 1..=bound.iter().sum::<i32>() {
+            match search_total(self, &mut trial, &bound, &mut candidate, 0, total, &mut checks, limit) {
+                Some(true) => return Augmentation::Minimal(candidate),
+                Some(false) => {}
+                None => return Augmentation::Capped { reached: total, suggestion: min_additional_for_safety(self).unwrap_or(bound) },
+            }
+        }
+        Augmentation::Minimal(bound) // unreachable in practice, the bound itself is always safe
+    }
+
+    pub fn classify(&self) -> StateClass { // Function to tell an unsafe state, where no order is guaranteed to work, from a deadlocked one, where some process never can finish
+        if safe_check(self) {
+            return StateClass::Safe;
+        }
+        if !never_servable(self).is_empty() { // its need exceeds available plus everything the others hold, or detection found it stuck
+            return StateClass::Deadlocked;
+        }
+        StateClass::UnsafeButNotDeadlocked
+    }
+
+    pub fn max_grantable(&self, process_id: usize) -> Result<Vec<i32>, BankersError> { // Function to find the largest request that keeps the system safe, lower resource indices are maximized first
+        if process_id >= self.process_count() {
+            return Err(BankersError::InvalidProcess(process_id));
+        }
+        if self.is_finished(process_id) {
+            return Ok(vec![0; self.resource_count()]); // a finished process asks for nothing
+        }
+        Ok(largest_safe_within(self, process_id, &self.need[process_id]))
+    }
+
+    pub fn safety_margin(&self) -> Vec<i32> { // Function to find how much of each resource could be taken out of available on its own with the state staying safe
+        let mut margin = vec![0; self.resource_count()];
+        if !safe_check(self) {
+            return margin; // all zeros if already unsafe
+        }
+        let mut trial = self.clone();
+        for j in 0..margin.len() {
+            let (mut low, mut high) = (0, self.available[j].max(0)); // removing low units is always safe
+            while low < high { // more available never makes a state less safe, so binary search works
+                let middle = low + (high - low + 1) / 2;
+                trial.available[j] = self.available[j] - middle;
+                if safe_check(&trial) {
+                    low = middle;
+                } else {
+                    high = middle - 1;
+                }
+            }
+            trial.available[j] = self.available[j]; // searches are per resource
+            margin[j] = low;
+        }
+        margin
+    }
+
+    pub fn max_withdrawal(&self) -> Vec<i32> { // Function to find amounts that can all be taken out of available at once with the state staying safe, unlike the separate safety margins, lower resource indices are maximized first
+        let mut withdrawal = vec![0; self.resource_count()];
+        if !safe_check(self) {
+            return withdrawal;
+        }
+        let mut trial = self.clone();
+        for j in 0..withdrawal.len() {
+            let (mut low, mut high) = (0, self.available[j].max(0)); // the earlier resources stay withdrawn while this one is searched
+            while low < high {
+                let middle = low + (high - low + 1) / 2;
+                trial.available[j] = self.available[j] - middle;
+                if safe_check(&trial) {
+                    low = middle;
+                } else {
+                    high = middle - 1;
+                }
+            }
+            trial.available[j] = self.available[j] - low;
+            withdrawal[j] = low;
+        }
+        withdrawal
+    }
+
+    pub fn min_margin(&self) -> i32 { // smallest safety margin over the resources, 0 means some resource is at the boundary
+        self.safety_margin().into_iter().min().unwrap_or(0)
+    }
+
+    pub fn suggest_safe_request(&self, process_id: usize, desired: &[i32]) -> Option<Vec<i32>> { // Function to find a safe request to try instead of a denied one, like max_grantable capped by desired
+        if process_id >= self.process_count() || desired.len() != self.resource_count() || self.is_finished(process_id) {
+            return None;
+        }
+        let suggestion = largest_safe_within(self, process_id, desired);
+        if suggestion.iter().all(|&r| r == 0) {
+            return None; // only the empty request is safe
+        }
+        Some(suggestion)
+    }
+}
+
+pub fn explain_classification(state: &SystemState) -> String { // Function to explain the classification with the processes that can and can't still finish
+    let names = |ids: &[usize]| ids.iter().map(|i| format!("P{}", i)).collect::<Vec<_>>().join(", ");
+    let finishable = |ids: &[usize]| if ids.is_empty() { "No process can finish with what is available.".to_string() } else { format!("{} can still finish.", names(ids)) };
+    let stuck = unfinishable_processes(state);
+    let can_finish: Vec<usize> = (0..state.process_count()).filter(|&i| !state.is_finished(i) && !stuck.contains(&i)).collect();
+    match state.classify() {
+        StateClass::Safe => format!("Every process can finish: running them in the order {} lets each one take its remaining need from available.", names(&find_safe_sequence(state).unwrap_or_default())),
+        StateClass::UnsafeButNotDeadlocked => format!(
+            "{} No order of completions frees enough for {}, so the system can't guarantee that every process finishes. \
+             Nothing is stuck yet: every remaining need is at most available plus what the other processes hold, so the right releases could still let them finish.",
+            finishable(&can_finish),
+            names(&stuck),
+        ),
+        StateClass::Deadlocked => format!(
+            "{} can never make progress: {}. {}",
+            names(&never_servable(state)),
+            if has_requests(state) { "the outstanding requests wait on resources held by each other" } else { "the remaining need exceeds available plus everything the other processes hold" },
+            finishable(&can_finish),
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)] // the recursion carries its scratch state
+fn search_total(state: &SystemState, trial: &mut SystemState, bound: &[i32], candidate: &mut [i32], j: usize, remaining: i32, checks: &mut usize, limit: usize) -> Option<bool> { // Function to try the vectors with this total in lexicographic order, None once limit is hit
+    if j == candidate.len() {
+        if remaining > 0 {
+            return Some(false);
+        }
+        *checks += 1;
+        if *checks > limit {
+            return None;
+        }
+        for r in 0..candidate.len() {
+            trial.available[r] = state.available[r] + candidate[r];
+        }
+        return Some(safe_check(trial));
+    }
+    let rest: i32 = bound[j + 1..].iter().sum(); // what the later resources can still take
+    for amount in (remaining - rest).max(0)..=remaining.min(bound[j]) {
+        candidate[j] = amount;
+        if search_total(state, trial, bound, candidate, j + 1, remaining - amount, checks, limit)? {
+            return Some(true);
+        }
+    }
+    candidate[j] = 0;
+    Some(false)
+}
+
+fn has_requests(state: &SystemState) -> bool { // Function to check if any unfinished process is waiting on a request
+    (0..state.process_count()).any(|i| !state.is_finished(i) && state.outstanding_request(i).iter().any(|&r| r > 0))
+}
+
+fn never_servable(state: &SystemState) -> Vec<usize> { // Function to list the deadlocked processes, by detection when requests are known
+    if has_requests(state) {
+        return detect_deadlock(state);
+    }
+    (0..state.process_count())
+        .filter(|&i| !state.is_finished(i))
+        .filter(|&i| {
+            (0..state.resource_count()).any(|j| {
+                let reclaimable: i32 = (0..state.process_count()).filter(|&k| k != i && !state.is_finished(k)).map(|k| state.held(k, j)).sum();
+                state.need[i][j] > state.available[j] + reclaimable
+            })
+        })
+        .collect()
+}
+
+fn largest_safe_within(state: &SystemState, process_id: usize, limit: &[i32]) -> Vec<i32> { // Function to pick the largest safe request below limit, resource by resource
+    let mut request = vec![0; state.resource_count()];
+    for j in 0..request.len() {
+        let cap = limit[j].min(state.need[process_id][j]).min(state.available[j]).max(0); // never more than wanted, needed or available
+        let mut is_safe = |amount: i32| {
+            request[j] = amount;
+            state.is_request_safe(process_id, &request).unwrap_or(false)
+        };
+        let amount = match state.resource_kinds[j] {
+            ResourceKind::Exclusive => { // the units asked for less stay in available, so less of an exclusive resource is never less safe
+                let (mut low, mut high) = (0, cap);
+                while low < high {
+                    let middle = low + (high - low + 1) / 2;
+                    if is_safe(middle) {
+                        low = middle;
+                    } else {
+                        high = middle - 1;
+                    }
+                }
+                low
+            }
+            ResourceKind::Shareable => (0..=cap).rev().find(|&amount| is_safe(amount)).unwrap_or(0), // asking for less only raises the need, scan every amount
+        };
+        request[j] = amount; // 0 keeps an unsafe state as it is
+    }
+    request
+}
+
+pub const LOOKAHEAD_NODE_LIMIT: usize = 100_000; // states lookahead explores before it stops searching
+pub const LOOKAHEAD_DEPTH: usize = 3; // steps the verbose denial annotation looks ahead
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct LookaheadReport { // Struct to hold how soon each process can complete
+    pub depth: usize, // steps that were looked ahead
+    pub completes_within: Vec<Option<usize>>, // fewest steps until each process completes, Some(0) if already finished, None if not within depth
+    pub explored: usize, // states visited
+    pub truncated: bool, // the node limit stopped the search, a process marked None might still complete in time
+}
+
+impl fmt::Display for LookaheadReport { // Print one line per process
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Lookahead over {} steps ({} states explored):", self.depth, self.explored)?;
+        for (i, steps) in self.completes_within.iter().enumerate() {
+            match steps {
+                Some(0) => writeln!(f, "P{} already finished", i)?,
+                Some(1) => writeln!(f, "P{} can complete after 1 step", i)?,
+                Some(steps) => writeln!(f, "P{} can complete after {} steps", i, steps)?,
+                None => writeln!(f, "P{} can't complete within {} steps", i, self.depth)?,
+            }
+        }
+        if self.truncated {
+            writeln!(f, "The search stopped after {} states, some paths weren't explored.", self.explored)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct Shortfall { // Struct to hold one resource a blocked process can never get enough of
+    pub resource: usize, // the resource that falls short
+    pub needs: i32, // remaining need of the blocked process
+    pub free: i32, // the most that would ever be free once every process that can finish has
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct DenialExplanation { // Struct to hold why granting a request would leave the system unsafe
+    pub process: usize, // process that asked
+    pub request: Vec<i32>, // what it asked for
+    pub blocked: Vec<(usize, Vec<Shortfall>)>, // processes the scan couldn't complete after the grant, with what they fall short of
+}
+
+impl fmt::Display for DenialExplanation { // Print as "granting [1, 0, 2] to P2 would leave P4 unable to finish (needs 3 of R1, at most 2 would ever be free)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blocked: Vec<String> = self.blocked.iter()
+            .map(|(i, shortfalls)| {
+                let short: Vec<String> = shortfalls.iter().map(|s| format!("needs {} of R{}, at most {} would ever be free", s.needs, s.resource, s.free)).collect();
+                format!("P{} unable to finish ({})", i, short.join("; "))
+            })
+            .collect();
+        let listed = match blocked.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+            None => "every process able to finish".to_string(), // only for a hand built explanation, a denial always blocks someone
+        };
+        write!(f, "granting {:?} to P{} would leave {}", self.request, self.process, listed)
+    }
+}
+
+impl SystemState {
+    pub fn lookahead(&self, depth: usize) -> LookaheadReport { // Function to find the fewest steps until each process completes, a step grants one process its whole need, which it releases right away
+        self.lookahead_within(depth, LOOKAHEAD_NODE_LIMIT)
+    }
+
+    pub fn lookahead_within(&self, depth: usize, node_limit: usize) -> LookaheadReport { // Function to look ahead like lookahead, stopping after node_limit states
+        // breadth first over the sets of completed processes, so the first time a process completes is the soonest
+        let start: Vec<bool> = (0..self.process_count()).map(|i| self.is_finished(i)).collect();
+        let mut report = LookaheadReport {
+            depth,
+            completes_within: start.iter().map(|&d| d.then_some(0)).collect(),
+            explored: 1,
+            truncated: false,
+        };
+        let mut seen = HashSet::from([start.clone()]);
+        let mut frontier = vec![(start, self.available.clone())];
+        for step in 1..=depth {
+            let mut next = Vec::new();
+            for (done, work) in &frontier {
+                for i in (0..self.process_count()).filter(|&i| !done[i] && self.need[i].iter().zip(work).all(|(n, w)| n <= w)) {
+                    let mut child = done.clone();
+                    child[i] = true;
+                    let freed: Vec<i32> = (0..work.len()).map(|j| work[j] + self.held(i, j)).collect();
+                    if seen.contains(&child) {
+                        continue;
+                    }
+                    if report.explored >= node_limit {
+                        report.truncated = true;
+                        return report;
+                    }
+                    report.explored += 1;
+                    report.completes_within[i].get_or_insert(step);
+                    seen.insert(child.clone());
+                    next.push((child, freed));
+                }
+            }
+            frontier = next;
+        }
+        report
+    }
+
+    pub fn blocked_by_request(&self, process_id: usize, request: &[i32], depth: usize) -> Vec<(usize, Option<usize>)> { // Function to list the other processes that would take longer to complete if the request were granted unchecked, with their new step count (None if beyond depth)
+        let mut granted = self.clone();
+        if !request_without_avoidance(&mut granted, process_id, request) { // it doesn't even fit, nothing is blocked by it
+            return Vec::new();
+        }
+        let before = self.lookahead(depth);
+        let after = granted.lookahead(depth);
+        (0..self.process_count())
+            .filter_map(|i| match (before.completes_within[i], after.completes_within[i]) {
+                (Some(now), later) if i != process_id && now > 0 && later.is_none_or(|later| later > now) => Some((i, later)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn explain_denial(&self, process_id: usize, request: &[i32]) -> Option<DenialExplanation> { // Function to name the processes an unsafe grant would block and what each falls short of, None if the request is malformed, doesn't fit or is safe
+        if self.is_request_safe(process_id, request).ok()? {
+            return None;
+        }
+        let mut granted = self.clone();
+        if !request_without_avoidance(&mut granted, process_id, request) {
+            return None;
+        }
+        let (work, done) = finish_everything_possible(&granted); // every process left over is blocked
+        let blocked = (0..granted.process_count())
+            .filter(|&i| !done[i])
+            .map(|i| {
+                let shortfalls = (0..work.len())
+                    .filter(|&j| granted.need[i][j] > work[j])
+                    .map(|j| Shortfall { resource: j, needs: granted.need[i][j], free: work[j] })
+                    .collect();
+                (i, shortfalls)
+            })
+            .collect();
+        Some(DenialExplanation { process: process_id, request: request.to_vec(), blocked })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::state::{release_resource, request_resource};
+
+    fn random_state(rng: &mut StdRng, processes: usize, resources: usize) -> SystemState { // Function to build a small random state
+        let max: Vec<Vec<i32>> = (0..processes).map(|_| (0..resources).map(|_| rng.gen_range(0..=4)).collect()).collect();
+        let allocated = max.iter().map(|row| row.iter().map(|&m| rng.gen_range(0..=m)).collect()).collect();
+        let available = (0..resources).map(|_| rng.gen_range(0..=4)).collect();
+        SystemState::from_matrices(available, max, allocated).unwrap()
+    }
+
+    fn vectors_up_to(cap: &[i32]) -> Vec<Vec<i32>> { // Function to list every vector between zero and cap
+        cap.iter().fold(vec![Vec::new()], |vectors, &c| vectors.iter().flat_map(|v| (0..=c.max(0)).map(move |x| [v.clone(), vec![x]].concat())).collect())
+    }
+
+    #[test]
+    fn max_grant_is_the_lexicographic_maximum_of_an_exhaustive_search() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut checked = 0;
+        for _ in 0..300 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            if !safe_check(&state) {
+                continue;
+            }
+            for pid in 0..state.process_count() {
+                let cap: Vec<i32> = (0..resources).map(|j| state.need[pid][j].min(state.available[j])).collect();
+                let safe: Vec<Vec<i32>> = vectors_up_to(&cap).into_iter().filter(|r| state.is_request_safe(pid, r).unwrap()).collect();
+                let grant = state.max_grantable(pid).unwrap();
+                assert_eq!(Some(&grant), safe.iter().max(), "{:?} P{}", state, pid); // Vec compares lexicographically
+                assert!(!safe.iter().any(|r| r != &grant && r.iter().zip(&grant).all(|(a, b)| a >= b)), "a safe request dominates {:?}", grant);
+                checked += grant.iter().any(|&g| g > 0) as usize;
+            }
+        }
+        assert!(checked > 50, "too few states allow a grant");
+    }
+
+    #[test]
+    fn max_grant_with_a_shareable_resource_is_safe() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for _ in 0..300 {
+            let processes = rng.gen_range(1..=4);
+            let mut state = random_state(&mut rng, processes, 2);
+            state.resource_kinds[1] = ResourceKind::Shareable;
+            state.recount_capacity();
+            for pid in 0..state.process_count() {
+                let grant = state.max_grantable(pid).unwrap();
+                if safe_check(&state) {
+                    assert!(state.is_request_safe(pid, &grant).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_grant_of_a_finished_or_missing_process() {
+        let mut state = SystemState::from_matrices(vec![2], vec![vec![3]], vec![vec![1]]).unwrap();
+        assert_eq!(state.max_grantable(0), Ok(vec![2]));
+        release_resource(&mut state, 0);
+        assert_eq!(state.max_grantable(0), Ok(vec![0]));
+        assert_eq!(state.max_grantable(1), Err(BankersError::InvalidProcess(1)));
+    }
+
+    #[test]
+    fn suggestion_is_capped_by_the_desired_request() {
+        let state = classic();
+        assert!(!state.is_request_safe(0, &[3, 3, 0]).unwrap()); // the desired request would be denied
+        let suggestion = state.suggest_safe_request(0, &[3, 3, 0]).unwrap();
+        assert!(suggestion.iter().zip([3, 3, 0]).all(|(s, d)| *s <= d));
+        assert!(state.is_request_safe(0, &suggestion).unwrap());
+        assert_eq!(state.suggest_safe_request(0, &[0, 0, 0]), None);
+        assert_eq!(state.suggest_safe_request(0, &[1, 0]), None);
+    }
+
+    #[test]
+    fn optimal_request_is_granted_and_one_more_of_anything_is_not() {
+        let mut rng = StdRng::seed_from_u64(13);
+        for _ in 0..200 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            for pid in 0..processes {
+                let request = optimal_request(&state, pid);
+                let mut granted = state.clone();
+                assert_eq!(request_resource(&mut granted, pid, &request), safe_check(&state)); // granting never makes an unsafe state safe, so it gets zeros
+                if !safe_check(&state) {
+                    continue; // nothing is safe to grant, the zero request is all there is
+                }
+                for j in 0..resources {
+                    let mut more = request.clone();
+                    more[j] += 1;
+                    assert!(!request_resource(&mut state.clone(), pid, &more), "{:?} P{} {:?}", state, pid, more);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_need_process_is_always_admitted_and_an_impossible_max_never_is() {
+        let mut rng = StdRng::seed_from_u64(14);
+        for _ in 0..100 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            let before = state.clone();
+            assert_eq!(can_safely_add_process(&state, &vec![0; resources]), safe_check(&state)); // it can finish first and frees nothing
+            let beyond: Vec<i32> = state.total_capacity().iter().map(|&t| t + 1).collect(); // more than all units together
+            assert!(!can_safely_add_process(&state, &beyond));
+            assert_eq!(state, before);
+        }
+    }
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn slack_of_the_textbook_state() {
+        let mut state = classic();
+        assert_eq!(resource_slack(&state), vec![-4, -1, -1]); // P0 still needs 7, 4 and 3
+        assert_eq!(min_slack(&state), -4);
+        release_resource(&mut state, 0); // finished processes no longer count
+        assert_eq!(resource_slack(&state), vec![-3, 1, 0]); // R2 is at the boundary
+        assert_eq!(min_slack(&state), -3);
+        assert_eq!(min_slack(&SystemState::default()), i32::MAX);
+    }
+
+    #[test]
+    fn verify_sequence_accepts_the_textbook_order_and_names_the_failing_step() {
+        let state = classic();
+        assert_eq!(state.verify_sequence(&[1, 3, 4, 0, 2]), Ok(()));
+        assert_eq!(state.verify_sequence(&[1, 3, 9]), Err(VerifyError::Invalid(BankersError::InvalidProcess(9))));
+        assert_eq!(state.verify_sequence(&[1, 0, 3, 4, 2]), Err(VerifyError::CannotRun { step: 2, process: 0, resource: 0, short_by: 2 })); // work is [5, 3, 2] after P1
+    }
+
+    #[test]
+    fn safety_margin_matches_a_brute_force_removal() {
+        let mut rng = StdRng::seed_from_u64(13);
+        for _ in 0..300 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            let margin = state.safety_margin();
+            for j in 0..resources {
+                let mut trial = state.clone();
+                let removable = (0..=state.available[j]).take_while(|&k| { trial.available[j] = state.available[j] - k; safe_check(&trial) }).last().unwrap_or(0);
+                assert_eq!(margin[j], removable, "{:?} R{}", state, j);
+            }
+            assert_eq!(state.min_margin(), margin.iter().copied().min().unwrap_or(0));
+        }
+    }
+
+    #[test]
+    fn classify_the_fixtures() {
+        let fixture = |name: &str| crate::input::read_input(&format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        assert_eq!(fixture("classic.txt").classify(), StateClass::Safe);
+        assert_eq!(fixture("unsafe_not_deadlocked.txt").classify(), StateClass::UnsafeButNotDeadlocked); // each needs 2 of 1 available, but could get the other's 2
+        assert_eq!(fixture("unsafe.txt").classify(), StateClass::Deadlocked); // P0 needs 4 and at most 2 could ever be free
+        let mut waiting = fixture("unsafe_not_deadlocked.txt");
+        waiting.request_matrix = vec![vec![2], vec![2]];
+        assert_eq!(waiting.classify(), StateClass::Deadlocked); // with the requests known, detection decides
+    }
+
+    #[test]
+    fn augmentation_matches_a_brute_force_search() {
+        let mut rng = StdRng::seed_from_u64(14);
+        let mut unsafe_states = 0;
+        for _ in 0..300 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            let mut trial = state.clone();
+            let smallest = vectors_up_to(&vec![4; resources]).into_iter()
+                .filter(|extra| { trial.available = state.available.iter().zip(extra).map(|(a, e)| a + e).collect(); safe_check(&trial) })
+                .min_by_key(|extra| (extra.iter().sum::<i32>(), extra.clone())); // smallest total, then lexicographically first
+            match state.augmentation(usize::MAX) {
+                Augmentation::AlreadySafe => assert_eq!(smallest, Some(vec![0; resources])),
+                Augmentation::Minimal(extra) => {
+                    assert_eq!(Some(extra.clone()), smallest, "{:?}", state);
+                    assert_eq!(state.minimal_augmentation(), Some(extra));
+                    unsafe_states += 1;
+                }
+                capped => panic!("no limit was given but got {:?}", capped),
+            }
+        }
+        assert!(unsafe_states > 30, "too few unsafe states");
+    }
+
+    #[test]
+    fn max_withdrawal_is_the_largest_joint_headroom_of_an_exhaustive_search() {
+        let mut rng = StdRng::seed_from_u64(19);
+        for _ in 0..300 {
+            let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            let withdrawal = state.max_withdrawal();
+            if !safe_check(&state) {
+                assert_eq!(withdrawal, vec![0; resources]);
+                continue;
+            }
+            let mut trial = state.clone();
+            let safe: Vec<Vec<i32>> = vectors_up_to(&state.available).into_iter()
+                .filter(|taken| { trial.available = state.available.iter().zip(taken).map(|(a, t)| a - t).collect(); safe_check(&trial) })
+                .collect();
+            assert_eq!(Some(&withdrawal), safe.iter().max(), "{:?}", state); // Vec compares lexicographically
+            assert!(!safe.iter().any(|w| w != &withdrawal && w.iter().zip(&withdrawal).all(|(a, b)| a >= b)), "a larger withdrawal is safe"); // no entry can grow
+            assert!(withdrawal.iter().zip(state.safety_margin()).all(|(w, m)| *w <= m));
+        }
+    }
+
+    #[test]
+    fn all_possible_requests_lists_every_fitting_request_once() {
+        let state = classic();
+        let requests = all_possible_requests(&state, 1, 1000).unwrap();
+        assert_eq!(requests.len(), 2 * 3 * 3); // P1 needs [1, 2, 2] and all of it is available
+        assert!(requests.iter().all(|r| state.validate_request(1, r).is_ok()), "every request fits need and available");
+        let mut unique = requests.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), requests.len());
+        assert_eq!(all_possible_requests(&state, 0, 1000).unwrap().len(), 4 * 4 * 3); // need [7, 4, 3] is capped by available [3, 3, 2]
+        assert_eq!(all_possible_requests(&state, 1, 17), Err(BankersError::TooMany { what: "requests".to_string(), count: 18, limit: 17 }));
+        assert_eq!(all_possible_requests(&state, 5, 1000), Err(BankersError::InvalidProcess(5)));
+    }
+
+    #[test]
+    fn safety_boundary_of_the_textbook_state_has_u_at_the_origin_and_s_at_available() {
+        let map = visualize_safety_boundary(&classic(), 0, 1);
+        let lines: Vec<&str> = map.lines().collect();
+        assert_eq!(lines[0], "R1 (available amount)");
+        let cells = |y: usize| -> Vec<&str> { lines[1 + 3 - y].split(" | ").nth(1).unwrap().split(' ').collect() }; // rows run from R1 = 3 down to 0
+        assert_eq!(cells(0)[0], "U"); // with no R0 or R1 free nobody can finish
+        assert_eq!(cells(3)[3], "S"); // the current amounts, the state is safe
+        assert_eq!(cells(0).len(), 4);
+        assert!(lines.last().unwrap().ends_with("R0"));
+        assert!(visualize_safety_boundary(&classic(), 0, 0).starts_with("Can't map R0 against R0"));
+    }
+
+    #[test]
+    fn path_to_unsafe_of_three_processes_is_two_grants_long() {
+        let state = SystemState::from_matrices(vec![3], vec![vec![3], vec![3], vec![3]], vec![vec![0], vec![0], vec![0]]).unwrap();
+        for process in 0..3 {
+            for amount in 1..=3 { // no single grant is enough, whoever got it can still finish
+                let mut once = state.clone();
+                assert!(request_without_avoidance(&mut once, process, &[amount]) && safe_check(&once));
+            }
+        }
+        assert_eq!(shortest_path_to_unsafe(&state), Ok(Some(vec![(0, vec![1]), (1, vec![1])]))); // 1 left while three processes need 2 or 3
+        let seven = SystemState::from_matrices(vec![1], vec![vec![1]; 7], vec![vec![0]; 7]).unwrap();
+        assert_eq!(shortest_path_to_unsafe(&seven), Err(BankersError::TooMany { what: "processes".to_string(), count: 7, limit: PATH_SEARCH_PROCESS_LIMIT }));
+    }
+
+    #[test]
+    fn path_to_unsafe_takes_two_steps_and_stops_at_the_node_limit() {
+        let state = SystemState::from_matrices(vec![3], vec![vec![3], vec![3]], vec![vec![0], vec![0]]).unwrap(); // any one grant leaves someone able to finish
+        let path = shortest_path_to_unsafe(&state).unwrap().unwrap();
+        assert_eq!(path, vec![(0, vec![1]), (1, vec![1])]); // 1 left, both need 2
+        let mut unchecked = state.clone();
+        for (process, request) in &path {
+            assert!(request_without_avoidance(&mut unchecked, *process, request));
+        }
+        assert!(!safe_check(&unchecked));
+        assert_eq!(shortest_path_to_unsafe_within(&state, 1), Err(BankersError::TooMany { what: "states".to_string(), count: 2, limit: 1 }));
+        assert_eq!(shortest_path_to_unsafe(&SystemState::from_matrices(vec![0], vec![vec![2]], vec![vec![1]]).unwrap()), Ok(Some(Vec::new()))); // already unsafe
+        assert_eq!(shortest_path_to_unsafe(&SystemState::from_matrices(vec![2], vec![vec![2]], vec![vec![0]]).unwrap()), Ok(None)); // a lone process always finishes
+    }
+
+    fn soonest_completions(state: &SystemState, done: &mut Vec<bool>, work: &[i32], step: usize, depth: usize, soonest: &mut Vec<Option<usize>>) { // Function to try every order of full need grants up to depth steps
+        if step > depth {
+            return;
+        }
+        for i in 0..state.process_count() {
+            if done[i] || state.need[i].iter().zip(work).any(|(n, w)| n > w) {
+                continue;
+            }
+            soonest[i] = Some(soonest[i].map_or(step, |s| s.min(step)));
+            let freed: Vec<i32> = (0..work.len()).map(|j| work[j] + state.held(i, j)).collect();
+            done[i] = true;
+            soonest_completions(state, done, &freed, step + 1, depth, soonest);
+            done[i] = false;
+        }
+    }
+
+    #[test]
+    fn lookahead_matches_an_exhaustive_exploration() {
+        let mut rng = StdRng::seed_from_u64(21);
+        for _ in 0..200 {
+            let (processes, resources) = (rng.gen_range(1..=5), rng.gen_range(1..=3));
+            let state = random_state(&mut rng, processes, resources);
+            for depth in 0..=processes {
+                let report = state.lookahead(depth);
+                let mut soonest = vec![None; processes];
+                soonest_completions(&state, &mut vec![false; processes], &state.available, 1, depth, &mut soonest);
+                assert_eq!(report.completes_within, soonest, "{:?} depth {}", state, depth);
+                assert!(!report.truncated);
+            }
+        }
+        let truncated = classic().lookahead_within(5, 2);
+        assert!(truncated.truncated && truncated.explored == 2);
+    }
+
+    #[test]
+    fn unchecked_grant_delays_the_other_process() {
+        let state = SystemState::from_matrices(vec![2], vec![vec![2], vec![2]], vec![vec![0], vec![0]]).unwrap();
+        assert_eq!(state.blocked_by_request(1, &[1], 3), vec![(0, Some(2))]); // P0 has to wait for P1 to finish and release
+        assert_eq!(state.blocked_by_request(1, &[1], 1), vec![(0, None)]);
+        assert_eq!(state.blocked_by_request(1, &[3], 3), Vec::new()); // doesn't fit
+    }
+
+    #[test]
+    fn verify_sequence_reports_repeats_gaps_finished_processes_and_the_short_resource() {
+        let mut state = classic();
+        assert_eq!(state.verify_sequence(&[1, 1]), Err(VerifyError::Repeated { step: 2, process: 1 }));
+        assert_eq!(state.verify_sequence(&[1, 3, 4]), Err(VerifyError::Incomplete { missing: vec![0, 2] }));
+        assert_eq!(state.verify_sequence(&[1, 3, 2]), Err(VerifyError::Incomplete { missing: vec![0, 4] })); // every step runs, but P0 and P4 never do
+        let short = state.verify_sequence(&[3, 0]).unwrap_err(); // work is [5, 4, 3] after P3
+        assert_eq!(short, VerifyError::CannotRun { step: 2, process: 0, resource: 0, short_by: 2 });
+        assert_eq!(short.to_string(), "Step 2: Process 0 cannot run (R0 is short by 2 at that point)");
+        assert_eq!(VerifyError::Incomplete { missing: vec![0, 2] }.to_string(), "sequence never runs P0, P2");
+        release_resource(&mut state, 1);
+        assert_eq!(state.verify_sequence(&[1]), Err(VerifyError::Invalid(BankersError::AlreadyFinished(1))));
+        assert_eq!(state.verify_sequence(&[3, 4, 0, 2]), Ok(())); // finished processes don't have to appear
+    }
+
+    #[test]
+    fn traced_safety_check_follows_the_textbook_steps() {
+        let trace = safe_check_traced(&classic());
+        assert_eq!(trace.initial_work, vec![3, 3, 2]);
+        assert_eq!(trace.initial_finish, vec![false; 5]);
+        let steps: Vec<(usize, Vec<i32>)> = trace.steps.iter().map(|step| (step.process, step.work.clone())).collect();
+        assert_eq!(steps, vec![(1, vec![5, 3, 2]), (3, vec![7, 4, 3]), (4, vec![7, 4, 5]), (0, vec![7, 5, 5]), (2, vec![10, 5, 7])]);
+        assert_eq!(trace.steps[2].finish, vec![false, true, false, true, true]); // P0 and P2 are still waiting after P4
+        assert!(trace.safe && trace.steps[4].finish.iter().all(|&d| d));
+        assert_eq!(trace.steps.iter().map(|step| step.process).collect::<Vec<_>>(), find_safe_sequence(&classic()).unwrap()); // same order as the plain check
+    }
+
+    #[test]
+    fn sequence_table_stops_at_the_first_step_that_cannot_run() {
+        let table = sequence_table(&classic(), &[1, 0, 3]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3); // header, P1, P0
+        assert!(lines[1].contains("[3, 3, 2]") && lines[1].ends_with("[5, 3, 2]"));
+        assert!(lines[2].ends_with("can't run, R0 short by 2"));
+        assert!(sequence_table(&classic(), &[1, 1]).lines().last().unwrap().ends_with("already ran"));
+    }
+
+    #[test]
+    fn denial_explanation_names_only_the_blocked_processes() {
+        let state = SystemState::from_matrices(vec![1, 1], vec![vec![2, 0], vec![0, 1], vec![2, 1]], vec![vec![0, 0], vec![0, 0], vec![1, 0]]).unwrap();
+        let explanation = state.explain_denial(0, &[1, 0]).unwrap(); // P1 can still finish, but it frees no R0
+        let short_of_r0 = vec![Shortfall { resource: 0, needs: 1, free: 0 }];
+        assert_eq!(explanation.blocked, vec![(0, short_of_r0.clone()), (2, short_of_r0)]);
+        assert_eq!(
+            explanation.to_string(),
+            "granting [1, 0] to P0 would leave P0 unable to finish (needs 1 of R0, at most 0 would ever be free) and P2 unable to finish (needs 1 of R0, at most 0 would ever be free)"
+        );
+        assert_eq!(state.explain_denial(1, &[0, 1]), None); // safe
+        assert_eq!(state.explain_denial(0, &[2, 0]), None); // doesn't fit
+        assert_eq!(state.explain_denial(3, &[0, 0]), None); // no such process
+    }
+}
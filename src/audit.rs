@@ -0,0 +1,179 @@
+// Authors: Victor Vu
+// File: audit.rs
+// Description: Timestamped audit trail of every simulation event
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::error::BankersError;
+use crate::observer::StateObserver;
+use crate::state::SystemState;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Derive traits for printing, copy and JSON output of enum
+pub enum AuditEvent { // Enum of the events recorded in the audit trail
+    Grant(Vec<i32>), // request granted
+    Deny(Vec<i32>), // request denied
+    Release, // process released everything and completed
+    Crash, // process crashed
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Derive traits for printing, copy and JSON output of struct
+pub struct AuditEntry { // Struct to hold one event of the audit trail
+    pub ts: SystemTime, // when the event happened
+    pub process: usize, // process the event belongs to
+    pub event: AuditEvent, // what happened
+}
+
+pub struct AuditObserver { // Observer appending every event to a shared audit trail
+    pub log: Arc<Mutex<Vec<AuditEntry>>>, // the audit trail
+}
+
+impl AuditObserver {
+    fn push(&self, process: usize, event: AuditEvent) {
+        self.log.lock().unwrap().push(AuditEntry { ts: SystemTime::now(), process, event });
+    }
+}
+
+impl StateObserver for AuditObserver {
+    fn on_grant(&self, pid: usize, req: &[i32], _state: &SystemState) {
+        self.push(pid, AuditEvent::Grant(req.to_vec()));
+    }
+
+    fn on_deny(&self, pid: usize, req: &[i32]) {
+        self.push(pid, AuditEvent::Deny(req.to_vec()));
+    }
+
+    fn on_release(&self, pid: usize, _state: &SystemState) {
+        self.push(pid, AuditEvent::Release);
+    }
+
+    fn on_crash(&self, pid: usize, _state: &SystemState) {
+        self.push(pid, AuditEvent::Crash);
+    }
+}
+
+pub fn write_audit_log(log: &[AuditEntry], path: &str) -> Result<(), BankersError> { // Function to write the audit trail as JSON
+    let json = serde_json::to_string_pretty(log).map_err(|e| BankersError::Parse(e.to_string()))?;
+    std::fs::write(path, json + "\n").map_err(|e| BankersError::Io(e.to_string()))
+}
+
+pub fn audit_summary(log: &[AuditEntry]) -> String { // Function to print the audit trail as a timeline
+    let start = match log.first() {
+        Some(first) => first.ts,
+        None => return "Audit log is empty.\n".to_string(),
+    };
+    let mut summary = String::new();
+    for entry in log {
+        let offset = entry.ts.duration_since(start).unwrap_or_default().as_secs_f64(); // seconds since the first event
+        let event = match &entry.event {
+            AuditEvent::Grant(request) => format!("granted {:?}", request),
+            AuditEvent::Deny(request) => format!("denied {:?}", request),
+            AuditEvent::Release => "released".to_string(),
+            AuditEvent::Crash => "crashed".to_string(),
+        };
+        summary.push_str(&format!("+{:.3}s P{} {}\n", offset, entry.process, event));
+    }
+    summary
+}
+
+pub fn detect_livelock(log: &[AuditEntry], window: usize) -> Vec<usize> { // Function to find processes denied window times in a row while another process made progress
+    let processes = log.iter().map(|e| e.process + 1).max().unwrap_or(0);
+    let mut denials = vec![0; processes]; // denials in a row of each process
+    let mut others_progressed = vec![false; processes]; // another process was granted or released during the streak
+    let mut livelocked = vec![false; processes];
+    if window == 0 {
+        return Vec::new(); // 0 disables the check like the watchdog
+    }
+    for entry in log {
+        match entry.event {
+            AuditEvent::Deny(_) => {
+                denials[entry.process] += 1;
+                if denials[entry.process] >= window && others_progressed[entry.process] {
+                    livelocked[entry.process] = true; // no deadlock cycle, the others keep getting ahead of it
+                }
+            }
+            AuditEvent::Grant(_) | AuditEvent::Release => {
+                denials[entry.process] = 0; // the streak is broken
+                others_progressed[entry.process] = false;
+                for other in (0..processes).filter(|&p| p != entry.process) {
+                    others_progressed[other] |= denials[other] > 0; // counts only for an ongoing streak
+                }
+            }
+            AuditEvent::Crash => denials[entry.process] = 0,
+        }
+    }
+    (0..processes).filter(|&p| livelocked[p]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::simulation::Simulation;
+    use crate::state::all_complete;
+
+    fn audited_run(seed: u64) -> Vec<AuditEntry> { // Function to run the textbook state round robin with random requests and return its audit trail
+        let state = SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sim = Simulation::new(state);
+        sim.add_observer(Arc::new(AuditObserver { log: log.clone() }));
+        let mut rng = StdRng::seed_from_u64(seed);
+        while !all_complete(&sim.state) {
+            for pid in 0..sim.state.process_count() {
+                if sim.state.is_finished(pid) {
+                    continue;
+                }
+                let request: Vec<i32> = sim.state.need(pid).iter().map(|&n| rng.gen_range(0..=n)).collect();
+                if sim.request_resource(pid, &request) && sim.state.need(pid).iter().all(|&n| n == 0) {
+                    sim.release_resource(pid);
+                }
+            }
+        }
+        let entries = log.lock().unwrap().clone();
+        entries
+    }
+
+    #[test]
+    fn audit_log_of_a_seeded_run_is_in_timestamp_order() {
+        let log = audited_run(42);
+        assert_eq!(log.len(), 22 + 19 + 5); // grants, denials and releases of this seed
+        assert!(log.windows(2).all(|pair| pair[0].ts <= pair[1].ts));
+        for pid in 0..5 { // every process ends with its release
+            let last = log.iter().rev().find(|entry| entry.process == pid).unwrap();
+            assert_eq!(last.event, AuditEvent::Release);
+        }
+    }
+
+    #[test]
+    fn audit_log_survives_json() {
+        let log = audited_run(42);
+        let path = std::env::temp_dir().join(format!("bankers_{}_audit.json", std::process::id()));
+        write_audit_log(&log, path.to_str().unwrap()).unwrap();
+        let back: Vec<AuditEntry> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(back, log);
+        let summary = audit_summary(&log);
+        assert_eq!(summary.lines().count(), log.len());
+        assert!(summary.starts_with("+0.000s P"));
+    }
+
+    #[test]
+    fn livelock_needs_a_streak_of_denials_while_another_process_progresses() {
+        let entry = |process: usize, event: AuditEvent| AuditEntry { ts: SystemTime::now(), process, event };
+        let starved: Vec<AuditEntry> = (0..3).flat_map(|_| [entry(0, AuditEvent::Deny(vec![2])), entry(1, AuditEvent::Grant(vec![1]))]).collect();
+        assert_eq!(detect_livelock(&starved, 3), vec![0]);
+        assert_eq!(detect_livelock(&starved, 4), Vec::<usize>::new()); // the streak is too short
+        assert_eq!(detect_livelock(&starved, 0), Vec::<usize>::new()); // 0 disables the check
+        let stuck: Vec<AuditEntry> = (0..3).flat_map(|_| [entry(0, AuditEvent::Deny(vec![2])), entry(1, AuditEvent::Deny(vec![1]))]).collect();
+        assert_eq!(detect_livelock(&stuck, 3), Vec::<usize>::new()); // nobody progressed, that is a deadlock rather than a livelock
+        let mut broken = starved.clone();
+        broken.insert(3, entry(0, AuditEvent::Grant(vec![1]))); // P0 gets through once mid-streak
+        assert_eq!(detect_livelock(&broken, 3), Vec::<usize>::new());
+    }
+}
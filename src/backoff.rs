@@ -0,0 +1,120 @@
+// Authors: Victor Vu
+// File: backoff.rs
+// Description: Growing wait between the requests of a process that keep getting denied
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::thread;
+use std::time::Duration;
+
+pub const BACKOFF_MAX_FACTOR: u64 = 8; // without --backoff-max-ms the wait grows to this many times the base
+
+pub trait Sleeper { // Trait for whatever waits out a backoff delay, the thread in a run and a recorder in tests
+    fn sleep(&mut self, delay_ms: u64);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct ThreadSleeper; // Struct to sleep the calling thread
+
+impl Sleeper for ThreadSleeper {
+    fn sleep(&mut self, delay_ms: u64) {
+        thread::sleep(Duration::from_millis(delay_ms));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct Backoff<S: Sleeper = ThreadSleeper> { // Struct to space out the requests of a process whose requests keep getting denied
+    base_ms: u64, // wait after the first denial in a row
+    max_ms: u64, // longest wait
+    current_ms: u64, // wait after the next denial
+    count: u32, // denials in a row
+    sleeper: S, // waits out each delay
+}
+
+impl Backoff {
+    pub fn new(base_ms: u64, max_ms: u64) -> Self { // a backoff that sleeps the thread, max_ms below base_ms means no growth
+        Backoff::with_sleeper(base_ms, max_ms, ThreadSleeper)
+    }
+}
+
+impl<S: Sleeper> Backoff<S> {
+    pub fn with_sleeper(base_ms: u64, max_ms: u64, sleeper: S) -> Self { // Function to make a backoff that waits with the given sleeper
+        Backoff { base_ms, max_ms: max_ms.max(base_ms), current_ms: base_ms, count: 0, sleeper }
+    }
+
+    pub fn next_delay(&mut self) -> u64 { // Function to take the wait for this denial and double the next one, up to the max
+        let delay = self.current_ms;
+        self.current_ms = self.current_ms.saturating_mul(2).min(self.max_ms);
+        self.count += 1;
+        delay
+    }
+
+    pub fn wait(&mut self) { // Function to wait after a denial
+        let delay = self.next_delay();
+        tracing::debug!(denials = self.count, delay_ms = delay, "backing off");
+        self.sleeper.sleep(delay);
+    }
+
+    pub fn reset(&mut self) { // Function to start over after a grant
+        self.current_ms = self.base_ms;
+        self.count = 0;
+    }
+
+    pub fn denials(&self) -> u32 { // denials in a row since the last grant
+        self.count
+    }
+
+    pub fn sleeper(&self) -> &S {
+        &self.sleeper
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockSleeper { // records every delay instead of sleeping
+        delays: Vec<u64>,
+    }
+
+    impl Sleeper for MockSleeper {
+        fn sleep(&mut self, delay_ms: u64) {
+            self.delays.push(delay_ms);
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_starts_over_after_a_grant() {
+        let mut backoff = Backoff::with_sleeper(10, 50, MockSleeper::default());
+        for _ in 0..5 {
+            backoff.wait();
+        }
+        assert_eq!(backoff.sleeper().delays, vec![10, 20, 40, 50, 50]); // 80 is capped at 50
+        assert_eq!(backoff.denials(), 5);
+        backoff.reset();
+        backoff.wait();
+        assert_eq!(backoff.sleeper().delays.last(), Some(&10));
+        assert_eq!(backoff.denials(), 1);
+    }
+
+    #[test]
+    fn backoff_from_50_doubles_until_it_is_capped_at_5000() {
+        let mut backoff = Backoff::with_sleeper(50, 5000, MockSleeper::default());
+        for _ in 0..9 {
+            backoff.wait();
+        }
+        assert_eq!(backoff.sleeper().delays, vec![50, 100, 200, 400, 800, 1600, 3200, 5000, 5000]); // 6400 is capped at 5000
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), 50);
+    }
+
+    #[test]
+    fn backoff_with_a_max_below_the_base_never_grows() {
+        let mut backoff = Backoff::with_sleeper(30, 5, MockSleeper::default());
+        backoff.wait();
+        backoff.wait();
+        assert_eq!(backoff.sleeper().delays, vec![30, 30]);
+        let mut huge = Backoff::with_sleeper(u64::MAX / 2 + 1, u64::MAX, MockSleeper::default());
+        assert_eq!((huge.next_delay(), huge.next_delay()), (u64::MAX / 2 + 1, u64::MAX)); // doubling saturates instead of overflowing
+    }
+}
@@ -0,0 +1,144 @@
+// Authors: Victor Vu
+// File: cache.rs
+// Description: Memoized safety checks for states that repeat during a simulation
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use crate::state::{safe_check, SystemState};
+
+#[derive(Debug, Clone, Default)] // Derive traits for printing, copy and an empty cache
+pub struct SafeCache { // Struct to hold recent safety results, the least recently used is evicted first
+    capacity: usize, // most results kept at once
+    entries: HashMap<u64, (bool, u64)>, // state hash to its result and when it was last used
+    clock: u64, // counts lookups to order the entries by use
+    hits: usize, // lookups answered from the cache
+    misses: usize, // lookups that ran the safety check
+}
+
+impl SafeCache {
+    pub fn new(capacity: usize) -> Self {
+        SafeCache { capacity, ..Default::default() }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub(crate) fn state_hash(state: &SystemState) -> u64 { // Function to hash the fields the safety check reads
+    let mut hasher = DefaultHasher::new();
+    state.available.hash(&mut hasher);
+    state.allocated.hash(&mut hasher);
+    state.need.hash(&mut hasher);
+    state.resource_kinds.hash(&mut hasher);
+    for i in 0..state.process_count() {
+        state.is_finished(i).hash(&mut hasher); // finished processes are skipped by the check
+    }
+    hasher.finish()
+}
+
+pub fn safe_check_cached(state: &SystemState, cache: &mut SafeCache) -> bool { // Function to see if the system is in a safe state, reusing the result for a state seen before
+    if cache.capacity == 0 { // a cache without room never stores anything
+        cache.misses += 1;
+        return safe_check(state);
+    }
+    cache.clock += 1;
+    let key = state_hash(state);
+    if let Some(entry) = cache.entries.get_mut(&key) {
+        entry.1 = cache.clock;
+        cache.hits += 1;
+        return entry.0;
+    }
+    cache.misses += 1;
+    let safe = safe_check(state);
+    if cache.entries.len() >= cache.capacity { // make room by dropping the least recently used result
+        if let Some(&oldest) = cache.entries.iter().min_by_key(|(_, &(_, used))| used).map(|(key, _)| key) {
+            cache.entries.remove(&oldest);
+        }
+    }
+    cache.entries.insert(key, (safe, cache.clock));
+    safe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::state::{release_resource, request_resource, request_resource_cached};
+
+    fn random_state(rng: &mut StdRng) -> SystemState { // Function to build a small random state
+        let max: Vec<Vec<i32>> = (0..3).map(|_| (0..2).map(|_| rng.gen_range(0..=3)).collect()).collect();
+        let allocated = max.iter().map(|row| row.iter().map(|&m| rng.gen_range(0..=m)).collect()).collect();
+        SystemState::from_matrices((0..2).map(|_| rng.gen_range(0..=2)).collect(), max, allocated).unwrap()
+    }
+
+    #[test]
+    fn cache_hits_agree_with_the_safety_check() {
+        let mut rng = StdRng::seed_from_u64(15);
+        let states: Vec<SystemState> = (0..200).map(|_| random_state(&mut rng)).collect();
+        let mut cache = SafeCache::new(1000);
+        for state in &states {
+            assert_eq!(safe_check_cached(state, &mut cache), safe_check(state));
+        }
+        let misses = cache.misses();
+        for state in &states { // every state is cached now
+            assert_eq!(safe_check_cached(state, &mut cache), safe_check(state));
+        }
+        assert_eq!(cache.misses(), misses);
+        assert_eq!(cache.hits() + cache.misses(), 400);
+        assert!(cache.hits() >= 200);
+    }
+
+    #[test]
+    fn cached_requests_decide_like_uncached_ones() {
+        let mut rng = StdRng::seed_from_u64(16);
+        let mut plain = random_state(&mut rng);
+        let mut cached = plain.clone();
+        let mut cache = SafeCache::new(8); // small enough to evict
+        for _ in 0..500 {
+            let pid = rng.gen_range(0..3);
+            if rng.gen_bool(0.1) {
+                release_resource(&mut plain, pid);
+                release_resource(&mut cached, pid);
+                continue;
+            }
+            let request: Vec<i32> = plain.need(pid).iter().map(|&n| rng.gen_range(0..=n.max(0))).collect();
+            assert_eq!(request_resource_cached(&mut cached, pid, &request, &mut cache), request_resource(&mut plain, pid, &request));
+        }
+        assert_eq!(plain, cached);
+        assert!(cache.len() <= 8 && cache.hits() > 0);
+    }
+
+    #[test]
+    fn least_recently_used_result_is_evicted_and_zero_capacity_stores_nothing() {
+        let mut rng = StdRng::seed_from_u64(17);
+        let (a, b, c) = (random_state(&mut rng), random_state(&mut rng), random_state(&mut rng));
+        let mut cache = SafeCache::new(2);
+        safe_check_cached(&a, &mut cache);
+        safe_check_cached(&b, &mut cache);
+        safe_check_cached(&a, &mut cache); // b is now the least recently used
+        safe_check_cached(&c, &mut cache);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.contains_key(&state_hash(&a)) && !cache.entries.contains_key(&state_hash(&b)));
+        let mut none = SafeCache::new(0);
+        safe_check_cached(&a, &mut none);
+        safe_check_cached(&a, &mut none);
+        assert!(none.is_empty());
+        assert_eq!((none.hits(), none.misses()), (0, 2));
+    }
+}
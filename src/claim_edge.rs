@@ -0,0 +1,124 @@
+// Authors: Victor Vu
+// File: claim_edge.rs
+// Description: Claim edge (resource allocation graph) safety check for single instance resources
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use crate::state::{ResourceKind, SystemState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum SafetyAlgorithm { // Enum of the ways request_resource decides if a grant is safe
+    Matrix, // the banker's safety check over the need and allocation matrices
+    ClaimEdge, // cycle detection in the claim edge graph, single instance resources only
+    Auto, // the claim edge graph whenever every resource has a single instance, the matrices otherwise
+}
+
+impl SafetyAlgorithm {
+    pub fn uses_claim_edges(&self, state: &SystemState) -> bool { // Function to see if the claim edge graph decides for this state
+        *self != SafetyAlgorithm::Matrix && is_single_instance(state) // resources added at runtime fall back to the matrices
+    }
+}
+
+pub fn is_single_instance(state: &SystemState) -> bool { // Function to see if every resource is one exclusive unit that no process claims twice
+    state.total_capacity().iter().all(|&total| total == 1)
+        && (0..state.resource_count()).all(|j| state.resource_kind(j) == ResourceKind::Exclusive)
+        && (0..state.process_count()).filter(|&i| !state.is_finished(i)).all(|i| state.max[i].iter().all(|&m| m <= 1))
+}
+
+pub fn safe_check_claim_edges(state: &SystemState, process_id: usize, request: &[i32]) -> bool { // Function to grant a request tentatively and check the claim edge graph has no cycle
+    if process_id >= state.process_count() || request.len() != state.resource_count() {
+        return false;
+    }
+    let fits = (0..request.len()).all(|j| request[j] >= 0 && request[j] <= state.need[process_id][j] && request[j] <= state.available[j]);
+    fits && claim_graph_acyclic(state, process_id, request)
+}
+
+pub(crate) fn claim_graph_acyclic(state: &SystemState, process_id: usize, delta: &[i32]) -> bool { // Function to look for a cycle as if process_id had been granted delta
+    let processes = state.process_count();
+    let held = |i: usize, j: usize| state.allocated[i][j] + if i == process_id { delta[j] } else { 0 };
+    let need = |i: usize, j: usize| state.need[i][j] - if i == process_id { delta[j] } else { 0 };
+    let edges = |node: usize| -> Vec<usize> { // process nodes come first, resource j is node processes + j
+        if node < processes { // claim edges, a request edge is a claim that is asked for right now
+            if state.is_finished(node) {
+                return Vec::new();
+            }
+            (0..state.resource_count()).filter(|&j| need(node, j) > 0).map(|j| processes + j).collect()
+        } else { // assignment edges, the resource points at whoever holds it
+            (0..processes).filter(|&i| !state.is_finished(i) && held(i, node - processes) > 0).collect()
+        }
+    };
+    let mut color = vec![0u8; processes + state.resource_count()]; // 0 unvisited, 1 on the current path, 2 done
+    for start in 0..color.len() {
+        if color[start] != 0 {
+            continue;
+        }
+        let mut stack = vec![(start, edges(start), 0)]; // iterative depth first search
+        color[start] = 1;
+        while let Some((node, next, index)) = stack.last_mut() {
+            if *index == next.len() { // every edge explored, leave the path
+                color[*node] = 2;
+                stack.pop();
+                continue;
+            }
+            let target = next[*index];
+            *index += 1;
+            match color[target] {
+                1 => return false, // back edge, the processes on the cycle wait on each other forever
+                0 => {
+                    color[target] = 1;
+                    stack.push((target, edges(target), 0));
+                }
+                _ => {}
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::state::safe_check;
+
+    fn single_instance_state(rng: &mut StdRng) -> SystemState { // Function to build a random state where every resource has one unit
+        let (processes, resources) = (rng.gen_range(1..=4), rng.gen_range(1..=4));
+        let max: Vec<Vec<i32>> = (0..processes).map(|_| (0..resources).map(|_| rng.gen_bool(0.5) as i32).collect()).collect();
+        let mut allocated = vec![vec![0; resources]; processes];
+        let mut available = vec![1; resources];
+        for j in 0..resources {
+            let claimants: Vec<usize> = (0..processes).filter(|&i| max[i][j] == 1).collect();
+            if !claimants.is_empty() && rng.gen_bool(0.6) { // the unit is held by one of the processes that claim it
+                allocated[claimants[rng.gen_range(0..claimants.len())]][j] = 1;
+                available[j] = 0;
+            }
+        }
+        SystemState::from_matrices(available, max, allocated).unwrap()
+    }
+
+    #[test]
+    fn claim_edges_agree_with_the_matrix_check_on_single_instance_states() {
+        let mut rng = StdRng::seed_from_u64(20);
+        let (mut granted, mut refused) = (0, 0);
+        for _ in 0..500 {
+            let state = single_instance_state(&mut rng);
+            assert!(is_single_instance(&state));
+            assert_eq!(claim_graph_acyclic(&state, 0, &vec![0; state.resource_count()]), safe_check(&state), "{:?}", state);
+            let pid = rng.gen_range(0..state.process_count());
+            let request: Vec<i32> = state.need(pid).iter().map(|&n| rng.gen_range(0..=n)).collect();
+            let by_matrix = state.is_request_safe(pid, &request).unwrap_or(false);
+            assert_eq!(safe_check_claim_edges(&state, pid, &request), by_matrix, "{:?} P{} {:?}", state, pid, request);
+            if by_matrix { granted += 1 } else { refused += 1 }
+        }
+        assert!(granted > 50 && refused > 50, "{} granted, {} refused", granted, refused);
+    }
+
+    #[test]
+    fn auto_only_uses_claim_edges_for_single_instance_states() {
+        let single = SystemState::from_matrices(vec![0, 1], vec![vec![1, 1], vec![1, 0]], vec![vec![1, 0], vec![0, 0]]).unwrap();
+        let multi = SystemState::from_matrices(vec![2], vec![vec![2]], vec![vec![0]]).unwrap();
+        assert!(SafetyAlgorithm::Auto.uses_claim_edges(&single) && !SafetyAlgorithm::Auto.uses_claim_edges(&multi));
+        assert!(!SafetyAlgorithm::Matrix.uses_claim_edges(&single));
+        assert!(!SafetyAlgorithm::ClaimEdge.uses_claim_edges(&multi)); // falls back to the matrices
+    }
+}
@@ -0,0 +1,413 @@
+// Authors: Victor Vu
+// File: config.rs
+// Description: Simulation configuration from environment variables and CLI flags
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use crate::error::BankersError;
+use crate::claim_edge::SafetyAlgorithm;
+use crate::recovery::VictimRule;
+use crate::state::SequencePolicy;
+
+pub const USAGE: &str = "\
+Usage: vu_bankers_algo [OPTIONS] [INPUT_FILE]
+
+Options:
+  --input <PATH>             input file, .json files use the JSON schema (default: input.txt)
+  --infer-shape              read a text input without the resource and process count lines
+  --seed <N>                 seed the random request generator for reproducible runs
+  --sleep-ms <N>             milliseconds each process sleeps between requests (default: 250)
+  --backoff-base-ms <N>      milliseconds a process waits after its first denial in a row (default: the sleep)
+  --backoff-max-ms <N>       longest wait after denials, each denial in a row doubles it up to N (default: 8 times the base)
+  --workers <N>              number of worker threads sharing the processes, alias --threads (default: one per process)
+  --weighted                 workers pick the next process at random by its weight (from JSON, default equal)
+  --split-lock               lock available and each process row separately instead of the whole state, plain requests only
+  --no-color                 disable colored output (also off when stdout is not a terminal)
+  --commands                 read runtime commands from stdin: 'd' dumps the state, 'kill <PID>' terminates a process, 'request <PID> <AMOUNTS>' requests
+  --compact                  print the state on one line after each request
+  --trace                    print what changed after each request instead of the whole table
+  --margins                  print how much of each resource could be removed safely after each request
+  --verbose                  print extra details while simulating
+  --add-resources <SPEC>     add resources during the run, e.g. 'at=5s deltas=2 0 1' (repeatable)
+  --remove-resources <SPEC>  remove available resources during the run if it stays safe, same format
+  --spawn-every <MS>         admit a random new process every MS milliseconds while the input processes run
+  --cycles <N>               acquire and release cycles each process runs before completing (default: 1)
+  --livelock-window <N>      report processes denied N times in a row while others progressed, 0 disables (default: 20)
+  --watchdog <N>             abort after N denied requests in a row, 0 disables (default: 1000)
+  --revise-max               let each process randomly revise its max claim once mid-run
+  --partial-release          let processes release a random part of their holdings mid-run
+  --verify-safe-invariant    panic if the state is ever unsafe after a grant (doubles the safety checks)
+  --clamp                    only request what is currently available, denials are then always for safety
+  --smart-retry              retry a denied request with the largest part of it that is safe
+  --partial-grants           grant the largest safe scaled down part of a request, the rest stays needed
+  --pending-queue            denied requests wait and are granted in order once releases make them safe
+  --no-avoidance             grant any request that fits, retry denied ones and detect deadlocks
+  --recover                  with --no-avoidance, preempt resources from deadlocked processes instead of aborting
+  --victim-policy <P>        held (fewest units held), priority (lowest priority) or progress (largest need) victim first (default: held)
+  --safe-cache <N>           remember the safety result of the last N distinct states
+  --algorithm <A>            safety check: matrix, claim-edge (single instance resources) or auto (default: matrix)
+  --audit-log <PATH>         write the timestamped audit trail to PATH as JSON when the run ends
+  --trace-flame <PATH>       write the tracing spans of the run to PATH as folded stacks for a flamegraph
+  --save-on-exit <PATH>      write the final state to PATH as JSON when the run ends
+  --resume-from <PATH>       resume from a state written by --save-on-exit instead of the input file
+  --dry-run                  validate the input, print SAFE or UNSAFE with a diagnosis and exit
+  --sequence-policy <P>      safe sequence printed when verbose: index, priority, inherit or need (default: index)
+  --all-sequences [N]        print up to N safe sequences of the input (default: 10) and exit
+  --count-sequences          count the safe sequences of the input and exit
+  --verify-sequence <IDS>    check that a sequence like '0 2 1' or '0,2,1' is a safe sequence of the input, print the work vector per step and exit
+  --safety-map <A B>         print which amounts of resources A and B are safe, e.g. '0 1', and exit
+  --monte-carlo <N>          run N seeded single threaded simulations, print statistics and exit
+  --csv-output <PATH>        with --monte-carlo, write the raw per run data to PATH as CSV
+  --path-to-unsafe           print the fewest unchecked grants that make the input unsafe (up to 6 processes) and exit
+  --headroom                 print how much can be withdrawn from available while staying safe and exit
+  --lookahead <N>            print the fewest steps each process needs to complete, up to N steps, and exit
+  --safety-trace             print every step of the safety check on the initial state and exit
+  --max-grant <PID>          print the largest request process PID could be granted safely and exit
+  --help                     print this message
+
+Environment variables (overridden by the flags above):
+  BANKERS_INPUT_FILE         same as --input
+  BANKERS_SEED               same as --seed
+  BANKERS_SLEEP_MS           same as --sleep-ms
+  BANKERS_THREADS            same as --workers
+  BANKERS_VERBOSE            same as --verbose when set to 1/true/yes
+
+Exit codes:
+  0  initial state is safe and the simulation completed
+  1  invalid input file or configuration (details on stderr)
+  2  initial state is unsafe
+  3  the watchdog aborted the run (deadlock or livelock suspected)
+  4  internal error, e.g. a worker thread panicked
+  5  interrupted with Ctrl-C (partial report on stdout)
+";
+
+pub const EXIT_OK: u8 = 0; // simulation completed
+pub const EXIT_INVALID_INPUT: u8 = 1; // input file or configuration is invalid
+pub const EXIT_UNSAFE: u8 = 2; // initial state is unsafe
+pub const EXIT_WATCHDOG: u8 = 3; // watchdog aborted the run
+pub const EXIT_INTERNAL: u8 = 4; // worker thread panicked
+pub const EXIT_INTERRUPTED: u8 = 5; // stopped with Ctrl-C
+
+fn parse_policy(text: &str) -> Result<SequencePolicy, BankersError> { // Function to parse a --sequence-policy name
+    match text {
+        "index" => Ok(SequencePolicy::LowestIndexFirst),
+        "priority" => Ok(SequencePolicy::HighestPriorityFirst),
+        "need" => Ok(SequencePolicy::ShortestNeedFirst),
+        "inherit" => Ok(SequencePolicy::PriorityInheritance),
+        _ => Err(BankersError::Config(format!("--sequence-policy expects index, priority, inherit or need but got '{}'", text))),
+    }
+}
+
+fn parse_algorithm(text: &str) -> Result<SafetyAlgorithm, BankersError> { // Function to parse an --algorithm name
+    match text {
+        "matrix" => Ok(SafetyAlgorithm::Matrix),
+        "claim-edge" => Ok(SafetyAlgorithm::ClaimEdge),
+        "auto" => Ok(SafetyAlgorithm::Auto),
+        _ => Err(BankersError::Config(format!("--algorithm expects matrix, claim-edge or auto but got '{}'", text))),
+    }
+}
+
+fn parse_victim_policy(text: &str) -> Result<VictimRule, BankersError> { // Function to parse a --victim-policy name
+    match text {
+        "held" => Ok(VictimRule::FewestHeld),
+        "priority" => Ok(VictimRule::LowestPriority),
+        "progress" => Ok(VictimRule::LeastProgress),
+        _ => Err(BankersError::Config(format!("--victim-policy expects held, priority or progress but got '{}'", text))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)] // Derive traits for printing, copy and comparison of struct
+pub struct ResourceChange { // Struct to hold a scheduled addition or removal of resources
+    pub at_ms: u64, // milliseconds after the start of the simulation
+    pub deltas: Vec<i32>, // units added to or removed from each resource
+}
+
+fn parse_change(flag: &str, text: &str) -> Result<ResourceChange, BankersError> { // Function to parse "at=5s deltas=2 0 1"
+    let invalid = || BankersError::Config(format!("{} expects \"at=<N>s|<N>ms deltas=<N>...\" but got '{}'", flag, text));
+    let mut words = text.split_whitespace();
+    let at = words.next().and_then(|w| w.strip_prefix("at=")).ok_or_else(invalid)?;
+    let at_ms = if let Some(ms) = at.strip_suffix("ms") {
+        ms.parse().map_err(|_| invalid())?
+    } else if let Some(s) = at.strip_suffix('s') {
+        s.parse::<u64>().map_err(|_| invalid())? * 1000
+    } else {
+        return Err(invalid());
+    };
+    let first = words.next().and_then(|w| w.strip_prefix("deltas=")).ok_or_else(invalid)?;
+    let deltas = std::iter::once(first).chain(words).map(|w| w.parse().map_err(|_| invalid())).collect::<Result<Vec<i32>, _>>()?;
+    Ok(ResourceChange { at_ms, deltas })
+}
+
+#[derive(Debug, Clone, PartialEq)] // Derive traits for printing and copy of struct
+pub struct SimConfig { // Struct to hold the simulation settings
+    pub input_file: String, // path of the input file
+    pub infer_shape: bool, // text input has no count lines
+    pub seed: Option<u64>, // seed for the random generator, random if none
+    pub sleep_ms: u64, // sleep between requests in milliseconds
+    pub backoff_base_ms: Option<u64>, // wait after the first denial in a row, sleep_ms if none
+    pub backoff_max_ms: Option<u64>, // cap of the doubling wait after denials, BACKOFF_MAX_FACTOR times the base if none
+    pub workers: Option<usize>, // number of worker threads, one per process if none
+    pub weighted: bool, // workers pick the next process by its weight instead of in turn
+    pub split_lock: bool, // workers share a SplitState instead of locking the whole state
+    pub commands: bool, // read runtime commands from stdin
+    pub trace: bool, // print state diffs instead of tables
+    pub compact: bool, // print the state on one line instead of tables
+    pub margins: bool, // print the safety margin after each request
+    pub verbose: bool, // print extra details
+    pub no_color: bool, // never color the output
+    pub add_resources: Vec<ResourceChange>, // scheduled hot plugs of resources
+    pub remove_resources: Vec<ResourceChange>, // scheduled removals of resources
+    pub spawn_every: Option<u64>, // admit a new process this often in milliseconds
+    pub cycles: usize, // acquire and release cycles per process
+    pub watchdog: usize, // denied requests in a row before aborting, 0 disables
+    pub livelock_window: usize, // denials in a row before a process counts as livelocked, 0 disables
+    pub revise_max: bool, // processes revise their max claim once
+    pub partial_release: bool, // processes sometimes release part of their holdings
+    pub verify_safe_invariant: bool, // check the state is still safe after every grant
+    pub clamp: bool, // requests never exceed available
+    pub smart_retry: bool, // retry denied requests with a safe suggestion
+    pub partial_grants: bool, // grant the largest safe scaled down part of a request
+    pub pending_queue: bool, // denied requests wait until they are safe instead of being rolled again
+    pub no_avoidance: bool, // disable the safety check and run deadlock detection instead
+    pub recover: bool, // recover from a detected deadlock by preemption instead of aborting
+    pub victim_policy: VictimRule, // which deadlocked process recovery preempts first
+    pub algorithm: SafetyAlgorithm, // how request_resource decides if a grant is safe
+    pub safe_cache: Option<usize>, // remember this many safety results, None checks every time
+    pub trace_flame: Option<String>, // write folded span stacks for a flamegraph here
+    pub audit_log: Option<String>, // write the audit trail here
+    pub save_on_exit: Option<String>, // write the final state here
+    pub resume_from: Option<String>, // read the starting state from here instead of the input file
+    pub dry_run: bool, // only validate the input and report its safety
+    pub sequence_policy: SequencePolicy, // how the verbose safe sequence is chosen
+    pub all_sequences: Option<usize>, // print up to this many safe sequences and exit
+    pub count_sequences: bool, // print the number of safe sequences and exit
+    pub verify_sequence: Option<Vec<usize>>, // check this sequence and exit
+    pub safety_map: Option<(usize, usize)>, // print the safety map of these two resources and exit
+    pub monte_carlo: Option<usize>, // run this many simulations for statistics and exit
+    pub csv_output: Option<String>, // raw monte carlo data
+    pub path_to_unsafe: bool, // print the shortest path to an unsafe state and exit
+    pub headroom: bool, // print the withdrawable capacity and exit
+    pub lookahead: Option<usize>, // print how soon each process can complete within this many steps and exit
+    pub safety_trace: bool, // print the steps of the safety check on the initial state and exit
+    pub max_grant: Option<usize>, // print the largest safe request of this process and exit
+    pub help: bool, // print usage and exit
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            input_file: "input.txt".to_string(),
+            infer_shape: false,
+            seed: None,
+            sleep_ms: 250,
+            backoff_base_ms: None,
+            backoff_max_ms: None,
+            workers: None,
+            weighted: false,
+            split_lock: false,
+            commands: false,
+            trace: false,
+            compact: false,
+            margins: false,
+            verbose: false,
+            no_color: false,
+            add_resources: Vec::new(),
+            remove_resources: Vec::new(),
+            spawn_every: None,
+            cycles: 1,
+            watchdog: 1000,
+            livelock_window: 20,
+            revise_max: false,
+            partial_release: false,
+            verify_safe_invariant: false,
+            clamp: false,
+            smart_retry: false,
+            partial_grants: false,
+            pending_queue: false,
+            no_avoidance: false,
+            recover: false,
+            victim_policy: VictimRule::FewestHeld,
+            algorithm: SafetyAlgorithm::Matrix,
+            safe_cache: None,
+            trace_flame: None,
+            audit_log: None,
+            save_on_exit: None,
+            resume_from: None,
+            dry_run: false,
+            sequence_policy: SequencePolicy::LowestIndexFirst,
+            all_sequences: None,
+            count_sequences: false,
+            verify_sequence: None,
+            safety_map: None,
+            monte_carlo: None,
+            csv_output: None,
+            path_to_unsafe: false,
+            headroom: false,
+            lookahead: None,
+            safety_trace: false,
+            max_grant: None,
+            help: false,
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, BankersError> { // Function to parse a single setting value
+    value.trim().parse().map_err(|_| BankersError::Config(format!("invalid value '{}' for {}", value, name)))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, BankersError> { // Function to parse a boolean setting value
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(BankersError::Config(format!("invalid value '{}' for {}", value, name))),
+    }
+}
+
+impl SimConfig {
+    pub fn apply_env<F>(&mut self, get: F) -> Result<(), BankersError> // Overlay settings from environment variables
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(v) = get("BANKERS_INPUT_FILE") {
+            self.input_file = v;
+        }
+        if let Some(v) = get("BANKERS_SEED") {
+            self.seed = Some(parse_value("BANKERS_SEED", &v)?);
+        }
+        if let Some(v) = get("BANKERS_SLEEP_MS") {
+            self.sleep_ms = parse_value("BANKERS_SLEEP_MS", &v)?;
+        }
+        if let Some(v) = get("BANKERS_THREADS") {
+            self.workers = Some(parse_value("BANKERS_THREADS", &v)?);
+        }
+        if let Some(v) = get("BANKERS_VERBOSE") {
+            self.verbose = parse_bool("BANKERS_VERBOSE", &v)?;
+        }
+        Ok(())
+    }
+
+    fn split_lock_conflict(&self) -> Option<&'static str> { // Function to name a flag --split-lock can't run with, it only has plain requests and releases
+        let conflicts = [
+            (self.no_avoidance, "--no-avoidance"),
+            (self.pending_queue, "--pending-queue"),
+            (self.partial_grants, "--partial-grants"),
+            (self.smart_retry, "--smart-retry"),
+            (self.revise_max, "--revise-max"),
+            (self.partial_release, "--partial-release"),
+            (self.verify_safe_invariant, "--verify-safe-invariant"),
+            (self.cycles > 1, "--cycles"),
+            (self.safe_cache.is_some(), "--safe-cache"),
+            (self.algorithm != SafetyAlgorithm::Matrix, "--algorithm"),
+            (self.spawn_every.is_some(), "--spawn-every"),
+            (self.commands, "--commands"),
+            (!self.add_resources.is_empty(), "--add-resources"),
+            (!self.remove_resources.is_empty(), "--remove-resources"),
+            (self.trace, "--trace"),
+            (self.compact, "--compact"),
+            (self.margins, "--margins"),
+        ];
+        conflicts.into_iter().find(|&(set, _)| set).map(|(_, flag)| flag)
+    }
+
+    pub fn apply_args<I>(&mut self, args: I) -> Result<(), BankersError> // Overlay settings from command line flags
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() { // Loop through the arguments
+            if arg == "--all-sequences" { // the limit is optional
+                let limit = match args.peek() {
+                    Some(next) if !next.starts_with('-') => parse_value("--all-sequences", &args.next().unwrap())?,
+                    _ => 10,
+                };
+                self.all_sequences = Some(limit);
+                continue;
+            }
+            let mut value = |name: &str| args.next().ok_or_else(|| BankersError::Config(format!("{} needs a value", name)));
+            match arg.as_str() {
+                "--input" => self.input_file = value("--input")?,
+                "--infer-shape" => self.infer_shape = true,
+                "--seed" => self.seed = Some(parse_value("--seed", &value("--seed")?)?),
+                "--sleep-ms" => self.sleep_ms = parse_value("--sleep-ms", &value("--sleep-ms")?)?,
+                "--backoff-base-ms" => self.backoff_base_ms = Some(parse_value("--backoff-base-ms", &value("--backoff-base-ms")?)?),
+                "--backoff-max-ms" => self.backoff_max_ms = Some(parse_value("--backoff-max-ms", &value("--backoff-max-ms")?)?),
+                "--workers" | "--threads" => self.workers = Some(parse_value(&arg, &value(&arg)?)?),
+                "--weighted" => self.weighted = true,
+                "--split-lock" => self.split_lock = true,
+                "--revise-max" => self.revise_max = true,
+                "--partial-release" => self.partial_release = true,
+                "--verify-safe-invariant" => self.verify_safe_invariant = true,
+                "--clamp" => self.clamp = true,
+                "--smart-retry" => self.smart_retry = true,
+                "--partial-grants" => self.partial_grants = true,
+                "--pending-queue" => self.pending_queue = true,
+                "--no-avoidance" => self.no_avoidance = true,
+                "--recover" => self.recover = true,
+                "--victim-policy" => self.victim_policy = parse_victim_policy(&value("--victim-policy")?)?,
+                "--algorithm" => self.algorithm = parse_algorithm(&value("--algorithm")?)?,
+                "--safe-cache" => self.safe_cache = Some(parse_value("--safe-cache", &value("--safe-cache")?)?),
+                "--trace-flame" => self.trace_flame = Some(value("--trace-flame")?),
+                "--audit-log" => self.audit_log = Some(value("--audit-log")?),
+                "--save-on-exit" => self.save_on_exit = Some(value("--save-on-exit")?),
+                "--resume-from" => self.resume_from = Some(value("--resume-from")?),
+                "--no-color" => self.no_color = true,
+                "--sequence-policy" => self.sequence_policy = parse_policy(&value("--sequence-policy")?)?,
+                "--dry-run" => self.dry_run = true,
+                "--count-sequences" => self.count_sequences = true,
+                "--verify-sequence" => {
+                    let ids = value("--verify-sequence")?;
+                    let ids = ids.split(|c: char| c == ',' || c.is_whitespace()).filter(|id| !id.is_empty()); // "0 2 1" or "0,2,1"
+                    self.verify_sequence = Some(ids.map(|id| parse_value("--verify-sequence", id)).collect::<Result<_, _>>()?);
+                }
+                "--safety-map" => {
+                    let pair = value("--safety-map")?;
+                    let ids: Vec<usize> = pair.split_whitespace().map(|id| parse_value("--safety-map", id)).collect::<Result<_, _>>()?;
+                    match ids.as_slice() {
+                        [a, b] => self.safety_map = Some((*a, *b)),
+                        _ => return Err(BankersError::Config(format!("--safety-map expects two resources like '0 1' but got '{}'", pair))),
+                    }
+                }
+                "--monte-carlo" => self.monte_carlo = Some(parse_value("--monte-carlo", &value("--monte-carlo")?)?),
+                "--csv-output" => self.csv_output = Some(value("--csv-output")?),
+                "--path-to-unsafe" => self.path_to_unsafe = true,
+                "--headroom" => self.headroom = true,
+                "--lookahead" => self.lookahead = Some(parse_value("--lookahead", &value("--lookahead")?)?),
+                "--safety-trace" => self.safety_trace = true,
+                "--max-grant" => self.max_grant = Some(parse_value("--max-grant", &value("--max-grant")?)?),
+                "--commands" => self.commands = true,
+                "--trace" => self.trace = true,
+                "--compact" => self.compact = true,
+                "--margins" => self.margins = true,
+                "--verbose" | "-v" => self.verbose = true,
+                "--add-resources" => self.add_resources.push(parse_change(&arg, &value(&arg)?)?),
+                "--remove-resources" => self.remove_resources.push(parse_change(&arg, &value(&arg)?)?),
+                "--spawn-every" => self.spawn_every = Some(parse_value("--spawn-every", &value("--spawn-every")?)?),
+                "--cycles" => self.cycles = parse_value("--cycles", &value("--cycles")?)?,
+                "--livelock-window" => self.livelock_window = parse_value("--livelock-window", &value("--livelock-window")?)?,
+                "--watchdog" => self.watchdog = parse_value("--watchdog", &value("--watchdog")?)?,
+                "--help" | "-h" => self.help = true,
+                _ if arg.starts_with('-') => return Err(BankersError::Config(format!("unknown option '{}'", arg))),
+                _ => self.input_file = arg, // a bare argument is the input file
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_env_and_args<I>(args: I) -> Result<SimConfig, BankersError> // Build the config: defaults < environment < flags
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = SimConfig::default();
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.apply_args(args)?;
+        if config.workers == Some(0) { // Check at least one worker thread is requested
+            return Err(BankersError::Config("thread count must be at least 1".to_string()));
+        }
+        if config.cycles == 0 { // Check every process runs at least once
+            return Err(BankersError::Config("cycle count must be at least 1".to_string()));
+        }
+        if let Some(flag) = config.split_lock.then(|| config.split_lock_conflict()).flatten() { // Check --split-lock only meets flags it supports
+            return Err(BankersError::Config(format!("--split-lock can't be combined with {}", flag)));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,122 @@
+// Authors: Victor Vu
+// File: error.rs
+// Description: Error type for input parsing and state validation
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Serialize so errors can be reported in JSON as well
+pub enum BankersError { // Enum of everything that can go wrong building or using a state
+    Io(String), // input file could not be read
+    Parse(String), // input file is malformed
+    Config(String), // invalid environment variable or command line flag
+    DimensionMismatch { context: String, expected: usize, found: usize }, // a vector or matrix has the wrong size
+    NeedMismatch { process: usize, expected: Vec<i32>, found: Vec<i32> }, // provided need differs from max - allocated
+    NegativeAmount { resource: usize, amount: i32 }, // an amount that must be positive is negative
+    InvalidProcess(usize), // no process with this index
+    ExceedsNeed { process: usize, resource: usize, requested: i32, need: i32 }, // request is more than the process still needs
+    ExceedsAvailable { resource: usize, requested: i32, available: i32 }, // request is more than is available
+    ReleaseTooMuch { process: usize, resource: usize, tried: i32, held: i32 }, // release is more than the process holds
+    AlreadyFinished(usize), // process already completed or crashed
+    Unsafe(String), // the change would leave the system unsafe
+    AllocatedExceedsMax { process: usize, resource: usize, allocated: i32, max: i32 }, // a process holds more than its max claim
+    TooMany { what: String, count: u128, limit: usize }, // an enumeration would produce more results than allowed
+}
+
+impl fmt::Display for BankersError { // Human readable error messages
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BankersError::Io(msg) => write!(f, "Can't open input file: {}", msg),
+            BankersError::Parse(msg) => write!(f, "Invalid input: {}", msg),
+            BankersError::Config(msg) => write!(f, "Invalid configuration: {}", msg),
+            BankersError::DimensionMismatch { context, expected, found } => {
+                write!(f, "{} has length {} but expected {}", context, found, expected)
+            }
+            BankersError::NeedMismatch { process, expected, found } => {
+                write!(f, "P{} need {:?} doesn't match max - allocated {:?}", process, found, expected)
+            }
+            BankersError::NegativeAmount { resource, amount } => write!(f, "R{} amount {} can't be negative", resource, amount),
+            BankersError::InvalidProcess(process) => write!(f, "P{} doesn't exist", process),
+            BankersError::ExceedsNeed { process, resource, requested, need } => {
+                write!(f, "P{} requested {} of R{} but only needs {}", process, requested, resource, need)
+            }
+            BankersError::ExceedsAvailable { resource, requested, available } => {
+                write!(f, "requested {} of R{} but only {} available", requested, resource, available)
+            }
+            BankersError::ReleaseTooMuch { process, resource, tried, held } => {
+                write!(f, "P{} tried to release {} of R{} but only holds {}", process, tried, resource, held)
+            }
+            BankersError::AlreadyFinished(process) => write!(f, "P{} has already finished", process),
+            BankersError::Unsafe(msg) => write!(f, "Unsafe: {}", msg),
+            BankersError::AllocatedExceedsMax { process, resource, allocated, max } => {
+                write!(f, "P{} holds {} of R{} but its max is {}", process, allocated, resource, max)
+            }
+            BankersError::TooMany { what, count, limit } => write!(f, "{} {} exceed the limit of {}", count, what, limit),
+        }
+    }
+}
+
+impl std::error::Error for BankersError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Serialize so errors can be reported in JSON as well
+pub enum BatchError { // Enum of the ways a batch of requests can be refused
+    Invalid { index: usize, process: usize, error: BankersError }, // request at index failed validation, counting the earlier ones
+    Unsafe, // every request is valid but granting them together is unsafe
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BatchError::Invalid { index, process, error } => write!(f, "batch request {} (P{}) is invalid: {}", index, process, error),
+            BatchError::Unsafe => write!(f, "granting the whole batch would leave the system unsafe"),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Serialize so errors can be reported in JSON as well
+pub enum MergeError { // Enum of the ways two states can't be merged
+    ResourceCountMismatch { left: usize, right: usize }, // the states don't have the same resource types
+    KindMismatch { resource: usize }, // the resource is exclusive in one state and shareable in the other
+    Overflow { resource: usize }, // the combined total doesn't fit in an i32
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::ResourceCountMismatch { left, right } => write!(f, "can't merge a state with {} resources into one with {}", right, left),
+            MergeError::KindMismatch { resource } => write!(f, "R{} is exclusive in one state and shareable in the other", resource),
+            MergeError::Overflow { resource } => write!(f, "the combined total of R{} is too large", resource),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)] // Serialize so errors can be reported in JSON as well
+pub enum VerifyError { // Enum of the ways a proposed safe sequence can be wrong, steps count from 1
+    Invalid(BankersError), // the sequence names a process that doesn't exist, or one that already finished
+    Repeated { step: usize, process: usize }, // the process already ran earlier in the sequence
+    CannotRun { step: usize, process: usize, resource: usize, short_by: i32 }, // the process needs more of the first short resource than is available at that point
+    Incomplete { missing: Vec<usize> }, // every step works but these processes never run
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::Invalid(error) => write!(f, "{}", error),
+            VerifyError::Repeated { step, process } => write!(f, "Step {}: Process {} already ran", step, process),
+            VerifyError::CannotRun { step, process, resource, short_by } => {
+                write!(f, "Step {}: Process {} cannot run (R{} is short by {} at that point)", step, process, resource, short_by)
+            }
+            VerifyError::Incomplete { missing } => {
+                let names: Vec<String> = missing.iter().map(|i| format!("P{}", i)).collect();
+                write!(f, "sequence never runs {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
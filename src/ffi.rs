@@ -0,0 +1,95 @@
+// Authors: Victor Vu
+// File: ffi.rs
+// Description: C interface to the system state, the header is generated by build.rs with the ffi feature
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::slice;
+use crate::state::{self, SystemState};
+
+/// Creates a state with `processes` processes and `resources` resources, everything zero.
+/// Returns null if the state can't be built. Free it with `bankers_destroy`.
+#[no_mangle]
+pub extern "C" fn bankers_state_create(processes: usize, resources: usize) -> *mut SystemState {
+    match SystemState::from_matrices(vec![0; resources], vec![vec![0; resources]; processes], vec![vec![0; resources]; processes]) {
+        Ok(state) => Box::into_raw(Box::new(state)), // C owns it until bankers_destroy
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Sets the available units of every resource, false if `len` isn't the number of resources or an amount is negative.
+///
+/// # Safety
+/// `state` must come from `bankers_state_create` and `available` must point to `len` integers.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_set_available(state: *mut SystemState, available: *const i32, len: usize) -> bool {
+    let (Some(state), false) = (state.as_mut(), available.is_null()) else { return false };
+    let available = slice::from_raw_parts(available, len);
+    if len != state.resource_count() || available.iter().any(|&a| a < 0) {
+        return false;
+    }
+    state.available = available.to_vec();
+    state.recount_capacity();
+    true
+}
+
+/// Sets the max claim and allocation of a process, its need becomes max - allocated.
+/// False if `len` isn't the number of resources, or an allocation is negative or above max.
+///
+/// # Safety
+/// `state` must come from `bankers_state_create`, `max` and `allocated` must point to `len` integers.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_set_process(state: *mut SystemState, pid: usize, max: *const i32, allocated: *const i32, len: usize) -> bool {
+    let (Some(state), false, false) = (state.as_mut(), max.is_null(), allocated.is_null()) else { return false };
+    let (max, allocated) = (slice::from_raw_parts(max, len), slice::from_raw_parts(allocated, len));
+    if pid >= state.process_count() || len != state.resource_count() || max.iter().zip(allocated).any(|(&m, &a)| a < 0 || a > m) {
+        return false;
+    }
+    state.max[pid] = max.to_vec();
+    state.allocated[pid] = allocated.to_vec();
+    state.need[pid] = max.iter().zip(allocated).map(|(m, a)| m - a).collect();
+    state.recount_capacity();
+    true
+}
+
+/// Requests `len` amounts for process `pid`, true if the request was granted and the state stays safe.
+///
+/// # Safety
+/// `state` must come from `bankers_state_create` and `req` must point to `len` integers.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_request(state: *mut SystemState, pid: usize, req: *const i32, len: usize) -> bool {
+    let (Some(state), false) = (state.as_mut(), req.is_null()) else { return false };
+    state::request_resource(state, pid, slice::from_raw_parts(req, len)) // a wrong length is denied like any malformed request
+}
+
+/// Releases everything process `pid` holds back to available and marks it completed.
+///
+/// # Safety
+/// `state` must come from `bankers_state_create`.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_release(state: *mut SystemState, pid: usize) {
+    if let Some(state) = state.as_mut() {
+        if pid < state.process_count() {
+            state::release_resource(state, pid);
+        }
+    }
+}
+
+/// True if the state is safe, false for an unsafe state or null.
+///
+/// # Safety
+/// `state` must be null or come from `bankers_state_create`.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_safe(state: *const SystemState) -> bool {
+    state.as_ref().is_some_and(state::safe_check)
+}
+
+/// Frees a state, null is ignored.
+///
+/// # Safety
+/// `state` must be null or come from `bankers_state_create`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bankers_destroy(state: *mut SystemState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
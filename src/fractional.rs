@@ -0,0 +1,70 @@
+// Authors: Victor Vu
+// File: fractional.rs
+// Description: Safety check for continuous, real valued resources
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use crate::error::BankersError;
+
+pub const EPSILON: f64 = 1e-9; // tolerance when comparing real valued amounts
+
+#[derive(Debug, Clone, PartialEq)] // Derive traits for printing and copy of struct
+pub struct FloatState { // Struct to hold a system state with real valued resources
+    pub available: Vec<f64>, // available resources
+    pub max: Vec<Vec<f64>>, // max resources each process can request
+    pub allocated: Vec<Vec<f64>>, // resources currently allocated to each process
+    pub need: Vec<Vec<f64>>, // resources each process still needs
+}
+
+impl FloatState {
+    pub fn from_matrices(available: Vec<f64>, max: Vec<Vec<f64>>, allocated: Vec<Vec<f64>>) -> Result<FloatState, BankersError> { // Build a state, need is max - allocated
+        if allocated.len() != max.len() { // Check allocated has a row per process
+            return Err(BankersError::DimensionMismatch { context: "allocated".to_string(), expected: max.len(), found: allocated.len() });
+        }
+        for i in 0..max.len() { // Check every row has a column per resource
+            if max[i].len() != available.len() {
+                return Err(BankersError::DimensionMismatch { context: format!("max[{}]", i), expected: available.len(), found: max[i].len() });
+            }
+            if allocated[i].len() != available.len() {
+                return Err(BankersError::DimensionMismatch { context: format!("allocated[{}]", i), expected: available.len(), found: allocated[i].len() });
+            }
+        }
+        let need = max.iter().zip(&allocated).map(|(m, a)| m.iter().zip(a).map(|(m, a)| m - a).collect()).collect(); // calculate needed resources
+        Ok(FloatState { available, max, allocated, need })
+    }
+}
+
+pub fn safe_check_f64(state: &FloatState) -> bool { // Function to see if a real valued state is safe, need may exceed work by up to EPSILON
+    let mut work = state.available.clone(); // resources available as processes finish
+    let mut done = vec![false; state.need.len()];
+    loop {
+        let next = (0..state.need.len()) // find a process that can finish within tolerance
+            .find(|&i| !done[i] && state.need[i].iter().zip(&work).all(|(n, w)| *n <= *w + EPSILON));
+        match next {
+            Some(i) => {
+                for j in 0..work.len() {
+                    work[j] += state.allocated[i][j]; // the process finishes and releases its allocation
+                }
+                done[i] = true;
+            }
+            None => return done.iter().all(|&d| d),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding_error_is_safe_within_tolerance() {
+        let state = FloatState::from_matrices(vec![0.3, 1.5], vec![vec![0.1 + 0.2, 1.0], vec![0.5, 2.0]], vec![vec![0.0, 1.0], vec![0.2, 0.5]]).unwrap();
+        assert!(state.need[0][0] > state.available[0]); // 0.30000000000000004 > 0.3, an exact check would call this unsafe
+        assert!(safe_check_f64(&state)); // P0 finishes within EPSILON, then its unit of R1 covers P1
+    }
+
+    #[test]
+    fn a_real_shortfall_is_unsafe() {
+        let state = FloatState::from_matrices(vec![0.3], vec![vec![0.31], vec![1.0]], vec![vec![0.0], vec![0.5]]).unwrap();
+        assert!(!safe_check_f64(&state));
+    }
+}
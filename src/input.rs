@@ -0,0 +1,324 @@
+// Authors: Victor Vu
+// File: input.rs
+// Description: Parsing of the text and JSON input files
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use serde::{Deserialize, Serialize};
+use crate::error::BankersError;
+use crate::state::{ProcessState, ResourceKind, SystemState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)] // JSON schema of a system state
+pub struct StateJson {
+    pub available: Vec<i32>, // available resources
+    pub max: Vec<Vec<i32>>, // max resources of each process
+    pub allocated: Vec<Vec<i32>>, // allocated resources of each process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub need: Option<Vec<Vec<i32>>>, // optional need, validated against max - allocated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_states: Option<Vec<ProcessState>>, // optional process states, all running if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priorities: Option<Vec<u32>>, // optional priorities, all 0 if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_kinds: Option<Vec<ResourceKind>>, // optional kind of each resource, all exclusive if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_budgets: Option<Vec<Option<usize>>>, // optional request budget of each process, null runs until it finishes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<Vec<u32>>, // optional scheduling weight of each process, all 1 if missing
+}
+
+impl TryFrom<StateJson> for SystemState { // Validate the JSON schema and build the system state
+    type Error = BankersError;
+
+    fn try_from(json: StateJson) -> Result<Self, Self::Error> {
+        let mut state = SystemState::from_matrices(json.available, json.max, json.allocated)?; // check the shapes and compute need
+        let process_amount = state.process_count();
+
+        if let Some(given) = &json.need { // Check a provided need matches the computed one
+            if given.len() != process_amount {
+                return Err(BankersError::DimensionMismatch { context: "need".to_string(), expected: process_amount, found: given.len() });
+            }
+            for i in 0..process_amount {
+                if given[i] != state.need[i] {
+                    return Err(BankersError::NeedMismatch { process: i, expected: state.need[i].clone(), found: given[i].clone() });
+                }
+            }
+        }
+
+        if let Some(process_states) = json.process_states { // all processes stay running if missing
+            if process_states.len() != process_amount {
+                return Err(BankersError::DimensionMismatch { context: "process_states".to_string(), expected: process_amount, found: process_states.len() });
+            }
+            state.process_states = process_states;
+        }
+        if let Some(priorities) = json.priorities {
+            if priorities.len() != process_amount {
+                return Err(BankersError::DimensionMismatch { context: "priorities".to_string(), expected: process_amount, found: priorities.len() });
+            }
+            state.priorities = priorities;
+        }
+        if let Some(kinds) = json.resource_kinds {
+            if kinds.len() != state.resource_count() {
+                return Err(BankersError::DimensionMismatch { context: "resource_kinds".to_string(), expected: state.resource_count(), found: kinds.len() });
+            }
+            state.resource_kinds = kinds;
+        }
+        if let Some(budgets) = json.step_budgets {
+            if budgets.len() != process_amount {
+                return Err(BankersError::DimensionMismatch { context: "step_budgets".to_string(), expected: process_amount, found: budgets.len() });
+            }
+            state.step_budgets = budgets;
+        }
+        if let Some(weights) = json.weights {
+            if weights.len() != process_amount {
+                return Err(BankersError::DimensionMismatch { context: "weights".to_string(), expected: process_amount, found: weights.len() });
+            }
+            state.weights = weights;
+        }
+        state.recount_capacity(); // shareable units held by processes don't count twice
+        Ok(state)
+    }
+}
+
+impl From<SystemState> for StateJson { // Convert a system state into its JSON schema
+    fn from(state: SystemState) -> Self {
+        StateJson {
+            available: state.available, max: state.max, allocated: state.allocated,
+            need: Some(state.need),
+            process_states: Some(state.process_states),
+            priorities: if state.priorities.iter().any(|&p| p > 0) { Some(state.priorities) } else { None }, // only saved when set
+            resource_kinds: if state.resource_kinds.contains(&ResourceKind::Shareable) { Some(state.resource_kinds) } else { None },
+            step_budgets: if state.step_budgets.iter().any(|b| b.is_some()) { Some(state.step_budgets) } else { None },
+            weights: if state.weights.iter().any(|&w| w != 1) { Some(state.weights) } else { None },
+        }
+    }
+}
+
+pub fn read_json(file_path: &str) -> Result<SystemState, BankersError> { // Function to parse a JSON input file
+    let file = File::open(file_path).map_err(|e| BankersError::Io(e.to_string()))?; // open the input file
+    let json: StateJson = serde_json::from_reader(BufReader::new(file)).map_err(|e| BankersError::Parse(e.to_string()))?; // parse the schema
+    SystemState::try_from(json) // validate the schema
+}
+
+pub fn save_state(state: &SystemState, path: &str) -> Result<(), BankersError> { // Function to snapshot a state, process states included
+    let json = serde_json::to_string_pretty(state).map_err(|e| BankersError::Parse(e.to_string()))?;
+    std::fs::write(path, json + "\n").map_err(|e| BankersError::Io(e.to_string()))
+}
+
+pub fn load_state(path: &str) -> Result<SystemState, BankersError> { // Function to resume from a snapshot written by save_state
+    read_json(path)
+}
+
+pub fn read_state(file_path: &str) -> Result<SystemState, BankersError> { // Function to parse either input format based on extension
+    if file_path.ends_with(".json") {
+        read_json(file_path)
+    } else {
+        read_input(file_path)
+    }
+}
+
+fn next_line<I: Iterator<Item = std::io::Result<String>>>(lines: &mut I, what: &str) -> Result<String, BankersError> { // Function to get the next line or explain which one is missing
+    match lines.next() {
+        Some(Ok(line)) => Ok(line),
+        Some(Err(e)) => Err(BankersError::Io(e.to_string())),
+        None => Err(BankersError::Parse(format!("missing line for {}", what))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, BankersError> { // Function to parse a single number
+    text.trim().parse().map_err(|_| BankersError::Parse(format!("expected a number for {} but found '{}'", what, text.trim())))
+}
+
+fn parse_row(text: &str, what: &str) -> Result<Vec<i32>, BankersError> { // Function to parse a whitespace separated row of numbers
+    text.split_whitespace().map(|s| parse_number(s, what)).collect()
+}
+
+fn parse_available(text: &str) -> Result<(Vec<i32>, bool), BankersError> { // Function to parse the available line, true if it holds totals prefixed with "total"
+    match text.trim().strip_prefix("total") {
+        Some(totals) => Ok((parse_row(totals, "total resources")?, true)),
+        None => Ok((parse_row(text, "available resources")?, false)),
+    }
+}
+
+fn available_from_totals(total: &[i32], allocated: &[Vec<i32>]) -> Result<Vec<i32>, BankersError> { // Function to compute available = total - sum(allocated), refusing to overspend
+    (0..total.len())
+        .map(|j| {
+            let used: i32 = allocated.iter().map(|row| row.get(j).copied().unwrap_or(0)).sum();
+            if used > total[j] {
+                return Err(BankersError::Parse(format!("R{}: {} units are allocated but the total is only {}", j, used, total[j])));
+            }
+            Ok(total[j] - used)
+        })
+        .collect()
+}
+
+pub fn parse_request_line(line: &str, n: usize) -> Result<Vec<i32>, String> { // Function to parse a typed request of n amounts, the error explains what was wrong
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() != n {
+        return Err(format!("expected {} integers, got {}", n, tokens.len()));
+    }
+    tokens
+        .iter()
+        .map(|token| match token.parse::<i32>() {
+            Ok(amount) if amount < 0 => Err(format!("amounts can't be negative, got {}", amount)),
+            Ok(amount) => Ok(amount),
+            Err(_) => Err(format!("'{}' is not an integer", token)),
+        })
+        .collect()
+}
+
+pub fn read_input(file_path: &str) -> Result<SystemState, BankersError> { // Function to parse the input file
+    let file = File::open(file_path).map_err(|e| BankersError::Io(e.to_string()))?; // open the input file
+    let reader = BufReader::new(file); // create a buffer reader
+    let mut lines = reader.lines(); // create an iterator over the lines
+    let resource_amount: usize = parse_number(&next_line(&mut lines, "number of resources")?, "number of resources")?; // parse number of resources
+    let process_amount: usize = parse_number(&next_line(&mut lines, "number of processes")?, "number of processes")?; // parse number of processes
+
+    // Parse available resources, or the totals to derive them from
+    let (mut available, totals) = parse_available(&next_line(&mut lines, "available resources")?)?;
+
+    if available.len() != resource_amount { // Check if available resources match resource amount
+        return Err(BankersError::Parse("Available resources and number of resources don't match!".to_string()));
+    }
+
+    let mut max = vec![vec![0; resource_amount]; process_amount]; // create a vector of max resources
+    let mut allocated = vec![vec![0; resource_amount]; process_amount]; // create a vector of allocated resources
+
+    for i in 0..process_amount { // Loop through the processes
+        let line = next_line(&mut lines, &format!("P{}", i))?; // get the next line
+        let parts: Vec<&str> = line.split('|').map(|s| s.trim()).collect(); // split the line by '|'
+        if parts.len() != 3 { // Check if the line has 3 parts
+            return Err(BankersError::Parse(format!("P{}: line must contain 3 parts separated by |", i)));
+        }
+
+        max[i] = parse_row(parts[0], &format!("P{} max", i))?; // parse max resources
+        allocated[i] = parse_row(parts[1], &format!("P{} allocated", i))?; // parse allocated resources
+        if max[i].len() != resource_amount { // Check the rows have a value per resource
+            return Err(BankersError::DimensionMismatch { context: format!("P{} max", i), expected: resource_amount, found: max[i].len() });
+        }
+        if allocated[i].len() != resource_amount {
+            return Err(BankersError::DimensionMismatch { context: format!("P{} allocated", i), expected: resource_amount, found: allocated[i].len() });
+        }
+    }
+    if totals { // whatever isn't allocated is available
+        available = available_from_totals(&available, &allocated)?;
+    }
+
+    SystemState::from_matrices(available, max, allocated) // computes need and refuses allocated > max or negative amounts
+}
+
+pub fn read_input_inferred(file_path: &str) -> Result<SystemState, BankersError> { // Function to parse the text format without the two count lines
+    let file = File::open(file_path).map_err(|e| BankersError::Io(e.to_string()))?; // open the input file
+    let mut lines = BufReader::new(file).lines().filter(|line| !matches!(line, Ok(l) if l.trim().is_empty())); // blank lines are skipped
+    let (available, totals) = parse_available(&next_line(&mut lines, "available resources")?)?; // the number of resources is its length
+
+    let mut max = Vec::new();
+    let mut allocated = Vec::new();
+    for (i, line) in lines.enumerate() { // every remaining line is a process
+        let line = line.map_err(|e| BankersError::Io(e.to_string()))?;
+        let parts: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+        if parts.len() != 2 && parts.len() != 3 { // need is optional
+            return Err(BankersError::Parse(format!("P{}: line must contain max | allocated, optionally followed by | need", i)));
+        }
+        max.push(parse_row(parts[0], &format!("P{} max", i))?);
+        allocated.push(parse_row(parts[1], &format!("P{} allocated", i))?);
+        if parts.len() == 3 { // Check a provided need matches the computed one
+            let given = parse_row(parts[2], &format!("P{} need", i))?;
+            let expected: Vec<i32> = max[i].iter().zip(&allocated[i]).map(|(m, a)| m - a).collect();
+            if given != expected {
+                return Err(BankersError::NeedMismatch { process: i, expected, found: given });
+            }
+        }
+    }
+    let available = if totals { available_from_totals(&available, &allocated)? } else { available };
+    SystemState::from_matrices(available, max, allocated) // checks every row has a value per resource
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, contents: &str) -> String { // Function to write a test input file and return its path
+        let path = std::env::temp_dir().join(format!("bankers_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn json_round_trip_keeps_the_state() {
+        let state = classic();
+        let json = serde_json::to_string(&state).unwrap();
+        let back: SystemState = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, state);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_process_states_and_optional_fields() {
+        let mut state = classic();
+        state.process_states[1] = ProcessState::Completed;
+        state.priorities[2] = 7;
+        state.resource_kinds[0] = ResourceKind::Shareable;
+        state.recount_capacity(); // P1 and P2 hold some of R0, which no longer counts twice
+        state.step_budgets[3] = Some(4);
+        state.weights[4] = 3;
+        let path = write_temp("round_trip.json", "");
+        save_state(&state, &path).unwrap();
+        assert_eq!(load_state(&path).unwrap(), state);
+    }
+
+    #[test]
+    fn json_need_must_match_max_minus_allocated() {
+        let path = write_temp("bad_need.json", r#"{"available": [1], "max": [[3]], "allocated": [[1]], "need": [[1]]}"#);
+        assert_eq!(read_json(&path), Err(BankersError::NeedMismatch { process: 0, expected: vec![2], found: vec![1] }));
+    }
+
+    #[test]
+    fn json_rows_must_have_a_value_per_resource() {
+        let path = write_temp("short_row.json", r#"{"available": [1, 1], "max": [[3, 1], [2]], "allocated": [[1, 0], [0]]}"#);
+        assert!(matches!(read_json(&path), Err(BankersError::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn totals_line_gives_available_minus_what_is_allocated() {
+        let totals = write_temp("totals.txt", "3\n5\ntotal 10 5 7\n7 5 3 | 0 1 0 | 7 4 3\n3 2 2 | 2 0 0 | 1 2 2\n9 0 2 | 3 0 2 | 6 0 0\n2 2 2 | 2 1 1 | 0 1 1\n4 3 3 | 0 0 2 | 4 3 1\n");
+        let state = read_input(&totals).unwrap();
+        assert_eq!(state.available(), classic().available()); // 10 5 7 minus the 7 2 5 held
+        assert_eq!(state.allocated, classic().allocated);
+        let inferred = write_temp("totals_inferred.txt", "total 10 5 7\n7 5 3 | 0 1 0 | 7 4 3\n3 2 2 | 2 0 0 | 1 2 2\n9 0 2 | 3 0 2 | 6 0 0\n2 2 2 | 2 1 1 | 0 1 1\n4 3 3 | 0 0 2 | 4 3 1\n");
+        assert_eq!(read_input_inferred(&inferred).unwrap().available(), &[3, 3, 2]);
+    }
+
+    #[test]
+    fn totals_below_what_is_allocated_are_refused() {
+        let overspent = write_temp("totals_overspent.txt", "3\n5\ntotal 6 5 7\n7 5 3 | 0 1 0 | 7 4 3\n3 2 2 | 2 0 0 | 1 2 2\n9 0 2 | 3 0 2 | 6 0 0\n2 2 2 | 2 1 1 | 0 1 1\n4 3 3 | 0 0 2 | 4 3 1\n");
+        assert_eq!(read_input(&overspent), Err(BankersError::Parse("R0: 7 units are allocated but the total is only 6".to_string())));
+    }
+
+    #[test]
+    fn short_and_long_rows_name_the_process_and_length() {
+        let short = write_temp("short_row.txt", "2\n2\n1 1\n2 2 | 1 0 | 1 2\n2 | 0 | 2\n");
+        assert_eq!(read_input(&short), Err(BankersError::DimensionMismatch { context: "P1 max".to_string(), expected: 2, found: 1 }));
+        let long = write_temp("long_row.txt", "2\n2\n1 1\n2 2 | 1 0 1 | 1 2\n2 2 | 0 0 | 2 2\n");
+        assert_eq!(read_input(&long), Err(BankersError::DimensionMismatch { context: "P0 allocated".to_string(), expected: 2, found: 3 }));
+    }
+
+    #[test]
+    fn request_lines_need_exactly_n_non_negative_integers() {
+        assert_eq!(parse_request_line(" 1 0  2 ", 3), Ok(vec![1, 0, 2]));
+        assert_eq!(parse_request_line("1 0", 3), Err("expected 3 integers, got 2".to_string()));
+        assert_eq!(parse_request_line("1 0 2 4", 3), Err("expected 3 integers, got 4".to_string()));
+        assert_eq!(parse_request_line("1 x 2", 3), Err("'x' is not an integer".to_string()));
+        assert_eq!(parse_request_line("1 2.5 2", 3), Err("'2.5' is not an integer".to_string()));
+        assert_eq!(parse_request_line("1 -1 2", 3), Err("amounts can't be negative, got -1".to_string()));
+    }
+}
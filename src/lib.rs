@@ -0,0 +1,52 @@
+// Authors: Victor Vu
+// File: lib.rs
+// Description: Library root for the Banker's Algorithm simulation
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#![allow(clippy::needless_range_loop)] // index loops mirror the textbook matrix notation
+
+pub mod analysis; // capacity planning analysis
+pub mod audit; // timestamped audit trail
+pub mod backoff; // growing waits after denied requests
+pub mod cache; // memoized safety checks
+pub mod claim_edge; // claim edge graph safety check for single instance resources
+pub mod config; // simulation settings from environment and flags
+pub mod error; // error type shared by the whole crate
+#[cfg(feature = "ffi")]
+pub mod ffi; // C interface
+pub mod fractional; // safety check for real valued resources
+pub mod input; // input file parsing (text and JSON)
+pub mod monte_carlo; // statistics over many seeded runs
+pub mod observer; // observers notified about simulation events
+pub mod pending; // denied requests waiting for resources
+#[cfg(feature = "python")]
+pub mod python; // Python bindings
+pub mod recovery; // deadlock recovery by preemption
+pub mod sequences; // enumeration of safe sequences
+pub mod simulation; // state wrapper driving the observers
+pub mod split_state; // state with a lock per process for many threads
+pub mod state; // system state and the core algorithm
+pub mod stats; // statistics collected during a run
+#[cfg(feature = "wasm")]
+pub mod wasm; // JavaScript bindings
+
+pub use analysis::{all_possible_requests, can_safely_add_process, explain_classification, min_additional_for_safety, min_slack, optimal_request, resource_slack, safe_check_traced, sequence_table, shortest_path_to_unsafe, shortest_path_to_unsafe_within, unfinishable_processes, verify_sequence, visualize_safety_boundary, AUGMENTATION_SEARCH_LIMIT, Augmentation, DenialExplanation, GrantPath, LOOKAHEAD_DEPTH, LOOKAHEAD_NODE_LIMIT, LookaheadReport, PATH_SEARCH_NODE_LIMIT, PATH_SEARCH_PROCESS_LIMIT, SafetyStep, SafetyTrace, Shortfall, StateClass};
+pub use audit::{audit_summary, detect_livelock, write_audit_log, AuditEntry, AuditEvent, AuditObserver};
+pub use backoff::{Backoff, Sleeper, ThreadSleeper, BACKOFF_MAX_FACTOR};
+pub use cache::{safe_check_cached, SafeCache};
+pub use claim_edge::{is_single_instance, safe_check_claim_edges, SafetyAlgorithm};
+pub use config::SimConfig;
+pub use bankers_core as const_state; // fixed size state from the no_std bankers_core crate
+pub use bankers_core::{release_resource_const, request_resource_const, safe_check_const, ConstSystemState};
+pub use error::{BankersError, BatchError, MergeError, VerifyError};
+pub use fractional::{safe_check_f64, FloatState};
+pub use input::{load_state, parse_request_line, read_input, read_input_inferred, read_json, read_state, save_state};
+pub use monte_carlo::{run_monte_carlo, safety_probability, MonteCarloResults, RunResult, SAFETY_SAMPLES};
+pub use observer::{CountingObserver, PrintingObserver, StateObserver};
+pub use pending::PendingQueue;
+pub use recovery::{recover_by_preemption, Preemption, RecoveryReport, VictimPolicy, VictimRule};
+pub use sequences::SafeSequences;
+pub use simulation::{simulate_tick, weighted_choice, Simulation, TickEvent};
+pub use split_state::{ProcessRow, SplitState};
+pub use stats::{DurationStats, LockTimes, SimStats};
+pub use state::{all_complete, build_priority_inversion_scenario, clear_prerequisites, detect_deadlock, diff, find_safe_sequence, format_sequence, inject_resources, is_consistent, merge, merge_states, processes, processes_by_need, reactivate, release_partial, release_resource, replay_log, request_batch, request_log_for, request_resource, request_resource_cached, request_resource_claim_edges, request_resource_partial, request_single, request_without_avoidance, safe_check, set_prerequisite, shrink, simulate_crash, ProcessState, ProcessView, ResourceKind, SequencePolicy, SystemState};
@@ -1,204 +1,869 @@
-// Authors: Victor Vu 
+// Authors: Victor Vu
 // File: main.rs
 // Description: Main file for the Banker's Algorithm simulation
-// Copyright (C) 2024 Victor V. Vu 
+// Copyright (C) 2024 Victor V. Vu
 // License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::sync::{Arc, Mutex};
+use std::io::{BufRead, BufWriter, IsTerminal};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
 use std::thread;
-use rand::Rng;
-
-#[derive(Debug, Clone)] // Derive traits for printing and copy of struct
-struct SystemState { // Struct to hold the state of the system (structs must use camel case)
-    available: Vec<i32>, // available resources
-    max: Vec<Vec<i32>>, // max resources each process can request
-    allocated: Vec<Vec<i32>>, // resources currently allocated to each process
-    need: Vec<Vec<i32>>, // resources each process still needs
-    completed_process: Vec<bool>, // mark if a process has completed
-}
-
-fn safe_check(state: &SystemState) -> bool { // Function to see if the system is in a safe state
-    let mut cloned_resource = state.available.clone(); // clone the available resources
-    let mut done_process = vec![false; state.max.len()]; // vector of bool to track of completed processes
-
-    for _ in 0..state.max.len() { // Loop through the processes
-        let mut found = false; // start set found to false
-        for i in 0..state.max.len() { 
-            if !done_process[i] && state.need[i].iter().zip(&cloned_resource).all(|(n, w)| n <= w) { // check if process is not done & need is less than resources
-                for j in 0..cloned_resource.len() { 
-                    cloned_resource[j] += state.allocated[i][j]; // add allocated resources to cloned resources
-                }
-                done_process[i] = true; // mark process as done
-                found = true;
-            }
+use owo_colors::{OwoColorize, Stream, Style};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing_flame::{FlameLayer, FlushGuard};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use vu_bankers_algo::config::{EXIT_INTERNAL, EXIT_INTERRUPTED, EXIT_INVALID_INPUT, EXIT_OK, EXIT_UNSAFE, EXIT_WATCHDOG, USAGE};
+use vu_bankers_algo::{all_complete, audit_summary, detect_deadlock, detect_livelock, diff, explain_classification, find_safe_sequence, format_sequence, is_consistent, is_single_instance, load_state, min_slack, parse_request_line, reactivate, read_input_inferred, read_state, recover_by_preemption, resource_slack, run_monte_carlo, safe_check, safe_check_traced, safety_probability, save_state, sequence_table, shortest_path_to_unsafe, unfinishable_processes, verify_sequence, visualize_safety_boundary, weighted_choice, write_audit_log, AUGMENTATION_SEARCH_LIMIT, AuditObserver, Augmentation, Backoff, BACKOFF_MAX_FACTOR, LOOKAHEAD_DEPTH, LockTimes, PendingQueue, PrintingObserver, SAFETY_SAMPLES, SafeCache, SafetyAlgorithm, SimConfig, SimStats, Simulation, SplitState, SystemState, VerifyError};
+
+struct Watchdog { // Struct to abort the run when no request has been granted for too long
+    limit: usize, // denied requests in a row before aborting, 0 disables
+    denied_in_a_row: AtomicUsize, // denied requests since the last grant
+    tripped: AtomicBool, // set once the limit is reached
+}
+
+impl Watchdog {
+    fn record(&self, granted: bool) { // Function to count a request outcome
+        if granted {
+            self.denied_in_a_row.store(0, Ordering::SeqCst);
+        } else if self.denied_in_a_row.fetch_add(1, Ordering::SeqCst) + 1 >= self.limit && self.limit > 0 {
+            self.tripped.store(true, Ordering::SeqCst);
         }
-        if !found { // if no process is found, break the loop
-            break;
+    }
+
+    fn abort(&self) { // Function to stop the run right away
+        self.tripped.store(true, Ordering::SeqCst);
+    }
+
+    fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+}
+
+struct StateDump { // Struct to dump the state on request without stopping the simulation
+    requested: Arc<AtomicBool>, // set by the signal handler (or the 'd' key)
+    count: AtomicUsize, // number of dumps written so far
+}
+
+impl StateDump {
+    fn take(&self, state: &SystemState, stats: &SimStats) { // Function to write a dump if one was requested, call while holding the lock
+        if !self.requested.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let n = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let completed: Vec<String> = state.completed_processes().map(|i| format!("P{}", i)).collect();
+        let dump = format!("{}\n{}Completed: {}\n", state, stats, if completed.is_empty() { "none".to_string() } else { completed.join(", ") });
+        let path = format!("state_dump_{}.txt", n);
+        match std::fs::write(&path, &dump) {
+            Ok(()) => println!("State dumped to {}", path),
+            Err(e) => eprintln!("Can't write {}: {}", path, e),
         }
     }
-    done_process.iter().all(|&f| f) // return true if all processes are done
 }
 
-fn request_resource(state: &mut SystemState, process_id: usize, request: &[i32]) -> bool { // Function to request resources
-    if request.iter().zip(&state.need[process_id]).any(|(r, n)| r > n) // check if request is greater than need
-        || request.iter().zip(&state.available).any(|(r, a)| r > a) // check if request is greater than available
-    {
-        return false; // false if request exceeds need/available resources
+#[cfg(not(target_arch = "wasm32"))]
+fn install_stop_trigger(flag: Arc<AtomicBool>) { // Function to stop the run on Ctrl-C, it finishes with a report instead of dying
+    if let Err(e) = ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst)) {
+        eprintln!("Can't install Ctrl-C handler: {}", e);
     }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn install_stop_trigger(_flag: Arc<AtomicBool>) {} // no Ctrl-C on wasm32, the binary only has to build there for wasm-pack test
 
-    for i in 0..request.len() { // loop through the request
-        state.available[i] -= request[i]; // subtract request from available resources
-        state.allocated[process_id][i] += request[i]; // add request to allocated resources
-        state.need[process_id][i] -= request[i]; // subtract request from needed resources
+#[cfg(unix)]
+fn install_dump_trigger(flag: Arc<AtomicBool>) { // Function to request a dump on SIGUSR1
+    if let Err(e) = signal_hook::flag::register(signal_hook::consts::SIGUSR1, flag) {
+        eprintln!("Can't install SIGUSR1 handler: {}", e);
     }
+}
+
+#[cfg(not(unix))]
+fn install_dump_trigger(_flag: Arc<AtomicBool>) {} // no signals here, the command reader handles 'd' instead
 
-    if safe_check(state) { // check if the system is in a safe state
-        true 
-    } else { // if not in a safe state
-        for i in 0..request.len() { 
-            state.available[i] += request[i]; // add request back to available resources
-            state.allocated[process_id][i] -= request[i]; // subtract request from allocated resources
-            state.need[process_id][i] += request[i]; // add request back to needed resources
+struct Shared { // Struct to hold everything the worker threads share
+    sim: Mutex<Simulation>, // the system state and its observers
+    stats: Mutex<SimStats>, // statistics of the run, always locked after the state
+    config: SimConfig, // simulation settings
+    watchdog: Watchdog, // aborts a run that stops making progress
+    dump: StateDump, // dumps the state on request
+    queue: Mutex<VecDeque<(usize, StdRng)>>, // processes waiting for a worker, each with its own random generator
+    spawning: AtomicBool, // new processes may still be admitted
+    stop: Arc<AtomicBool>, // set by Ctrl-C, every thread exits at its next iteration
+    revised: Mutex<HashSet<usize>>, // processes that already revised their max claim, locked after the statistics
+    parked: Mutex<HashMap<usize, StdRng>>, // processes waiting in the pending queue, locked after the system state and before the work queue
+    backoffs: Mutex<HashMap<usize, Backoff>>, // backoff of each process that was denied, never locked together with another lock
+    split: Option<SplitState>, // with --split-lock the workers request through this and sim only gets the final state
+    scheduler: Mutex<StdRng>, // picks the next process with --weighted, locked before the work queue
+    lock_times: Mutex<LockTimes>, // how long the workers held and waited for the system state, locked last
+}
+
+struct TimedGuard<'a> { // Struct to hold the system state lock and record how long it was held when dropped
+    guard: MutexGuard<'a, Simulation>, // the locked simulation
+    acquired: Instant, // when the lock was acquired
+    times: &'a Mutex<LockTimes>, // where the hold time goes
+}
+
+impl Deref for TimedGuard<'_> {
+    type Target = Simulation;
+
+    fn deref(&self) -> &Simulation {
+        &self.guard
+    }
+}
+
+impl DerefMut for TimedGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Simulation {
+        &mut self.guard
+    }
+}
+
+impl Drop for TimedGuard<'_> {
+    fn drop(&mut self) { // recorded just before the state is unlocked
+        if let Ok(mut times) = self.times.lock() { // a poisoned lock only loses the sample, no panic while unwinding
+            times.held.record(self.acquired.elapsed());
         }
-        false 
     }
 }
 
-fn release_resource(state: &mut SystemState, process_id: usize) { // Function to release resources
-    for i in 0..state.available.len() { 
-        state.available[i] += state.allocated[process_id][i]; // add allocated resources back to available resources
-        state.allocated[process_id][i] = 0; // set allocated resources to 0
-        state.need[process_id][i] = state.max[process_id][i]; // set needed resources to max resources
+fn lock_sim(shared: &Shared) -> TimedGuard<'_> { // Function for the workers to lock the system state, timing the wait and the hold
+    let asked = Instant::now();
+    let guard = shared.sim.lock().unwrap();
+    let acquired = Instant::now();
+    shared.lock_times.lock().unwrap().waited.record(acquired - asked);
+    TimedGuard { guard, acquired, times: &shared.lock_times }
+}
+
+fn process_rng(seed: Option<u64>, process_id: usize) -> StdRng { // Function to create the random number generator of a process
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(process_id as u64)), // seeded runs are reproducible per process
+        None => StdRng::from_entropy(),
     }
-    state.completed_process[process_id] = true; // mark process as completed
 }
 
-fn process_thread(system_state: Arc<Mutex<SystemState>>, process_id: usize) { // Function to simulate process resource requests
-    let mut random = rand::thread_rng(); // create a random number generator
+const PARTIAL_RELEASE_CHANCE: f64 = 0.25; // chance a granted process releases part of its holdings with --partial-release
+const REVISE_MAX_CHANCE: f64 = 0.2; // chance a granted process revises its max claim with --revise-max
 
-    while !system_state.lock().unwrap().completed_process[process_id] { 
-        let request: Vec<i32> = { // create a vector of random requests
-            let state = system_state.lock().unwrap(); // lock the system state
-            state.need[process_id] // get the needed resources for the process
-                .iter() // iterate through the needed resources
-                .map(|&n| random.gen_range(0..=n)) // generate random number between 0 and needed resource
-                .collect() // collect the random numbers into a vector
-        };
+fn process_step(shared: &Shared, process_id: usize, random: &mut StdRng) -> bool { // Function to simulate one resource request of a process, true if granted
+    let mut sim = { // lock the system state, the request is built from the same snapshot it is checked against
+        let _waiting = tracing::trace_span!("wait_state").entered(); // time spent blocked on the lock shows up in flamegraphs
+        lock_sim(shared)
+    };
+    let outstanding = sim.state.outstanding_request(process_id);
+    let request: Vec<i32> = if !sim.avoidance && outstanding.iter().any(|&r| r > 0) { // without avoidance a denied request is retried until granted
+        outstanding.to_vec()
+    } else {
+        sim.state.need(process_id) // get the needed resources for the process
+            .iter() // iterate through the needed resources
+            .zip(sim.state.available())
+            .map(|(&n, &a)| if shared.config.clamp { n.min(a) } else { n }) // clamp mode never asks for more than is available
+            .map(|n| random.gen_range(0..=n)) // generate random number between 0 and needed resource
+            .collect() // collect the random numbers into a vector
+    };
+    let before = shared.config.trace.then(|| sim.state.clone()); // snapshot to print only the changes
+
+    let mut granted = if shared.config.partial_grants {
+        match sim.request_resource_partial(process_id, &request) {
+            Some(part) if part != request => { // the rest stays in need for a later request
+                println!("Process {}: asked for {:?}, partially granted {:?}\n", process_id, request, part);
+                true
+            }
+            part => part.is_some(),
+        }
+    } else {
+        sim.request_resource(process_id, &request) // observers print the outcome
+    };
+    let mut stats = shared.stats.lock().unwrap(); // lock the statistics
+    if !granted && sim.avoidance && sim.state.is_request_safe(process_id, &request) == Ok(false) { // it fit, only the safety check refused it
+        stats.safety_denied += 1;
+    }
+    if !granted && sim.avoidance && shared.config.smart_retry { // ask for what can be granted safely instead of rolling again
+        if let Some(suggestion) = sim.state.suggest_safe_request(process_id, &request) {
+            granted = sim.request_resource(process_id, &suggestion);
+            if granted {
+                stats.converted += 1;
+            }
+        }
+    }
+    stats.record(granted); // once per request, a converted denial counts as granted
+    stats.record_request_size(&request);
+    if !granted && shared.config.verbose { // many denials with nothing runnable points at a livelock
+        println!("Process {}: {} of the unfinished processes can run to completion right now\n", process_id, sim.state.immediately_runnable().len());
+        if sim.avoidance { // show what the denied grant would have cost the others
+            if let Some(explanation) = sim.state.explain_denial(process_id, &request) {
+                println!("Process {}: {}", process_id, explanation);
+            }
+            for (blocked, steps) in sim.state.blocked_by_request(process_id, &request, LOOKAHEAD_DEPTH) {
+                match steps {
+                    Some(steps) => println!("Process {}: granting {:?} would block P{} for at least {} steps", process_id, request, blocked, steps),
+                    None => println!("Process {}: granting {:?} would block P{} for more than {} steps", process_id, request, blocked, LOOKAHEAD_DEPTH),
+                }
+            }
+        }
+    }
+    if granted && shared.config.revise_max && random.gen_bool(REVISE_MAX_CHANCE) && shared.revised.lock().unwrap().insert(process_id) { // revise the claim once per process
+        let capacity = sim.state.total_capacity();
+        let new_max: Vec<i32> = sim.state.allocated(process_id).iter().zip(&capacity).map(|(&a, &c)| random.gen_range(a..=c)).collect(); // anything from what it holds to everything there is
+        let old_max = sim.state.max(process_id).to_vec();
+        match sim.state.update_max(process_id, &new_max) {
+            Ok(()) => println!("Process {}: revised its max {:?} ==> {:?}\n", process_id, old_max, new_max),
+            Err(e) => println!("Process {}: revision to {:?} refused: {}\n", process_id, new_max, e),
+        }
+    }
+    if granted && sim.avoidance && shared.config.verify_safe_invariant && !safe_check(&sim.state) { // runtime check of the algorithm itself
+        panic!("Safety invariant violated after granting Process {}:\n{}", process_id, sim.state);
+    }
+    if granted && sim.state.need(process_id).iter().all(|&n| n == 0) { // check if all needed resources are 0
+        if stats.record_cycle(process_id) < shared.config.cycles {
+            sim.release_and_reset(process_id); // release resources and start the next cycle
+        } else {
+            sim.release_resource(process_id); // release resources
+            stats.record_completion(process_id);
+        }
+    } else if granted && shared.config.partial_release && random.gen_bool(PARTIAL_RELEASE_CHANCE) { // model a process finishing a phase of its work
+        let amounts: Vec<i32> = sim.state.allocated(process_id).iter().map(|&a| random.gen_range(0..=a)).collect(); // give back a random part of each holding
+        if amounts.iter().any(|&a| a > 0) {
+            sim.release_partial(process_id, &amounts).expect("amounts never exceed the allocation");
+        }
+    }
 
-        let mut state = system_state.lock().unwrap(); // lock the system state
+    match &before {
+        Some(before) => { // trace mode prints what changed instead of the whole table
+            let changes = diff(before, &sim.state);
+            println!("Changes: {}", if changes.is_empty() { "none".to_string() } else { changes.join(", ") });
+            println!();
+        }
+        None if shared.config.compact => println!("{}", sim.state.compact()),
+        None => print!("{}", sim.state), // print the current state
+    }
+    if shared.config.margins {
+        let margin = sim.state.safety_margin();
+        println!("Safety margin: {:?}", margin);
+        println!();
+        stats.record_margin(margin.into_iter().min().unwrap_or(0));
+    }
 
-        if request_resource(&mut state, process_id, &request) { // check if the request can be granted
-            println!("Process {}: Requesting {:?} ... Process {}: Request granted", process_id, request, process_id); 
-            println!(); // add a newline
+    if !granted && !sim.avoidance { // look for a deadlock whenever a retried request is denied
+        let deadlocked = detect_deadlock(&sim.state);
+        if !deadlocked.is_empty() {
+            let names: Vec<String> = deadlocked.iter().map(|i| format!("P{}", i)).collect();
+            let warning = format!("Deadlock detected: {} can never get their outstanding requests", names.join(", "));
+            println!("{}", warning.if_supports_color(Stream::Stdout, |t| t.style(Style::new().red().bold())));
+            if shared.config.recover { // roll victims back until the detection algorithm is satisfied
+                let report = recover_by_preemption(&mut sim.state, &deadlocked, &shared.config.victim_policy);
+                for preemption in &report.preemptions {
+                    stats.record_preemption(preemption.victim);
+                }
+                print!("{}", report);
+                println!("Recovered ==> Now available: {:?}\n", sim.state.available());
+            } else {
+                shared.watchdog.abort(); // nothing can change any more, stop the run
+            }
+        }
+    }
 
-            if state.need[process_id].iter().all(|&n| n == 0) { // check if all needed resources are 0
-                println!("Now available: {:?}", state.available);
-                println!("Process Maximum | Allocation | Need");
-                println!("--------------------------------------------");
+    shared.dump.take(&sim.state, &stats); // write a dump if one was requested
+    granted
+}
 
-                for (i, ((max, alloc), need)) in state.max.iter().zip(&state.allocated).zip(&state.need).enumerate() { // print the current state
-                    if state.completed_process[i] { // print completed if process is done
-                        println!("P{} --- completed ---", i);
-                    } else { // print the process state
-                        println!("P{} {:?} | {:?} | {:?}", i, max, alloc, need);
-                    }
+fn next_job(shared: &Shared) -> Option<(usize, StdRng)> { // Function to take the next process from the work queue, in turn or by weight
+    if !shared.config.weighted {
+        return shared.queue.lock().unwrap().pop_front();
+    }
+    let weights: Vec<u32> = { // copied so the state isn't locked together with the queue
+        let sim = shared.sim.lock().unwrap();
+        (0..sim.state.process_count()).map(|process_id| sim.state.weight(process_id)).collect()
+    };
+    let mut scheduler = shared.scheduler.lock().unwrap();
+    let mut queue = shared.queue.lock().unwrap();
+    let queued: Vec<u32> = queue.iter().map(|(process_id, _)| weights.get(*process_id).copied().unwrap_or(1)).collect();
+    match weighted_choice(&queued, &mut *scheduler) {
+        Some(index) => queue.remove(index),
+        None => queue.pop_front(), // only weight 0 processes are waiting, take them in turn
+    }
+}
+
+fn process_thread(shared: Arc<Shared>) { // Function for a worker thread pulling processes from the shared work queue
+    #[cfg(debug_assertions)]
+    if std::env::var_os("BANKERS_FAIL_WORKER").is_some() { // debug builds only, lets the tests reach exit code 4
+        panic!("BANKERS_FAIL_WORKER is set, failing the worker on purpose");
+    }
+    loop {
+        if shared.watchdog.tripped() || shared.stop.load(Ordering::SeqCst) { // stop as soon as the watchdog aborts the run or Ctrl-C is pressed
+            return;
+        }
+        let job = next_job(&shared); // take the next process waiting for a turn
+        let (process_id, mut random) = match job {
+            Some(job) => job,
+            None => {
+                if !shared.spawning.load(Ordering::SeqCst) && all_complete(&lock_sim(&shared).state) { // stop once every process is done and no more can arrive
+                    return;
                 }
-                println!("Process {}: has all resources it needs ==> Resources released...", process_id);
-                println!(); 
+                thread::sleep(std::time::Duration::from_millis(1)); // another worker holds the remaining processes
+                continue;
+            }
+        };
+        let _span = tracing::info_span!("process", pid = process_id).entered(); // everything this turn does is attributed to the process
+        {
+            let mut sim = lock_sim(&shared);
+            if sim.state.is_finished(process_id) {
+                continue; // finished processes leave the queue
+            }
+            if sim.state.budget_exhausted(process_id) { // a short lived process stops wherever it got to
+                sim.simulate_crash(process_id);
+                println!("Process {}: step budget of {} used up, terminated ==> Now available: {:?}\n", process_id, sim.state.step_budget(process_id).unwrap_or(0), sim.state.available());
+                continue;
+            }
+        }
+        let granted = process_step(&shared, process_id, &mut random);
+        shared.watchdog.record(granted);
+        let mut backing_off = None; // a denied process stays with this worker until it waited
+        {
+            let mut sim = lock_sim(&shared); // parking and waking under the same lock, so no wake up is lost
+            let mut parked = shared.parked.lock().unwrap();
+            for woken in sim.take_woken() { // a release granted these waiting requests
+                if let Some(woken_random) = parked.remove(&woken) { // not parked yet means it is requeued below by its own worker
+                    println!("Process {}: waiting request granted, back in line\n", woken);
+                    shared.queue.lock().unwrap().push_back((woken, woken_random));
+                }
+            }
+            if sim.pending.as_ref().is_some_and(|pending| pending.contains(process_id)) { // sleep until a release makes the request safe
+                println!("Process {}: waiting for resources to free up\n", process_id);
+                parked.insert(process_id, random);
+            } else if !sim.state.is_finished(process_id) {
+                if granted {
+                    shared.queue.lock().unwrap().push_back((process_id, random)); // back in line for another request
+                } else {
+                    backing_off = Some(random);
+                }
+            }
+        }
+        pause(&shared, process_id, granted);
+        if let Some(random) = backing_off {
+            shared.queue.lock().unwrap().push_back((process_id, random)); // back in line once the wait is over
+        }
+    }
+}
+
+fn pause(shared: &Shared, process_id: usize, granted: bool) { // Function to sleep after a grant, or back off after a denial
+    if granted {
+        if let Some(backoff) = shared.backoffs.lock().unwrap().get_mut(&process_id) {
+            backoff.reset();
+        }
+        thread::sleep(std::time::Duration::from_millis(shared.config.sleep_ms)); // sleep to simulate process
+        return;
+    }
+    let base_ms = shared.config.backoff_base_ms.unwrap_or(shared.config.sleep_ms);
+    let max_ms = shared.config.backoff_max_ms.unwrap_or(base_ms.saturating_mul(BACKOFF_MAX_FACTOR));
+    let mut backoff = shared.backoffs.lock().unwrap().remove(&process_id).unwrap_or_else(|| Backoff::new(base_ms, max_ms));
+    backoff.wait(); // the process waits longer after every denial in a row, whichever worker runs it
+    shared.backoffs.lock().unwrap().insert(process_id, backoff);
+}
+
+fn split_step(shared: &Shared, split: &SplitState, process_id: usize, random: &mut StdRng) -> bool { // Function to simulate one request of a process with --split-lock, true if granted
+    let need = split.row(process_id).need; // only the own row is locked
+    let available = split.available();
+    let request: Vec<i32> = need.iter().zip(&available).map(|(&n, &a)| if shared.config.clamp { n.min(a) } else { n }).map(|n| random.gen_range(0..=n)).collect();
+    let granted = split.request_resource(process_id, &request); // the safety check runs on a copy, nothing is locked meanwhile
+    let line = format!("Process {}: Requesting {:?} ... Process {}: Request {}", process_id, request, process_id, if granted { "granted" } else { "denied" });
+    println!("{}\n", line.if_supports_color(Stream::Stdout, |t| t.style(if granted { Style::new().green() } else { Style::new().red() })));
+    let mut stats = shared.stats.lock().unwrap();
+    if !granted && request.iter().zip(&need).all(|(r, n)| r <= n) && request.iter().zip(&available).all(|(r, a)| r <= a) { // it fit the copies it was built from, so the safety check refused it
+        stats.safety_denied += 1;
+    }
+    stats.record(granted);
+    stats.record_request_size(&request);
+    if granted && split.row(process_id).need.iter().all(|&n| n == 0) {
+        split.release_resource(process_id);
+        stats.record_completion(process_id);
+        println!("Process {}: has all resources it needs ==> Resources released...\n", process_id);
+    }
+    if shared.dump.requested.load(Ordering::SeqCst) { // joining the parts copies every row, so only when a dump was asked for
+        shared.dump.take(&split.to_state(), &stats);
+    }
+    granted
+}
 
-                release_resource(&mut state, process_id); // release resources
+fn split_thread(shared: Arc<Shared>, split: &SplitState) { // Function for a worker thread with --split-lock, the whole state is never locked
+    loop {
+        if shared.watchdog.tripped() || shared.stop.load(Ordering::SeqCst) {
+            return;
+        }
+        let (process_id, mut random) = match next_job(&shared) {
+            Some(job) => job,
+            None if split.all_finished() => return,
+            None => {
+                thread::sleep(std::time::Duration::from_millis(1)); // another worker holds the remaining processes
+                continue;
             }
-        } else { // if request is denied
-            println!("Process {}: Requesting {:?} ... Process {}: Request denied", process_id, request, process_id);
-            println!();
+        };
+        let _span = tracing::info_span!("process", pid = process_id).entered();
+        if split.row(process_id).finished {
+            continue;
         }
+        let granted = split_step(&shared, split, process_id, &mut random);
+        shared.watchdog.record(granted);
+        if !granted {
+            pause(&shared, process_id, false);
+            shared.queue.lock().unwrap().push_back((process_id, random)); // back in line once the wait is over
+            continue;
+        }
+        if !split.row(process_id).finished {
+            shared.queue.lock().unwrap().push_back((process_id, random)); // back in line for another request
+        }
+        pause(&shared, process_id, true);
+    }
+}
 
-        println!("Now available: {:?}", state.available);
-        println!("Process Maximum | Allocation | Need");
-        println!("--------------------------------------------");
+fn install_command_reader(shared: Arc<Shared>) { // Function to read runtime commands from stdin: 'd' dumps the state, 'kill <pid>' terminates a process, 'request <pid> <amounts>' makes a request
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["d"] => shared.dump.requested.store(true, Ordering::SeqCst),
+                ["kill", pid] => match pid.parse::<usize>() {
+                    Ok(process_id) => match shared.sim.lock().unwrap().state.terminate(process_id) {
+                        Ok(reclaimed) => println!("Process {}: terminated ==> Reclaimed {:?}\n", process_id, reclaimed),
+                        Err(e) => eprintln!("Can't terminate: {}", e),
+                    },
+                    Err(_) => eprintln!("Can't terminate: '{}' is not a process number", pid),
+                },
+                ["restart", pid] => match pid.parse::<usize>() {
+                    Ok(process_id) => {
+                        let mut sim = shared.sim.lock().unwrap();
+                        if process_id < sim.state.process_count() && sim.state.is_finished(process_id) {
+                            reactivate(&mut sim.state, process_id);
+                            shared.queue.lock().unwrap().push_back((process_id, process_rng(shared.config.seed, process_id))); // finished processes left the queue
+                            println!("Process {}: restarted ==> Need {:?}\n", process_id, sim.state.need(process_id));
+                        } else {
+                            eprintln!("Can't restart: P{} is not a finished process", process_id);
+                        }
+                    }
+                    Err(_) => eprintln!("Can't restart: '{}' is not a process number", pid),
+                },
+                ["request", pid, amounts @ ..] => match pid.parse::<usize>() {
+                    Ok(process_id) => {
+                        let mut sim = shared.sim.lock().unwrap();
+                        match parse_request_line(&amounts.join(" "), sim.state.resource_count()) {
+                            Ok(request) => match sim.state.validate_request(process_id, &request) {
+                                Ok(()) => {
+                                    sim.request_resource(process_id, &request); // observers print the outcome
+                                }
+                                Err(e) => eprintln!("Can't request: {}", e),
+                            },
+                            Err(e) => eprintln!("Can't request: {}", e),
+                        }
+                    }
+                    Err(_) => eprintln!("Can't request: '{}' is not a process number", pid),
+                },
+                [] => {}
+                _ => eprintln!("Unknown command '{}', expected 'd', 'kill <pid>', 'restart <pid>' or 'request <pid> <amounts>'", line.trim()),
+            }
+        }
+    });
+}
 
-        for (i, ((max, alloc), need)) in state.max.iter().zip(&state.allocated).zip(&state.need).enumerate() { // print the current state
-            if state.completed_process[i] {
-                println!("P{} --- completed ---", i);
-            } else { 
-                println!("P{} {:?} | {:?} | {:?}", i, max, alloc, need);
+fn spawn_thread(shared: Arc<Shared>, initial_amount: usize, interval: u64) { // Function to periodically admit a random process until the initial processes are done
+    let mut random = process_rng(shared.config.seed.map(|seed| seed.wrapping_sub(1)), 0); // separate stream from the processes
+    loop {
+        thread::sleep(std::time::Duration::from_millis(interval));
+        let mut sim = shared.sim.lock().unwrap();
+        if shared.watchdog.tripped() || shared.stop.load(Ordering::SeqCst) || (0..initial_amount).all(|i| sim.state.is_finished(i)) {
+            break;
+        }
+        let capacity = sim.state.total_capacity();
+        let max: Vec<i32> = capacity.iter().map(|&c| random.gen_range(0..=c)).collect(); // never more than the system has
+        let allocated: Vec<i32> = max.iter().zip(sim.state.available()).map(|(&m, &a)| random.gen_range(0..=m.min(a))).collect();
+        match sim.state.add_process(max.clone(), allocated.clone()) {
+            Ok(process_id) => {
+                println!("Admitted Process {} with max {:?} and allocated {:?}.", process_id, max, allocated);
+                println!();
+                shared.queue.lock().unwrap().push_back((process_id, process_rng(shared.config.seed, process_id))); // a worker picks it up like any other process
+            }
+            Err(e) => {
+                println!("Admission refused: {}", e);
+                println!();
             }
         }
-        drop(state); // drop the lock
-        thread::sleep(std::time::Duration::from_millis(250)); // sleep 0.25sec to simulate process
     }
-} 
+    shared.spawning.store(false, Ordering::SeqCst); // let the workers stop once the queue drains
+}
 
-fn read_input(file_path: &str) -> SystemState { // Function to parse the input file
-    let file = File::open(file_path).expect("Can't open input file"); // open the input file
-    let reader = BufReader::new(file); // create a buffer reader
-    let mut lines = reader.lines(); // create an iterator over the lines
-    let resource_amount = lines.next().unwrap().unwrap().parse().unwrap(); // parse number of resources
-    let process_amount = lines.next().unwrap().unwrap().parse().unwrap(); // parse number of processes
+fn install_tracing(flame_path: Option<&str>, color: bool) -> Result<Option<FlushGuard<BufWriter<File>>>, tracing_flame::Error> { // Function to print tracing events to stderr when RUST_LOG is set and record spans for --trace-flame
+    let printing = std::env::var_os("RUST_LOG").is_some()
+        .then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_ansi(color).with_filter(EnvFilter::from_default_env()));
+    let (flame, guard) = match flame_path {
+        Some(path) => {
+            let (layer, guard) = FlameLayer::with_file(path)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry().with(printing).with(flame).init();
+    Ok(guard)
+}
 
-    // Parse available resources
-    let available: Vec<i32> = lines.next().unwrap().unwrap() 
-        .split_whitespace()
-        .map(|s| s.parse().unwrap())
-        .collect();
+fn main() -> ExitCode { // Main function
+    let config = match SimConfig::from_env_and_args(std::env::args().skip(1)) { // environment first, then flags
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprint!("{}", USAGE);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        }
+    };
+    if config.help { // print usage and exit
+        print!("{}", USAGE);
+        return ExitCode::from(EXIT_OK);
+    }
+    if config.no_color { // colors are otherwise only used on terminals
+        owo_colors::set_override(false);
+    }
+    let _flame_guard = match install_tracing(config.trace_flame.as_deref(), !config.no_color && std::io::stderr().is_terminal()) { // flushes the folded stacks when main returns
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Can't write {}: {}", config.trace_flame.as_deref().unwrap_or_default(), e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        }
+    };
+    if config.verbose {
+        println!("Config: {:?}", config);
+    }
+    let loaded = match &config.resume_from { // resume from a snapshot or read the input file (.json files use the JSON schema)
+        Some(path) => load_state(path),
+        None if config.infer_shape => read_input_inferred(&config.input_file),
+        None => read_state(&config.input_file),
+    };
+    let initial_state = match loaded {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        }
+    };
+    if config.algorithm == SafetyAlgorithm::ClaimEdge && !is_single_instance(&initial_state) { // auto falls back to the matrices instead
+        eprintln!("Invalid configuration: --algorithm claim-edge needs every resource to be a single exclusive unit");
+        return ExitCode::from(EXIT_INVALID_INPUT);
+    }
+
+    if config.split_lock && (0..initial_state.process_count()).any(|i| initial_state.step_budget(i).is_some()) { // SplitState doesn't count steps
+        eprintln!("Invalid configuration: --split-lock can't run processes with a step budget");
+        return ExitCode::from(EXIT_INVALID_INPUT);
+    }
+
+    if config.dry_run { // validate the input and report its safety without simulating
+        if !is_consistent(&initial_state) {
+            eprintln!("Invalid input: the matrices are inconsistent");
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        }
+        print!("{}", initial_state);
+        println!("Classification: {}", initial_state.classify());
+        println!("{}", explain_classification(&initial_state));
+        return match find_safe_sequence(&initial_state) {
+            Some(sequence) => {
+                println!("SAFE");
+                println!("Safe sequence: {}", format_sequence(&sequence));
+                let mut random = process_rng(config.seed, 0);
+                println!("{:.1}% of {} random completion orders finish every process.", safety_probability(&initial_state, SAFETY_SAMPLES, &mut random) * 100.0, SAFETY_SAMPLES);
+                ExitCode::from(EXIT_OK)
+            }
+            None => {
+                println!("UNSAFE");
+                let stuck: Vec<String> = unfinishable_processes(&initial_state).iter().map(|i| format!("P{}", i)).collect();
+                println!("No order lets {} finish.", stuck.join(", "));
+                let describe = |extra: &[i32]| {
+                    let units: Vec<String> = extra.iter().enumerate().filter(|(_, &n)| n > 0).map(|(j, &n)| format!("{} unit{} of R{}", n, if n == 1 { "" } else { "s" }, j)).collect();
+                    units.join(" and ")
+                };
+                match initial_state.augmentation(AUGMENTATION_SEARCH_LIMIT) {
+                    Augmentation::Minimal(extra) => println!("Adding {} would make this state safe.", describe(&extra)),
+                    Augmentation::Capped { reached, suggestion } => {
+                        println!("Adding {} would make this state safe.", describe(&suggestion));
+                        println!("(search stopped at a total of {} extra units, a smaller change may exist)", reached);
+                    }
+                    Augmentation::AlreadySafe => {}
+                }
+                ExitCode::from(EXIT_UNSAFE)
+            }
+        };
+    }
 
-    if available.len() != resource_amount { // Check if available resources match resource amount
-        panic!("Available resources and number of resources don't match!");
+    if let Some((r_a, r_b)) = config.safety_map { // map the safe region instead of simulating, unsafe states included
+        print!("{}", initial_state);
+        print!("{}", visualize_safety_boundary(&initial_state, r_a, r_b));
+        return ExitCode::from(EXIT_OK);
     }
 
-    let mut max = vec![vec![0; resource_amount]; process_amount]; // create a vector of max resources
-    let mut allocated = vec![vec![0; resource_amount]; process_amount]; // create a vector of allocated resources
-    let mut need = vec![vec![0; resource_amount]; process_amount]; // create a vector of needed resources
+    if config.safety_trace { // print how the safety check decided instead of simulating, unsafe states included
+        print!("{}", initial_state);
+        print!("{}", safe_check_traced(&initial_state));
+        return ExitCode::from(EXIT_OK);
+    }
 
-    for i in 0..process_amount { // Loop through the processes
-        let line = lines.next().unwrap().unwrap(); // get the next line
-        let parts: Vec<&str> = line.split('|').map(|s| s.trim()).collect(); // split the line by '|'
-        if parts.len() != 3 { // Check if the line has 3 parts
-            panic!("Line must contain 3 parts separated by |");
+    // Print the initial state
+    print!("{}", initial_state);
+    match find_safe_sequence(&initial_state) {
+        Some(sequence) => {
+            println!("Safe sequence: {}", format_sequence(&sequence));
+            if config.verbose {
+                println!("Slack: {:?} (min {})", resource_slack(&initial_state), min_slack(&initial_state));
+            }
         }
+        None => { // refuse to simulate a state that is already unsafe
+            let warning = "Initial state is unsafe, no safe sequence exists.";
+            eprintln!("{}", warning.if_supports_color(Stream::Stderr, |t| t.style(Style::new().red().bold())));
+            return ExitCode::from(EXIT_UNSAFE);
+        }
+    }
+    println!(); // new line
 
-        max[i] = parts[0].split_whitespace().map(|s| s.parse().unwrap()).collect(); // parse max resources
-        allocated[i] = parts[1].split_whitespace().map(|s| s.parse().unwrap()).collect(); // parse allocated resources
-        need[i] = max[i].iter().zip(&allocated[i]).map(|(m, a)| m - a).collect(); // calculate needed resources
+    if let Some(limit) = config.all_sequences { // print the safe sequences instead of simulating
+        let mut sequences = initial_state.safe_sequences();
+        for (n, sequence) in sequences.by_ref().take(limit).enumerate() {
+            println!("{}: {}", n + 1, format_sequence(&sequence));
+        }
+        if sequences.next().is_some() {
+            println!("More than {} safe sequences exist.", limit);
+        } else {
+            println!("No more safe sequences exist.");
+        }
+        return ExitCode::from(EXIT_OK);
     }
 
-    SystemState { // Return the system state
-        available, max, allocated, need, 
-        completed_process: vec![false; process_amount], // mark all processes as not completed
+    if config.count_sequences { // print the number of safe sequences instead of simulating
+        println!("Safe sequences: {}", initial_state.count_safe_sequences());
+        return ExitCode::from(EXIT_OK);
+    }
+
+    if let Some(sequence) = &config.verify_sequence { // check the given sequence instead of simulating
+        let result = verify_sequence(&initial_state, sequence);
+        if !matches!(result, Err(VerifyError::Invalid(_))) {
+            print!("{}", sequence_table(&initial_state, sequence));
+        }
+        return match result {
+            Ok(()) => {
+                println!("{} is a safe sequence.", format_sequence(sequence));
+                ExitCode::from(EXIT_OK)
+            }
+            Err(VerifyError::Invalid(e)) => {
+                eprintln!("{}", e);
+                ExitCode::from(EXIT_INVALID_INPUT)
+            }
+            Err(e) => {
+                println!("{} is not a safe sequence: {}", format_sequence(sequence), e);
+                ExitCode::from(EXIT_UNSAFE)
+            }
+        };
     }
-}
 
-fn main() { // Main function
-    let file_path = "input.txt"; // input file name
-    let system_state = Arc::new(Mutex::new(read_input(file_path))); // read file and create a mutex
+    if let Some(runs) = config.monte_carlo { // print statistics over many runs instead of simulating
+        let results = run_monte_carlo(&initial_state, runs, &config);
+        print!("{}", results);
+        if let Some(path) = &config.csv_output {
+            match results.write_csv(path) {
+                Ok(()) => println!("Raw data written to {}", path),
+                Err(e) => eprintln!("Can't write raw data to {}: {}", path, e),
+            }
+        }
+        return ExitCode::from(EXIT_OK);
+    }
 
-    { // Lock the system state in a new scope
-        let state = system_state.lock().unwrap(); 
-        // Print the initial state
-        println!("Now available: {:?}", state.available);
-        println!("Process Maximum | Allocation | Need");
-        println!("--------------------------------------------");
+    if let Some(process_id) = config.max_grant { // print the largest safe request instead of simulating
+        match initial_state.max_grantable(process_id) {
+            Ok(request) => println!("Largest safe request for P{}: {:?}", process_id, request),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::from(EXIT_INVALID_INPUT);
+            }
+        }
+        return ExitCode::from(EXIT_OK);
+    }
 
-        for (i, ((max, alloc), need)) in state.max.iter().zip(&state.allocated).zip(&state.need).enumerate() { 
-            println!("P{} {:?} | {:?} | {:?}", i, max, alloc, need);
+    if config.path_to_unsafe { // show how the state could be driven unsafe instead of simulating
+        match shortest_path_to_unsafe(&initial_state) {
+            Ok(Some(path)) => {
+                for (index, (process_id, request)) in path.iter().enumerate() {
+                    let outcome = if index + 1 == path.len() { " → state becomes unsafe" } else { "" };
+                    println!("Step {}: Process {} requests {:?}{}", index + 1, process_id, request, outcome);
+                }
+            }
+            Ok(None) => println!("No sequence of grants leads to an unsafe state."),
+            Err(e) => { // too many processes or states to search
+                eprintln!("Can't search for a path to an unsafe state: {}", e);
+                return ExitCode::from(EXIT_INVALID_INPUT);
+            }
         }
-        println!(); // new line
-    } // Drop the lock
+        return ExitCode::from(EXIT_OK);
+    }
+
+    if config.headroom { // print the spare capacity instead of simulating
+        println!("Headroom per resource (each withdrawn alone): {:?}", initial_state.safety_margin());
+        println!("Headroom together (all withdrawn at once): {:?}", initial_state.max_withdrawal());
+        return ExitCode::from(EXIT_OK);
+    }
+
+    if let Some(depth) = config.lookahead { // print how soon each process can complete instead of simulating
+        print!("{}", initial_state.lookahead(depth));
+        return ExitCode::from(EXIT_OK);
+    }
+
+    let process_amount = initial_state.process_count();
+    let worker_amount = config.workers.unwrap_or(process_amount).min(process_amount); // never more workers than processes
+    let queue: VecDeque<(usize, StdRng)> = (0..process_amount).map(|process_id| (process_id, process_rng(config.seed, process_id))).collect(); // create a random number generator per process
+    let dump_requested = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    install_stop_trigger(Arc::clone(&stop));
+    install_dump_trigger(Arc::clone(&dump_requested));
+    let split = config.split_lock.then(|| SplitState::from_state(&initial_state));
+    let mut simulation = Simulation::new(initial_state);
+    simulation.add_observer(Arc::new(PrintingObserver { verbose: config.verbose, policy: config.sequence_policy })); // the simulation log
+    simulation.avoidance = !config.no_avoidance;
+    simulation.cache = config.safe_cache.map(SafeCache::new);
+    simulation.algorithm = config.algorithm;
+    simulation.pending = (config.pending_queue && !config.no_avoidance).then(PendingQueue::new); // without avoidance denied requests are retried anyway
+    let audit_log = Arc::new(Mutex::new(Vec::new())); // every event with its timestamp
+    simulation.add_observer(Arc::new(AuditObserver { log: Arc::clone(&audit_log) }));
+    let shared = Arc::new(Shared { // create the shared state behind a mutex
+        sim: Mutex::new(simulation),
+        stats: Mutex::new(SimStats::new(process_amount)),
+        watchdog: Watchdog { limit: config.watchdog, denied_in_a_row: AtomicUsize::new(0), tripped: AtomicBool::new(false) },
+        dump: StateDump { requested: dump_requested, count: AtomicUsize::new(0) },
+        queue: Mutex::new(queue),
+        spawning: AtomicBool::new(config.spawn_every.is_some()),
+        stop: Arc::clone(&stop),
+        revised: Mutex::new(HashSet::new()),
+        parked: Mutex::new(HashMap::new()),
+        backoffs: Mutex::new(HashMap::new()),
+        split,
+        scheduler: Mutex::new(process_rng(config.seed.map(|seed| seed.wrapping_sub(1)), 0)), // a stream no process uses
+        lock_times: Mutex::new(LockTimes::default()),
+        config,
+    });
+
+    if shared.config.commands || (cfg!(not(unix)) && std::io::stdin().is_terminal()) { // without signals the terminal is the only way to ask for a dump
+        install_command_reader(Arc::clone(&shared));
+    }
+
+    let changes = shared.config.add_resources.iter().map(|c| (c.clone(), true))
+        .chain(shared.config.remove_resources.iter().map(|c| (c.clone(), false)));
+    for (change, adding) in changes { // add or remove resources at their scheduled time
+        let shared_clone = Arc::clone(&shared);
+        thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(change.at_ms));
+            let mut sim = shared_clone.sim.lock().unwrap();
+            let (verb, result) = if adding {
+                ("Added", sim.state.add_resources(&change.deltas))
+            } else {
+                ("Removed", sim.state.remove_resources(&change.deltas))
+            };
+            match result {
+                Ok(()) => println!("{} resources {:?} ==> Now available: {:?}\n", verb, change.deltas, sim.state.available()),
+                Err(e) => eprintln!("Can't change resources: {}", e),
+            }
+        });
+    }
 
     let mut threads = vec![]; // create vector of threads
-    
-    for process_id in 0..system_state.lock().unwrap().max.len() { 
-        println!("Starting thread for Process {}.", process_id);
-        let system_state_clone = Arc::clone(&system_state); // clone the system state
+    println!("Starting {} worker threads for {} processes.", worker_amount, process_amount);
+    for _ in 0..worker_amount {
+        let shared_clone = Arc::clone(&shared); // clone the shared state
         let thread = thread::spawn(move || { // spawn a new thread
-            process_thread(system_state_clone, process_id);
+            match &shared_clone.split {
+                Some(split) => split_thread(Arc::clone(&shared_clone), split),
+                None => process_thread(shared_clone),
+            }
         });
         threads.push(thread); // push the thread to the vector
     }
 
+    if let Some(interval) = shared.config.spawn_every { // admit new processes while the initial ones run
+        let shared_clone = Arc::clone(&shared);
+        threads.push(thread::spawn(move || {
+            spawn_thread(shared_clone, process_amount, interval);
+        }));
+    }
+
+    let mut panicked = false;
     for thread in threads { // Join all threads
-        thread.join().unwrap();
+        if thread.join().is_err() { // a worker thread panicked
+            panicked = true;
+        }
+    }
+    if let Some(split) = &shared.split { // the reports below read the joined state
+        shared.sim.lock().unwrap().state = split.to_state();
+    }
+    let livelocked = detect_livelock(&audit_log.lock().unwrap(), shared.config.livelock_window);
+    if !livelocked.is_empty() { // starved rather than deadlocked, the other processes kept getting ahead
+        let names: Vec<String> = livelocked.iter().map(|p| format!("P{}", p)).collect();
+        let warning = format!("Livelock: {} denied {} times in a row while other processes made progress.", names.join(", "), shared.config.livelock_window);
+        eprintln!("{}", warning.if_supports_color(Stream::Stderr, |t| t.style(Style::new().yellow().bold())));
+    }
+    if let Some(path) = &shared.config.audit_log { // write the audit trail
+        let log = audit_log.lock().unwrap();
+        if shared.config.verbose {
+            print!("{}", audit_summary(&log));
+        }
+        match write_audit_log(&log, path) {
+            Ok(()) => println!("Audit log written to {}", path),
+            Err(e) => eprintln!("Can't write audit log to {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &shared.config.save_on_exit { // snapshot the final state
+        match shared.sim.lock() {
+            Ok(sim) => match save_state(&sim.state, path) {
+                Ok(()) => println!("State saved to {}", path),
+                Err(e) => eprintln!("Can't save state to {}: {}", path, e),
+            },
+            Err(_) => eprintln!("Can't save state to {}: a worker thread panicked while holding it", path),
+        }
+    }
+    if panicked {
+        eprintln!("A worker thread panicked, the simulation did not finish.");
+        return ExitCode::from(EXIT_INTERNAL);
+    }
+    if shared.stop.load(Ordering::SeqCst) { // report how far the run got
+        let sim = shared.sim.lock().unwrap();
+        let stats = shared.stats.lock().unwrap();
+        println!("Interrupted, partial report:");
+        print!("{}", stats);
+        println!("{}", stats.completion_order());
+        println!("{}", stats.request_histogram());
+        println!("{}", shared.lock_times.lock().unwrap());
+        print!("{}", sim.state);
+        return ExitCode::from(EXIT_INTERRUPTED);
+    }
+    if shared.watchdog.tripped() {
+        let warning = "Watchdog: no progress is possible, aborting the simulation.";
+        eprintln!("{}", warning.if_supports_color(Stream::Stderr, |t| t.style(Style::new().red().bold())));
+        return ExitCode::from(EXIT_WATCHDOG);
+    }
+    if !all_complete(&shared.sim.lock().unwrap().state) { // the workers only stop early on a watchdog abort
+        eprintln!("The worker threads stopped before every process finished.");
+        return ExitCode::from(EXIT_INTERNAL);
+    }
+    let stats = shared.stats.lock().unwrap();
+    print!("{}", stats);
+    if let Some(margin) = stats.min_margin {
+        println!("Minimum safety margin over the run: {}", margin);
+    }
+    if shared.config.cycles > 1 {
+        println!("{}", stats.cycle_counts());
+    }
+    println!("{}", stats.completion_order());
+    println!("{}", stats.request_histogram());
+    if shared.config.recover {
+        println!("{}", stats.victim_counts());
+    }
+    println!("{}", shared.lock_times.lock().unwrap());
+    if let Some(cache) = &shared.sim.lock().unwrap().cache {
+        println!("Safety cache: {} hits, {} misses", cache.hits(), cache.misses());
     }
     println!("All processes have finished.");
-}
\ No newline at end of file
+    ExitCode::from(EXIT_OK)
+}
@@ -0,0 +1,188 @@
+// Authors: Victor Vu
+// File: monte_carlo.rs
+// Description: Statistics over many seeded single threaded runs of the simulation
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::fmt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use crate::config::SimConfig;
+use crate::error::BankersError;
+use crate::state::{self, detect_deadlock, SystemState};
+
+const TICK_LIMIT: usize = 100_000; // ticks before a run that never finishes counts as stuck
+pub const SAFETY_SAMPLES: u32 = 1000; // random orders tried by the dry run
+
+#[derive(Debug, Clone, PartialEq)] // Derive traits for printing, copy and comparison of struct
+pub struct RunResult { // Struct to hold the outcome of one run
+    pub seed: u64, // seed the run was started with
+    pub ticks: usize, // ticks until every process finished, or until the run was stopped
+    pub completion_order: Vec<usize>, // processes in the order they completed
+    pub deadlocked: bool, // the run stopped because no progress was possible
+}
+
+#[derive(Debug, Clone, PartialEq)] // Derive traits for printing, copy and comparison of struct
+pub struct MonteCarloResults { // Struct to hold the statistics over all runs
+    pub runs: Vec<RunResult>, // raw data of every run
+    pub mean_ticks: f64, // mean completion time of the runs that finished
+    pub variance_ticks: f64, // variance of the completion time of the runs that finished
+    pub mean_position: Vec<Option<f64>>, // mean position of each process in the completion order, None if it never completed
+    pub deadlock_rate: f64, // fraction of runs that deadlocked
+}
+
+fn run_once(initial: &SystemState, seed: u64, config: &SimConfig) -> RunResult { // Function to run the simulation in ticks, every unfinished process makes one request per tick
+    let mut state = initial.clone();
+    let mut random = StdRng::seed_from_u64(seed);
+    let mut completion_order = Vec::new();
+    let mut denied_in_a_row = 0;
+    for tick in 1..=TICK_LIMIT {
+        for process_id in 0..state.process_count() {
+            if state.is_finished(process_id) {
+                continue;
+            }
+            let outstanding = state.outstanding_request(process_id);
+            let request: Vec<i32> = if config.no_avoidance && outstanding.iter().any(|&r| r > 0) { // retried until granted, like the threaded simulation
+                outstanding.to_vec()
+            } else {
+                state.need(process_id).iter().map(|&n| random.gen_range(0..=n)).collect()
+            };
+            let granted = if config.no_avoidance {
+                state::request_without_avoidance(&mut state, process_id, &request)
+            } else {
+                state::request_resource(&mut state, process_id, &request)
+            };
+            if granted {
+                denied_in_a_row = 0;
+                if state.need(process_id).iter().all(|&n| n == 0) {
+                    state::release_resource(&mut state, process_id);
+                    completion_order.push(process_id);
+                }
+            } else {
+                denied_in_a_row += 1;
+                let stuck = (config.no_avoidance && !detect_deadlock(&state).is_empty()) || (config.watchdog > 0 && denied_in_a_row >= config.watchdog);
+                if stuck {
+                    return RunResult { seed, ticks: tick, completion_order, deadlocked: true };
+                }
+            }
+        }
+        if state::all_complete(&state) {
+            return RunResult { seed, ticks: tick, completion_order, deadlocked: false };
+        }
+    }
+    RunResult { seed, ticks: TICK_LIMIT, completion_order, deadlocked: true }
+}
+
+pub fn safety_probability(state: &SystemState, samples: u32, rng: &mut StdRng) -> f64 { // Function to estimate the fraction of completion orders that let every process finish, always 0.0 for an unsafe state
+    if samples == 0 {
+        return 0.0;
+    }
+    let mut order: Vec<usize> = (0..state.process_count()).filter(|&i| !state.is_finished(i)).collect();
+    let mut successes = 0;
+    for _ in 0..samples {
+        order.shuffle(rng);
+        let mut work = state.available().to_vec(); // resources available as processes finish
+        let finishes = order.iter().all(|&i| {
+            if state.need(i).iter().zip(&work).any(|(n, w)| n > w) {
+                return false; // the order is fixed, so one process that can't run ends the sample
+            }
+            for j in 0..work.len() {
+                work[j] += state.held(i, j);
+            }
+            true
+        });
+        if finishes {
+            successes += 1;
+        }
+    }
+    successes as f64 / samples as f64
+}
+
+pub fn run_monte_carlo(initial: &SystemState, n: usize, config: &SimConfig) -> MonteCarloResults { // Function to run the simulation n times, run i uses the base seed plus i
+    let base = config.seed.unwrap_or_else(|| rand::thread_rng().gen()); // a random base still gives every run its own seed
+    let runs: Vec<RunResult> = (0..n as u64).map(|i| run_once(initial, base.wrapping_add(i), config)).collect();
+
+    let finished: Vec<f64> = runs.iter().filter(|r| !r.deadlocked).map(|r| r.ticks as f64).collect();
+    let mean_ticks = if finished.is_empty() { 0.0 } else { finished.iter().sum::<f64>() / finished.len() as f64 };
+    let variance_ticks = if finished.is_empty() { 0.0 } else { finished.iter().map(|t| (t - mean_ticks).powi(2)).sum::<f64>() / finished.len() as f64 };
+    let mean_position = (0..initial.process_count())
+        .map(|p| {
+            let positions: Vec<f64> = runs.iter().filter_map(|r| r.completion_order.iter().position(|&c| c == p)).map(|i| i as f64).collect();
+            if positions.is_empty() { None } else { Some(positions.iter().sum::<f64>() / positions.len() as f64) }
+        })
+        .collect();
+    let deadlock_rate = if runs.is_empty() { 0.0 } else { runs.iter().filter(|r| r.deadlocked).count() as f64 / runs.len() as f64 };
+    MonteCarloResults { runs, mean_ticks, variance_ticks, mean_position, deadlock_rate }
+}
+
+impl MonteCarloResults {
+    pub fn to_csv(&self) -> String { // Function to write the raw data, one line per run
+        let mut csv = String::from("run,seed,ticks,deadlocked,completion_order\n");
+        for (i, run) in self.runs.iter().enumerate() {
+            let order: Vec<String> = run.completion_order.iter().map(|p| p.to_string()).collect();
+            csv += &format!("{},{},{},{},{}\n", i + 1, run.seed, run.ticks, run.deadlocked, order.join(" "));
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, path: &str) -> Result<(), BankersError> { // Function to save the raw data as CSV
+        std::fs::write(path, self.to_csv()).map_err(|e| BankersError::Io(e.to_string()))
+    }
+}
+
+impl fmt::Display for MonteCarloResults { // Print the statistics as a summary table
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Runs: {} | Deadlocked: {:.1}%", self.runs.len(), self.deadlock_rate * 100.0)?;
+        writeln!(f, "Completion time: mean {:.2} ticks | variance {:.2}", self.mean_ticks, self.variance_ticks)?;
+        writeln!(f, "Process | Mean completion position")?;
+        writeln!(f, "--------------------------------------------")?;
+        for (i, position) in self.mean_position.iter().enumerate() {
+            match position {
+                Some(position) => writeln!(f, "P{} {:.2}", i, position)?,
+                None => writeln!(f, "P{} --- never completed ---", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::safe_check;
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn hundred_avoidance_runs_never_deadlock() {
+        let config = SimConfig { seed: Some(1), ..SimConfig::default() };
+        let results = run_monte_carlo(&classic(), 100, &config);
+        assert_eq!(results.runs.len(), 100);
+        assert_eq!(results.deadlock_rate, 0.0);
+        assert!(results.runs.iter().all(|r| r.completion_order.len() == 5));
+        assert_eq!(results.runs.iter().map(|r| r.seed).collect::<Vec<_>>(), (1..=100).collect::<Vec<u64>>()); // run i uses the base seed plus i
+        assert!(results.mean_position.iter().all(|p| p.is_some()));
+        assert_eq!(results.to_csv().lines().count(), 101); // header and one line per run
+    }
+
+    #[test]
+    fn safety_probability_is_0_when_deadlocked_and_1_when_every_order_works() {
+        let mut rng = StdRng::seed_from_u64(18);
+        let deadlocked = SystemState::from_matrices(vec![0], vec![vec![5], vec![2]], vec![vec![1], vec![1]]).unwrap();
+        assert_eq!(safety_probability(&deadlocked, SAFETY_SAMPLES, &mut rng), 0.0);
+        let mut plenty = classic();
+        plenty.available = vec![10, 5, 7]; // enough for any process to run first
+        assert!(safe_check(&plenty));
+        assert_eq!(safety_probability(&plenty, SAFETY_SAMPLES, &mut rng), 1.0);
+        let textbook = safety_probability(&classic(), SAFETY_SAMPLES, &mut rng);
+        assert!(textbook > 0.0 && textbook < 1.0, "{}", textbook); // only some orders of the textbook state work
+        assert_eq!(safety_probability(&plenty, 0, &mut rng), 0.0);
+    }
+}
@@ -0,0 +1,129 @@
+// Authors: Victor Vu
+// File: observer.rs
+// Description: Observers notified about grants, denials and releases
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::sync::atomic::{AtomicUsize, Ordering};
+use owo_colors::{OwoColorize, Stream};
+use crate::analysis::{min_slack, resource_slack};
+use crate::state::{format_sequence, SequencePolicy, SystemState};
+
+pub trait StateObserver: Send + Sync { // Trait for anything that wants to follow the simulation
+    fn on_grant(&self, pid: usize, req: &[i32], state: &SystemState); // called after a request is granted
+    fn on_deny(&self, pid: usize, req: &[i32]); // called after a request is denied
+    fn on_release(&self, pid: usize, state: &SystemState); // called after a process released its resources
+    fn on_crash(&self, _pid: usize, _state: &SystemState) {} // called after a process crashed, ignored by default
+    fn on_partial_release(&self, _pid: usize, _amounts: &[i32], _state: &SystemState) {} // called after a process gave back part of its allocation, ignored by default
+}
+
+pub struct PrintingObserver { // Observer printing the simulation log to stdout
+    pub verbose: bool, // print the safe sequence after each grant
+    pub policy: SequencePolicy, // how the printed safe sequence is chosen
+}
+
+impl StateObserver for PrintingObserver {
+    fn on_grant(&self, pid: usize, req: &[i32], state: &SystemState) {
+        let line = format!("Process {}: Requesting {:?} ... Process {}: Request granted", pid, req, pid);
+        println!("{}", line.if_supports_color(Stream::Stdout, |t| t.green()));
+        if self.verbose {
+            if let Some(sequence) = state.find_safe_sequence_by(self.policy) {
+                println!("Safe sequence: {}", format_sequence(&sequence));
+            }
+            println!("Slack: {:?} (min {})", resource_slack(state), min_slack(state));
+        }
+        println!(); // add a newline
+
+        if state.need[pid].iter().all(|&n| n == 0) { // print the state before the process releases everything
+            print!("{}", state);
+        }
+    }
+
+    fn on_deny(&self, pid: usize, req: &[i32]) {
+        let line = format!("Process {}: Requesting {:?} ... Process {}: Request denied", pid, req, pid);
+        println!("{}", line.if_supports_color(Stream::Stdout, |t| t.red()));
+        println!();
+    }
+
+    fn on_release(&self, pid: usize, _state: &SystemState) {
+        println!("Process {}: has all resources it needs ==> Resources released...", pid);
+        println!();
+    }
+
+    fn on_partial_release(&self, pid: usize, amounts: &[i32], _state: &SystemState) {
+        println!("Process {}: finished a phase ==> Released {:?}", pid, amounts);
+        println!();
+    }
+}
+
+#[derive(Debug, Default)] // Derive traits for printing and an all-zero start
+pub struct CountingObserver { // Observer counting events, handy for checking a run
+    pub grants: AtomicUsize, // number of granted requests
+    pub denies: AtomicUsize, // number of denied requests
+    pub releases: AtomicUsize, // number of releases
+}
+
+impl CountingObserver {
+    pub fn counts(&self) -> (usize, usize, usize) { // (grants, denies, releases)
+        (self.grants.load(Ordering::SeqCst), self.denies.load(Ordering::SeqCst), self.releases.load(Ordering::SeqCst))
+    }
+}
+
+impl StateObserver for CountingObserver {
+    fn on_grant(&self, _pid: usize, _req: &[i32], _state: &SystemState) {
+        self.grants.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_deny(&self, _pid: usize, _req: &[i32]) {
+        self.denies.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_release(&self, _pid: usize, _state: &SystemState) {
+        self.releases.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::simulation::Simulation;
+    use crate::state::{all_complete, request_log_for};
+
+    fn seeded_run(seed: u64) -> (usize, usize, usize) { // Function to run the textbook state round robin with random requests, returns the counts
+        let state = SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap();
+        let counter = Arc::new(CountingObserver::default());
+        let mut sim = Simulation::new(state);
+        sim.add_observer(counter.clone());
+        let mut rng = StdRng::seed_from_u64(seed);
+        while !all_complete(&sim.state) {
+            for pid in 0..sim.state.process_count() {
+                if sim.state.is_finished(pid) {
+                    continue;
+                }
+                let request: Vec<i32> = sim.state.need(pid).iter().map(|&n| rng.gen_range(0..=n)).collect();
+                if sim.request_resource(pid, &request) && sim.state.need(pid).iter().all(|&n| n == 0) {
+                    sim.release_resource(pid);
+                }
+            }
+        }
+        let counts = counter.counts();
+        let granted = (0..sim.state.process_count()).flat_map(|i| request_log_for(&sim.state, i)).filter(|(_, g)| *g).count();
+        assert_eq!(granted, counts.0); // the observer saw every logged grant
+        counts
+    }
+
+    #[test]
+    fn counting_observer_counts_a_seeded_run() {
+        let (grants, denies, releases) = seeded_run(42);
+        assert_eq!(releases, 5); // every process finished once
+        assert_eq!((grants, denies), (22, 19));
+        assert_eq!(seeded_run(42), (grants, denies, releases)); // same seed, same run
+    }
+}
@@ -0,0 +1,64 @@
+// Authors: Victor Vu
+// File: pending.rs
+// Description: Queue of denied requests that are granted once resources free up
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::collections::VecDeque;
+use crate::state::{self, SystemState};
+
+#[derive(Debug, Clone, Default, PartialEq)] // Derive traits for printing, copy, an empty queue and comparison of struct
+pub struct PendingQueue { // Struct to hold denied requests in the order they were made, at most one per process
+    entries: VecDeque<(usize, Vec<i32>)>, // waiting process and the request it is waiting on
+    woken: Vec<usize>, // processes whose request was granted since the last take_woken
+}
+
+impl PendingQueue {
+    pub fn new() -> Self {
+        PendingQueue::default()
+    }
+
+    pub fn enqueue(&mut self, process_id: usize, request: Vec<i32>) { // Function to wait on a request, replacing what the process waited on before
+        self.remove(process_id);
+        self.entries.push_back((process_id, request));
+    }
+
+    pub fn remove(&mut self, process_id: usize) { // Function to stop waiting, e.g. once the process got something else
+        self.entries.retain(|(p, _)| *p != process_id);
+    }
+
+    pub fn contains(&self, process_id: usize) -> bool {
+        self.entries.iter().any(|(p, _)| *p == process_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn grant_ready(&mut self, state: &mut SystemState) -> Vec<(usize, Vec<i32>)> { // Function to grant every waiting request that is now safe, first come first served
+        let mut granted = Vec::new();
+        let mut index = 0;
+        while index < self.entries.len() {
+            let (process_id, request) = &self.entries[index];
+            let (process_id, request) = (*process_id, request.clone());
+            if state.is_finished(process_id) { // nobody is waiting anymore
+                self.entries.remove(index);
+            } else if state.is_request_safe(process_id, &request).unwrap_or(false) && state::request_resource(state, process_id, &request) {
+                self.entries.remove(index);
+                self.woken.push(process_id);
+                granted.push((process_id, request));
+                index = 0; // a grant changes the state, the earlier requests get another look
+            } else {
+                index += 1;
+            }
+        }
+        granted
+    }
+
+    pub fn take_woken(&mut self) -> Vec<usize> { // Function to collect the processes granted since the last call
+        std::mem::take(&mut self.woken)
+    }
+}
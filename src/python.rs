@@ -0,0 +1,58 @@
+// Authors: Victor Vu
+// File: python.rs
+// Description: Python bindings for the system state, built with the python feature and maturin
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use crate::state::{self, SystemState};
+
+#[pyclass(name = "SystemState")]
+pub struct PySystemState { // Python class wrapping a system state
+    state: SystemState, // the wrapped state
+}
+
+impl PySystemState {
+    fn check_process(&self, pid: usize) -> PyResult<()> { // Function to raise ValueError for a process that doesn't exist
+        if pid >= self.state.process_count() {
+            return Err(PyValueError::new_err(format!("P{} doesn't exist", pid)));
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PySystemState {
+    #[new]
+    fn new(available: Vec<i32>, max_matrix: Vec<Vec<i32>>, allocated_matrix: Vec<Vec<i32>>) -> PyResult<Self> {
+        let state = SystemState::from_matrices(available, max_matrix, allocated_matrix).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PySystemState { state })
+    }
+
+    fn request_resource(&mut self, pid: usize, request: Vec<i32>) -> PyResult<bool> { // granted only if the state stays safe
+        self.check_process(pid)?;
+        if request.len() != self.state.resource_count() {
+            return Err(PyValueError::new_err(format!("request has {} values but there are {} resources", request.len(), self.state.resource_count())));
+        }
+        Ok(state::request_resource(&mut self.state, pid, &request))
+    }
+
+    fn release_resource(&mut self, pid: usize) -> PyResult<()> { // everything the process holds goes back to available
+        self.check_process(pid)?;
+        state::release_resource(&mut self.state, pid);
+        Ok(())
+    }
+
+    fn safe_check(&self) -> Option<Vec<usize>> { // a safe sequence, None if the state is unsafe
+        state::find_safe_sequence(&self.state)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SystemState({})", self.state.compact())
+    }
+}
+
+#[pymodule]
+fn vu_bankers_algo(module: &Bound<'_, PyModule>) -> PyResult<()> { // Python module named after the crate
+    module.add_class::<PySystemState>()
+}
@@ -0,0 +1,145 @@
+// Authors: Victor Vu
+// File: recovery.rs
+// Description: Deadlock recovery by preempting resources from deadlocked processes
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::fmt;
+use crate::state::{detect_deadlock, ProcessState, SystemState};
+
+pub trait VictimPolicy { // Trait for deciding which deadlocked process loses its resources, the lowest cost is preempted first
+    fn cost(&self, state: &SystemState, process_id: usize) -> i64;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum VictimRule { // Enum of the built in victim policies
+    FewestHeld, // the process holding the fewest units loses the least work
+    LowestPriority, // the least important process, see SystemState::priority
+    LeastProgress, // the process with the largest remaining need has done the least
+}
+
+impl VictimPolicy for VictimRule {
+    fn cost(&self, state: &SystemState, process_id: usize) -> i64 {
+        match self {
+            VictimRule::FewestHeld => (0..state.resource_count()).map(|j| state.held(process_id, j) as i64).sum(),
+            VictimRule::LowestPriority => state.priority(process_id) as i64,
+            VictimRule::LeastProgress => -state.need(process_id).iter().map(|&n| n as i64).sum::<i64>(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct Preemption { // Struct to hold what was taken from one victim
+    pub victim: usize, // process the resources were taken from
+    pub taken: Vec<i32>, // units taken, added back to its need
+    pub unblocked: Option<usize>, // process the preemption was meant to unblock, None if the victim lost everything
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)] // Derive traits for printing, copy, an empty report and comparison of struct
+pub struct RecoveryReport { // Struct to hold every preemption of a recovery in order
+    pub preemptions: Vec<Preemption>,
+}
+
+impl RecoveryReport {
+    pub fn victims(&self) -> Vec<usize> { // processes that lost resources, in the order they were first chosen
+        let mut victims = Vec::new();
+        for preemption in &self.preemptions {
+            if !victims.contains(&preemption.victim) {
+                victims.push(preemption.victim);
+            }
+        }
+        victims
+    }
+}
+
+impl fmt::Display for RecoveryReport { // Print one line per preemption
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.preemptions.is_empty() {
+            return writeln!(f, "No preemption needed.");
+        }
+        for preemption in &self.preemptions {
+            match preemption.unblocked {
+                Some(process) => writeln!(f, "Preempted {:?} from P{} to unblock P{}", preemption.taken, preemption.victim, process)?,
+                None => writeln!(f, "Preempted everything ({:?}) from P{}", preemption.taken, preemption.victim)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn recover_by_preemption(state: &mut SystemState, deadlocked: &[usize], policy: &dyn VictimPolicy) -> RecoveryReport { // Function to take resources from the cheapest deadlocked processes until detection finds no deadlock
+    let mut report = RecoveryReport::default();
+    let mut stuck = deadlocked.to_vec();
+    while !stuck.is_empty() {
+        let shortfall = |state: &SystemState, process: usize| -> Vec<i32> { // what the outstanding request lacks in available
+            state.outstanding_request(process).iter().zip(state.available()).map(|(r, a)| (r - a).max(0)).collect()
+        };
+        let cheapest = stuck.iter() // the cheapest victim that can unblock someone alone, then the fewest units taken
+            .flat_map(|&victim| stuck.iter().filter(move |&&waiting| waiting != victim).map(move |&waiting| (victim, waiting)))
+            .filter(|&(victim, waiting)| shortfall(state, waiting).iter().enumerate().all(|(j, &s)| s <= state.held(victim, j)))
+            .min_by_key(|&(victim, waiting)| (policy.cost(state, victim), shortfall(state, waiting).iter().sum::<i32>(), victim, waiting));
+        let preemption = match cheapest {
+            Some((victim, waiting)) => {
+                let taken: Vec<i32> = shortfall(state, waiting).iter().enumerate().map(|(j, &s)| if s > 0 { state.allocated(victim)[j].min(s) } else { 0 }).collect();
+                Preemption { victim, taken, unblocked: Some(waiting) }
+            }
+            None => { // no single victim holds enough, the cheapest one holding anything loses everything
+                let Some(victim) = stuck.iter().copied().filter(|&i| state.allocated(i).iter().any(|&a| a > 0)).min_by_key(|&i| (policy.cost(state, i), i)) else {
+                    break; // nothing left to take, the requests can't be met by preemption
+                };
+                Preemption { victim, taken: state.allocated(victim).to_vec(), unblocked: None }
+            }
+        };
+        for j in 0..preemption.taken.len() { // roll the victim back, it has to acquire these again
+            state.available[j] += state.consumed(j, preemption.taken[j]);
+            state.allocated[preemption.victim][j] -= preemption.taken[j];
+            state.need[preemption.victim][j] += preemption.taken[j];
+        }
+        state.process_states[preemption.victim] = ProcessState::Blocked;
+        report.preemptions.push(preemption);
+        stuck = detect_deadlock(state);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crossed_deadlock() -> SystemState { // Function to build P0 holding R0 and waiting on R1 while P1 holds R1 and waits on R0
+        let mut state = SystemState::from_matrices(vec![0, 0], vec![vec![1, 1], vec![3, 3]], vec![vec![1, 0], vec![0, 2]]).unwrap();
+        state.request_matrix = vec![vec![0, 1], vec![1, 0]];
+        state
+    }
+
+    #[test]
+    fn preemption_clears_the_deadlock() {
+        let mut state = crossed_deadlock();
+        assert_eq!(detect_deadlock(&state), vec![0, 1]);
+        let report = recover_by_preemption(&mut state, &[0, 1], &VictimRule::FewestHeld);
+        assert_eq!(report.preemptions, vec![Preemption { victim: 0, taken: vec![1, 0], unblocked: Some(1) }]); // P0 holds 1 unit, P1 holds 2
+        assert!(detect_deadlock(&state).is_empty());
+        assert_eq!(state.available, vec![1, 0]);
+        assert_eq!(state.need[0], vec![1, 1]); // P0 has to acquire R0 again
+        assert_eq!(state.process_states[0], ProcessState::Blocked);
+        assert_eq!(report.to_string(), "Preempted [1, 0] from P0 to unblock P1\n");
+        assert_eq!(recover_by_preemption(&mut state, &[], &VictimRule::FewestHeld).to_string(), "No preemption needed.\n");
+    }
+
+    #[test]
+    fn each_victim_policy_picks_its_own_victim() {
+        let mut by_priority = crossed_deadlock();
+        by_priority.set_priority(0, 5); // P1 is the least important
+        let report = recover_by_preemption(&mut by_priority, &[0, 1], &VictimRule::LowestPriority);
+        assert_eq!(report.victims(), vec![1]);
+        assert_eq!(report.preemptions[0].taken, vec![0, 1]); // only what P0 lacks
+        let report = recover_by_preemption(&mut crossed_deadlock(), &[0, 1], &VictimRule::LeastProgress);
+        assert_eq!(report.victims(), vec![1]); // P1 still needs 3 + 1, P0 only 1
+        struct Always(usize);
+        impl VictimPolicy for Always { // a custom policy that always prefers one process
+            fn cost(&self, _state: &SystemState, process_id: usize) -> i64 {
+                (process_id != self.0) as i64
+            }
+        }
+        assert_eq!(recover_by_preemption(&mut crossed_deadlock(), &[0, 1], &Always(1)).victims(), vec![1]);
+    }
+}
@@ -0,0 +1,275 @@
+// Authors: Victor Vu
+// File: sequences.rs
+// Description: Enumeration of every safe sequence of a system state
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use crate::state::SystemState;
+
+pub struct SafeSequences<'a> { // Iterator yielding every safe sequence with a backtracking search
+    state: &'a SystemState, // state being searched
+    work: Vec<i32>, // resources available after finishing the processes in path
+    done: Vec<bool>, // processes that are finished or already in path
+    path: Vec<usize>, // sequence built so far
+    next: Vec<usize>, // next candidate process to try at each depth
+    target: usize, // length of a complete sequence
+    exhausted: bool, // set once the search tree is fully explored
+}
+
+impl<'a> SafeSequences<'a> {
+    fn new(state: &'a SystemState) -> Self {
+        let done: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i)).collect(); // completed and crashed processes are skipped
+        let target = done.iter().filter(|&&d| !d).count();
+        SafeSequences { state, work: state.available.clone(), done, path: Vec::new(), next: vec![0], target, exhausted: false }
+    }
+
+    fn backtrack(&mut self) { // Function to undo the last process in path
+        self.next.pop();
+        if let Some(i) = self.path.pop() {
+            self.done[i] = false;
+            for j in 0..self.work.len() {
+                self.work[j] -= self.state.held(i, j); // take back what the process released
+            }
+        }
+    }
+}
+
+impl Iterator for SafeSequences<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        if self.exhausted {
+            return None;
+        }
+        if self.target == 0 { // nothing left to run, the empty sequence is the only one
+            self.exhausted = true;
+            return Some(Vec::new());
+        }
+        loop {
+            let depth = self.path.len();
+            let start = self.next[depth];
+            let candidate = (start..self.state.process_count()) // find the next process that can finish now
+                .find(|&i| !self.done[i] && self.state.need[i].iter().zip(&self.work).all(|(n, w)| n <= w));
+
+            match candidate {
+                Some(i) => {
+                    self.next[depth] = i + 1; // resume after this process when backtracking
+                    self.done[i] = true;
+                    for j in 0..self.work.len() {
+                        self.work[j] += self.state.held(i, j); // the process finishes and releases its allocation
+                    }
+                    self.path.push(i);
+                    self.next.push(0);
+                    if self.path.len() == self.target { // every process finished, yield the sequence
+                        let sequence = self.path.clone();
+                        self.backtrack();
+                        return Some(sequence);
+                    }
+                }
+                None if depth == 0 => { // nothing left to try at the root
+                    self.exhausted = true;
+                    return None;
+                }
+                None => self.backtrack(),
+            }
+        }
+    }
+}
+
+impl SystemState {
+    pub fn safe_sequences(&self) -> SafeSequences<'_> { // every safe sequence, computed lazily so callers can take the first few
+        SafeSequences::new(self)
+    }
+
+    pub fn count_safe_sequences(&self) -> u128 { // number of safe sequences without listing them
+        let active: Vec<usize> = (0..self.process_count()).filter(|&i| !self.is_finished(i)).collect(); // completed and crashed processes are skipped
+        if active.len() > BITMASK_LIMIT { // too many processes for the subset table, count by backtracking
+            return self.safe_sequences().count() as u128;
+        }
+        let mut memo = vec![None; 1 << active.len()];
+        count_from(self, &active, 0, &mut self.available.clone(), &mut memo)
+    }
+}
+
+pub const BITMASK_LIMIT: usize = 20; // most processes counted with the subset table (2^20 entries)
+
+fn count_from(state: &SystemState, active: &[usize], mask: usize, work: &mut [i32], memo: &mut [Option<u128>]) -> u128 { // Function to count completions of the processes not in mask
+    if mask == (1 << active.len()) - 1 {
+        return 1; // every process finished
+    }
+    if let Some(count) = memo[mask] {
+        return count; // work only depends on which processes finished, so the count can be reused
+    }
+    let mut count: u128 = 0;
+    for (bit, &i) in active.iter().enumerate() {
+        if mask & (1 << bit) == 0 && state.need[i].iter().zip(work.iter()).all(|(n, w)| n <= w) { // process can finish next
+            for j in 0..work.len() {
+                work[j] += state.held(i, j);
+            }
+            count += count_from(state, active, mask | (1 << bit), work, memo);
+            for j in 0..work.len() {
+                work[j] -= state.held(i, j);
+            }
+        }
+    }
+    memo[mask] = Some(count);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::state::{release_resource, safe_check, ResourceKind};
+
+    const BRUTEFORCE_LIMIT: usize = 8; // most processes the permutation check is run for (8! orders)
+
+    fn safe_check_bruteforce(state: &SystemState) -> bool { // Function to check safety by trying every completion order, a slow reference for safe_check
+        let mut order: Vec<usize> = (0..state.process_count()).filter(|&i| !state.is_finished(i)).collect();
+        permutations_from(state, &mut order, 0)
+    }
+
+    fn permutations_from(state: &SystemState, order: &mut [usize], fixed: usize) -> bool { // Function to try every order of the processes after the first fixed ones
+        if fixed == order.len() {
+            return runs_in_order(state, order);
+        }
+        for k in fixed..order.len() {
+            order.swap(fixed, k);
+            let safe = permutations_from(state, order, fixed + 1);
+            order.swap(fixed, k);
+            if safe {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn runs_in_order(state: &SystemState, order: &[usize]) -> bool { // Function to check every process finishes when run exactly in this order
+        let mut work = state.available.clone();
+        for &i in order {
+            if state.need[i].iter().zip(&work).any(|(n, w)| n > w) {
+                return false;
+            }
+            for j in 0..work.len() {
+                work[j] += state.held(i, j);
+            }
+        }
+        true
+    }
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    fn random_state(rng: &mut StdRng, processes: usize) -> SystemState { // Function to build a small random state, often safe and often not
+        let resources = rng.gen_range(1..=3);
+        let max: Vec<Vec<i32>> = (0..processes).map(|_| (0..resources).map(|_| rng.gen_range(0..=4)).collect()).collect();
+        let allocated = max.iter().map(|row| row.iter().map(|&m| rng.gen_range(0..=m)).collect()).collect();
+        let available = (0..resources).map(|_| rng.gen_range(0..=3)).collect();
+        let mut state = SystemState::from_matrices(available, max, allocated).unwrap();
+        if processes > 1 && rng.gen_bool(0.3) {
+            release_resource(&mut state, rng.gen_range(0..processes)); // completed processes take no part in the orders
+        }
+        state
+    }
+
+    fn count_permutations(state: &SystemState, order: &mut [usize], fixed: usize) -> u128 { // Function to count the orders that work by trying every permutation
+        if fixed == order.len() {
+            return runs_in_order(state, order) as u128;
+        }
+        let mut count = 0;
+        for k in fixed..order.len() {
+            order.swap(fixed, k);
+            count += count_permutations(state, order, fixed + 1);
+            order.swap(fixed, k);
+        }
+        count
+    }
+
+    #[test]
+    fn classic_state_has_16_safe_sequences() {
+        let state = classic();
+        let sequences: Vec<Vec<usize>> = state.safe_sequences().collect();
+        assert_eq!(sequences.len(), 16);
+        assert_eq!(sequences[0], vec![1, 3, 0, 2, 4]); // lowest index first comes out first
+        assert!(sequences.iter().all(|s| s[0] == 1 || s[0] == 3)); // only P1 and P3 can finish first
+        assert_eq!(state.safe_sequences().take(3).count(), 3);
+    }
+
+    #[test]
+    fn completed_processes_are_skipped() {
+        let mut state = classic();
+        release_resource(&mut state, 1);
+        assert_eq!(state.safe_sequences().count(), 8);
+        assert!(state.safe_sequences().all(|s| s.len() == 4 && !s.contains(&1)));
+    }
+
+    #[test]
+    fn small_examples_count_by_hand() {
+        let independent = SystemState::from_matrices(vec![0], vec![vec![1], vec![2], vec![3]], vec![vec![1], vec![2], vec![3]]).unwrap(); // nobody needs anything, any order works
+        assert_eq!(independent.safe_sequences().count(), 6);
+        let chain = SystemState::from_matrices(vec![1], vec![vec![2], vec![3]], vec![vec![1], vec![1]]).unwrap(); // P1 needs what P0 releases
+        assert_eq!(chain.safe_sequences().collect::<Vec<_>>(), vec![vec![0, 1]]);
+        let stuck = SystemState::from_matrices(vec![0], vec![vec![2], vec![2]], vec![vec![1], vec![1]]).unwrap();
+        assert_eq!(stuck.safe_sequences().count(), 0);
+        assert_eq!(SystemState::default().safe_sequences().collect::<Vec<_>>(), vec![Vec::<usize>::new()]);
+    }
+
+    #[test]
+    fn count_agrees_with_brute_force_up_to_8_processes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut several = 0; // states with more than one safe order
+        for processes in 1..=BRUTEFORCE_LIMIT {
+            for _ in 0..10 {
+                let state = random_state(&mut rng, processes);
+                let mut order: Vec<usize> = (0..processes).filter(|&i| !state.is_finished(i)).collect();
+                let expected = count_permutations(&state, &mut order, 0);
+                assert_eq!(state.count_safe_sequences(), expected, "{:?}", state);
+                assert_eq!(state.safe_sequences().count() as u128, expected);
+                several += (expected > 1) as usize;
+            }
+        }
+        assert!(several > 10, "the random states are too often unsafe");
+        assert_eq!(classic().count_safe_sequences(), 16);
+    }
+
+    fn any_state() -> impl Strategy<Value = SystemState> { // Strategy for states with up to BRUTEFORCE_LIMIT processes, some finished and some resources shareable
+        (1..=BRUTEFORCE_LIMIT, 1..=3usize).prop_flat_map(|(processes, resources)| {
+            (
+                proptest::collection::vec(0..=4i32, resources),
+                proptest::collection::vec(proptest::collection::vec((0..=4i32, 0..=4i32), resources), processes),
+                proptest::collection::vec(proptest::bool::weighted(0.2), processes),
+                proptest::collection::vec(proptest::bool::weighted(0.2), resources),
+            )
+        })
+        .prop_map(|(available, rows, finished, shareable)| {
+            let max = rows.iter().map(|row| row.iter().map(|&(a, b)| a.max(b)).collect()).collect();
+            let allocated = rows.iter().map(|row| row.iter().map(|&(a, b)| a.min(b)).collect()).collect();
+            let mut state = SystemState::from_matrices(available, max, allocated).unwrap();
+            for (j, &shared) in shareable.iter().enumerate() {
+                if shared {
+                    state.resource_kinds[j] = ResourceKind::Shareable;
+                }
+            }
+            for (i, &done) in finished.iter().enumerate() {
+                if done {
+                    release_resource(&mut state, i);
+                }
+            }
+            state
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn bruteforce_agrees_with_safe_check(state in any_state()) {
+            prop_assert_eq!(safe_check_bruteforce(&state), safe_check(&state));
+        }
+    }
+}
@@ -0,0 +1,260 @@
+// Authors: Victor Vu
+// File: simulation.rs
+// Description: Simulation wrapper notifying observers about state changes
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::cache::SafeCache;
+use crate::claim_edge::SafetyAlgorithm;
+use crate::error::BankersError;
+use crate::observer::StateObserver;
+use crate::pending::PendingQueue;
+use crate::state::{self, SystemState};
+
+pub struct Simulation { // Struct wrapping the state together with its observers
+    pub state: SystemState, // the system state
+    pub observers: Vec<Arc<dyn StateObserver>>, // notified about every grant, denial and release
+    pub avoidance: bool, // run the safety check before granting, off to let deadlocks happen
+    pub cache: Option<SafeCache>, // memoized safety results, None checks every time
+    pub algorithm: SafetyAlgorithm, // how the safety of a grant is decided
+    pub pending: Option<PendingQueue>, // denied requests waiting for resources, None lets processes roll new ones
+}
+
+impl Simulation {
+    pub fn new(state: SystemState) -> Self {
+        Simulation { state, observers: Vec::new(), avoidance: true, cache: None, algorithm: SafetyAlgorithm::Matrix, pending: None }
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn StateObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn request_resource(&mut self, process_id: usize, request: &[i32]) -> bool { // Function to request resources and notify observers
+        let granted = if self.avoidance && self.algorithm.uses_claim_edges(&self.state) {
+            state::request_resource_claim_edges(&mut self.state, process_id, request)
+        } else if let (true, Some(cache)) = (self.avoidance, &mut self.cache) {
+            state::request_resource_cached(&mut self.state, process_id, request, cache)
+        } else if self.avoidance {
+            state::request_resource(&mut self.state, process_id, request)
+        } else {
+            state::request_without_avoidance(&mut self.state, process_id, request)
+        };
+        for observer in &self.observers {
+            if granted {
+                observer.on_grant(process_id, request, &self.state);
+            } else {
+                observer.on_deny(process_id, request);
+            }
+        }
+        self.trace_outcome(process_id, request, granted);
+        self.update_pending(process_id, request, granted);
+        granted
+    }
+
+    pub fn request_resource_partial(&mut self, process_id: usize, request: &[i32]) -> Option<Vec<i32>> { // Function to grant what part of a request is safe and notify observers
+        if !self.avoidance { // without the safety check anything that fits is granted whole
+            return self.request_resource(process_id, request).then(|| request.to_vec());
+        }
+        let granted = state::request_resource_partial(&mut self.state, process_id, request);
+        for observer in &self.observers {
+            match &granted {
+                Some(part) => observer.on_grant(process_id, part, &self.state),
+                None => observer.on_deny(process_id, request),
+            }
+        }
+        self.trace_outcome(process_id, granted.as_deref().unwrap_or(request), granted.is_some());
+        self.update_pending(process_id, request, granted.is_some());
+        granted
+    }
+
+    fn trace_outcome(&self, process_id: usize, request: &[i32], granted: bool) { // Function to emit a tracing event for a grant or denial
+        let available = self.state.available();
+        if granted {
+            tracing::info!(process = process_id, ?request, ?available, "granted");
+        } else {
+            tracing::info!(process = process_id, ?request, ?available, "denied");
+        }
+    }
+
+    fn update_pending(&mut self, process_id: usize, request: &[i32], granted: bool) { // Function to queue a denied request, or drop the old one once the process got something
+        let Some(pending) = &mut self.pending else { return };
+        let waitable = self.avoidance && process_id < self.state.process_count() && request.len() == self.state.resource_count()
+            && request.iter().zip(self.state.need(process_id)).all(|(&r, &n)| r >= 0 && r <= n); // a request beyond need can never be granted
+        if granted || !waitable {
+            pending.remove(process_id);
+        } else {
+            pending.enqueue(process_id, request.to_vec());
+        }
+        if granted {
+            self.grant_pending(); // a grant changes the state, keep every waiting request ungrantable
+        }
+    }
+
+    fn grant_pending(&mut self) { // Function to grant the waiting requests that became safe and notify observers
+        let Some(mut pending) = self.pending.take() else { return };
+        for (process_id, request) in pending.grant_ready(&mut self.state) {
+            for observer in &self.observers {
+                observer.on_grant(process_id, &request, &self.state);
+            }
+        }
+        self.pending = Some(pending);
+    }
+
+    pub fn take_woken(&mut self) -> Vec<usize> { // Function to collect the processes whose waiting request was granted
+        self.pending.as_mut().map(|pending| pending.take_woken()).unwrap_or_default()
+    }
+
+    pub fn release_resource(&mut self, process_id: usize) { // Function to release resources and notify observers
+        state::release_resource(&mut self.state, process_id);
+        tracing::info!(process = process_id, available = ?self.state.available(), "released");
+        for observer in &self.observers {
+            observer.on_release(process_id, &self.state);
+        }
+        self.grant_pending();
+    }
+
+    pub fn release_and_reset(&mut self, process_id: usize) { // Function to finish a cycle, release everything and notify observers
+        self.state.release_and_reset(process_id);
+        tracing::info!(process = process_id, available = ?self.state.available(), "released");
+        for observer in &self.observers {
+            observer.on_release(process_id, &self.state);
+        }
+        self.grant_pending();
+    }
+
+    pub fn release_partial(&mut self, process_id: usize, amounts: &[i32]) -> Result<(), BankersError> { // Function to release part of an allocation and notify observers
+        self.state.release_partial(process_id, amounts)?;
+        for observer in &self.observers {
+            observer.on_partial_release(process_id, amounts, &self.state);
+        }
+        self.grant_pending();
+        Ok(())
+    }
+
+    pub fn simulate_crash(&mut self, process_id: usize) { // Function to crash a process and notify observers
+        state::simulate_crash(&mut self.state, process_id);
+        tracing::warn!(process = process_id, available = ?self.state.available(), "crashed");
+        for observer in &self.observers {
+            observer.on_crash(process_id, &self.state);
+        }
+        self.grant_pending();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum TickEvent { // Enum of what happened to a process during a tick
+    Granted { process: usize, request: Vec<i32> }, // request was granted
+    Denied { process: usize, request: Vec<i32> }, // request was denied
+    Released(usize), // process got everything and released it
+}
+
+pub fn weighted_choice<R: Rng>(weights: &[u32], rng: &mut R) -> Option<usize> { // Function to pick an index with probability proportional to its weight, None if every weight is 0
+    let total: u64 = weights.iter().map(|&w| w as u64).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut target = rng.gen_range(0..total);
+    for (index, &weight) in weights.iter().enumerate() {
+        if target < weight as u64 {
+            return Some(index);
+        }
+        target -= weight as u64;
+    }
+    None // unreachable, target is below the total
+}
+
+pub fn simulate_tick(state: &mut SystemState, rng: &mut StdRng) -> Vec<TickEvent> { // Function to give every unfinished process one random request, single threaded and deterministic for a seeded rng
+    let mut events = Vec::new();
+    for process_id in 0..state.process_count() {
+        if state.is_finished(process_id) {
+            continue;
+        }
+        let request: Vec<i32> = state.need[process_id].iter().map(|&n| rng.gen_range(0..=n)).collect(); // random request up to the need
+        if state::request_resource(state, process_id, &request) {
+            events.push(TickEvent::Granted { process: process_id, request });
+            if state.need[process_id].iter().all(|&n| n == 0) { // release like the threaded simulation does
+                state::release_resource(state, process_id);
+                events.push(TickEvent::Released(process_id));
+            }
+        } else {
+            events.push(TickEvent::Denied { process: process_id, request });
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::{Rng, SeedableRng};
+    use crate::state::is_consistent;
+
+    fn random_state(rng: &mut StdRng) -> SystemState { // Function to build a random state with up to 5 processes and 3 resources
+        let (processes, resources) = (rng.gen_range(1..=5), rng.gen_range(1..=3));
+        let max: Vec<Vec<i32>> = (0..processes).map(|_| (0..resources).map(|_| rng.gen_range(0..=6)).collect()).collect();
+        let allocated = max.iter().map(|row| row.iter().map(|&m| rng.gen_range(0..=m)).collect()).collect();
+        let available = (0..resources).map(|_| rng.gen_range(0..=6)).collect();
+        SystemState::from_matrices(available, max, allocated).unwrap()
+    }
+
+    #[test]
+    fn weighted_choice_never_picks_weight_0_and_follows_the_weights() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut picks = [0usize; 3];
+        for _ in 0..4000 {
+            picks[weighted_choice(&[0, 1, 3], &mut rng).unwrap()] += 1;
+        }
+        assert_eq!(picks[0], 0);
+        assert!((2.7..3.3).contains(&(picks[2] as f64 / picks[1] as f64)), "{:?}", picks); // weight 3 is picked about three times as often as weight 1
+        assert_eq!(weighted_choice(&[0, 0], &mut rng), None); // the caller falls back to taking them in turn
+        assert_eq!(weighted_choice(&[], &mut rng), None);
+    }
+
+    proptest! {
+        #[test]
+        fn ticks_keep_the_state_consistent(seed in any::<u64>(), ticks in 0..40usize) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut state = random_state(&mut rng);
+            let was_safe = state::safe_check(&state);
+            for _ in 0..ticks {
+                simulate_tick(&mut state, &mut rng);
+                prop_assert!(is_consistent(&state));
+                for i in 0..state.process_count() {
+                    prop_assert!(state.need(i).iter().chain(state.allocated(i)).all(|&x| x >= 0));
+                }
+                if was_safe {
+                    prop_assert!(state::safe_check(&state)); // grants never leave a safe state
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_ticks() {
+        let run = || {
+            let mut rng = StdRng::seed_from_u64(9);
+            let mut state = random_state(&mut rng);
+            (0..10).map(|_| simulate_tick(&mut state, &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn queued_large_request_is_granted_after_a_release() {
+        let state = SystemState::from_matrices(vec![1], vec![vec![3], vec![3]], vec![vec![2], vec![0]]).unwrap();
+        let mut sim = Simulation::new(state);
+        sim.pending = Some(PendingQueue::new());
+        assert!(!sim.request_resource(1, &[3])); // only 1 unit is free
+        assert!(sim.pending.as_ref().unwrap().contains(1));
+        assert!(sim.request_resource(0, &[1]));
+        assert!(sim.take_woken().is_empty()); // P1 still waits, P0 holds all 3 units
+        sim.release_resource(0);
+        assert_eq!(sim.take_woken(), vec![1]); // the release made room for the whole request
+        assert_eq!(sim.state.allocated[1], vec![3]);
+        assert_eq!(sim.state.available(), &[0]);
+        assert!(sim.pending.as_ref().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+// Authors: Victor Vu
+// File: split_state.rs
+// Description: System state with available and every process row behind their own locks, for runs with many threads
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+// A request copies available and the rows without holding more than one lock at a time and runs the safety check on
+// the copy with nothing locked. It then locks available only to commit: if the version counter moved since the copy,
+// another grant or release got in between and the request is decided again on a fresh copy.
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicU64, Ordering};
+#[cfg(loom)]
+use loom::sync::Mutex; // RUSTFLAGS="--cfg loom" cargo test --test loom_test checks every interleaving
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(loom))]
+use std::sync::Mutex;
+use crate::state::{ProcessState, ResourceKind, SystemState};
+
+#[derive(Debug, Clone, PartialEq, Eq)] // Derive traits for printing, copy and comparison of struct
+pub struct ProcessRow { // Struct to hold the part of the state that belongs to one process
+    pub max: Vec<i32>, // max resources the process may request
+    pub allocated: Vec<i32>, // resources allocated to the process
+    pub need: Vec<i32>, // resources the process still needs
+    pub finished: bool, // released everything or crashed, the process won't request anything again
+}
+
+pub struct SplitState { // Struct to hold a system state behind one lock for available and one per process
+    available: Mutex<Vec<i32>>, // available resources, always locked before any row
+    rows: Vec<Mutex<ProcessRow>>, // one row per process
+    kinds: Vec<ResourceKind>, // whether each resource is exclusive or shareable, never changes
+    version: AtomicU64, // bumped with available held before every change, a copy is consistent if it didn't move
+}
+
+impl SplitState {
+    pub fn from_state(state: &SystemState) -> SplitState { // Function to split a state into separately locked parts
+        let rows = (0..state.process_count())
+            .map(|i| Mutex::new(ProcessRow { max: state.max[i].clone(), allocated: state.allocated[i].clone(), need: state.need[i].clone(), finished: state.is_finished(i) }))
+            .collect();
+        SplitState { available: Mutex::new(state.available.clone()), rows, kinds: state.resource_kinds.clone(), version: AtomicU64::new(0) }
+    }
+
+    pub fn to_state(&self) -> SystemState { // Function to join the parts back into a consistent system state
+        let (available, rows, _) = self.snapshot();
+        let mut state = SystemState::from_matrices(available, rows.iter().map(|r| r.max.clone()).collect(), rows.iter().map(|r| r.allocated.clone()).collect())
+            .expect("the rows were taken from a consistent state");
+        for (i, row) in rows.iter().enumerate() {
+            state.need[i] = row.need.clone(); // a finished process has need = max, not max - allocated
+            if row.finished {
+                state.process_states[i] = ProcessState::Completed;
+            }
+        }
+        state.resource_kinds = self.kinds.clone();
+        state.recount_capacity();
+        state
+    }
+
+    pub fn process_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn row(&self, process_id: usize) -> ProcessRow { // Function to copy the row of one process, only that row is locked
+        self.rows[process_id].lock().unwrap().clone()
+    }
+
+    pub fn available(&self) -> Vec<i32> { // Function to copy the available resources
+        self.available.lock().unwrap().clone()
+    }
+
+    pub fn all_finished(&self) -> bool { // Function to see if every process released everything or crashed
+        self.rows.iter().all(|row| row.lock().unwrap().finished)
+    }
+
+    fn consumed(&self, resource_id: usize, amount: i32) -> i32 { // units an allocation of amount takes out of available
+        match self.kinds[resource_id] {
+            ResourceKind::Exclusive => amount,
+            ResourceKind::Shareable => 0,
+        }
+    }
+
+    fn snapshot(&self) -> (Vec<i32>, Vec<ProcessRow>, u64) { // Function to copy available and every row as they were at one point in time, with the version they were copied at
+        loop {
+            let (available, version) = {
+                let available = self.available.lock().unwrap(); // no change is half done while available is held
+                (available.clone(), self.version.load(Ordering::SeqCst))
+            };
+            let rows: Vec<ProcessRow> = self.rows.iter().map(|row| row.lock().unwrap().clone()).collect(); // each row is locked only while it is copied
+            if self.version.load(Ordering::SeqCst) == version { // a change bumps the version before touching a row
+                return (available, rows, version);
+            }
+        }
+    }
+
+    fn is_safe(&self, available: &[i32], rows: &[ProcessRow]) -> bool { // Function to run the safety check on a copy, nothing is locked
+        let mut work = available.to_vec(); // resources available as processes finish
+        let mut done: Vec<bool> = rows.iter().map(|row| row.finished).collect();
+        while let Some(i) = (0..rows.len()).find(|&i| !done[i] && rows[i].need.iter().zip(&work).all(|(n, w)| n <= w)) {
+            for j in 0..work.len() {
+                work[j] += self.consumed(j, rows[i].allocated[j]);
+            }
+            done[i] = true;
+        }
+        done.iter().all(|&d| d)
+    }
+
+    pub fn safe_check(&self) -> bool { // Function to see if the system is in a safe state
+        let (available, rows, _) = self.snapshot();
+        self.is_safe(&available, &rows)
+    }
+
+    pub fn request_resource(&self, process_id: usize, request: &[i32]) -> bool { // Function to grant a request if it keeps the system safe
+        if process_id >= self.rows.len() || request.len() != self.kinds.len() || request.iter().any(|&r| r < 0) {
+            return false; // a malformed request is denied without locking anything
+        }
+        loop {
+            let (available, mut rows, version) = self.snapshot();
+            let row = &mut rows[process_id];
+            let fits = !row.finished && request.iter().zip(&row.need).all(|(r, n)| r <= n) && request.iter().zip(&available).all(|(r, a)| r <= a);
+            let mut after = available;
+            for j in 0..request.len() { // tentative grant on the copy
+                after[j] -= self.consumed(j, request[j]);
+                row.allocated[j] += request[j];
+                row.need[j] -= request[j];
+            }
+            let safe = fits && self.is_safe(&after, &rows);
+            let mut available = self.available.lock().unwrap(); // held only to commit
+            if self.version.load(Ordering::SeqCst) != version {
+                continue; // a grant or release got in between, decide again on a fresh copy
+            }
+            if !safe {
+                return false;
+            }
+            self.version.fetch_add(1, Ordering::SeqCst);
+            *self.rows[process_id].lock().unwrap() = rows.swap_remove(process_id);
+            *available = after;
+            return true;
+        }
+    }
+
+    pub fn release_resource(&self, process_id: usize) { // Function to return everything a process holds and mark it completed
+        if process_id >= self.rows.len() {
+            return;
+        }
+        let mut available = self.available.lock().unwrap(); // only available and the own row are locked
+        self.version.fetch_add(1, Ordering::SeqCst);
+        let mut row = self.rows[process_id].lock().unwrap();
+        for j in 0..available.len() {
+            available[j] += self.consumed(j, row.allocated[j]);
+            row.allocated[j] = 0;
+            row.need[j] = row.max[j]; // like release_resource, a new run starts from the max claim
+        }
+        row.finished = true;
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::state::{is_consistent, release_resource, request_resource, safe_check};
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    fn same_matrices(split: &SplitState, state: &SystemState) -> bool { // Function to compare everything a split state keeps
+        let joined = split.to_state();
+        joined.available == state.available && joined.allocated == state.allocated && joined.need == state.need && (0..state.process_count()).all(|i| joined.is_finished(i) == state.is_finished(i))
+    }
+
+    #[test]
+    fn textbook_requests_are_decided_like_the_single_lock_state() {
+        let split = SplitState::from_state(&classic());
+        assert!(split.safe_check());
+        assert!(split.request_resource(1, &[1, 0, 2])); // the textbook grant
+        assert_eq!(split.available(), vec![2, 3, 0]);
+        assert!(!split.request_resource(4, &[3, 3, 0])); // more than is available
+        assert!(!split.request_resource(0, &[0, 2, 0])); // fits, but leaves the state unsafe
+        assert_eq!(split.available(), vec![2, 3, 0]); // denials are rolled back
+        assert!(!split.request_resource(1, &[1, 0, 0, 0]) && !split.request_resource(7, &[0, 0, 0]) && !split.request_resource(1, &[-1, 0, 0])); // malformed
+        split.release_resource(1);
+        assert!(split.row(1).finished && split.available() == vec![5, 3, 2]);
+        assert!(!split.request_resource(1, &[0, 0, 0])); // finished processes can't request again
+        let mut state = classic();
+        assert!(request_resource(&mut state, 1, &[1, 0, 2]));
+        release_resource(&mut state, 1);
+        assert!(same_matrices(&split, &state));
+    }
+
+    #[test]
+    fn unsafe_state_is_reported_unsafe() {
+        let state = SystemState::from_matrices(vec![0], vec![vec![5], vec![2]], vec![vec![1], vec![1]]).unwrap();
+        assert!(!SplitState::from_state(&state).safe_check());
+        assert!(SplitState::from_state(&SystemState::default()).safe_check()); // no processes, nothing can get stuck
+    }
+
+    #[test]
+    fn concurrent_requests_and_releases_keep_the_state_safe_and_conserved() {
+        let initial = classic();
+        let split = Arc::new(SplitState::from_state(&initial));
+        let threads: Vec<_> = (0..initial.process_count())
+            .map(|pid| {
+                let split = Arc::clone(&split);
+                thread::spawn(move || {
+                    let mut random = StdRng::seed_from_u64(pid as u64);
+                    for _ in 0..2000 {
+                        let row = split.row(pid);
+                        if row.finished {
+                            return;
+                        }
+                        if row.need.iter().all(|&n| n == 0) {
+                            split.release_resource(pid);
+                            continue;
+                        }
+                        let request: Vec<i32> = row.need.iter().map(|&n| random.gen_range(0..=n)).collect();
+                        split.request_resource(pid, &request);
+                        assert!(split.safe_check(), "a grant left the state unsafe");
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let state = split.to_state();
+        assert!(is_consistent(&state) && safe_check(&state));
+        assert_eq!(state.total_capacity(), initial.total_capacity()); // nothing was lost or made up
+        assert!(split.all_finished()); // a safe state always lets someone finish, so every process got there
+    }
+
+    fn small_state() -> impl Strategy<Value = SystemState> { // Strategy for states with up to 5 processes and 3 resources
+        (1..=5usize, 1..=3usize).prop_flat_map(|(processes, resources)| {
+            (
+                proptest::collection::vec(0..=4i32, resources),
+                proptest::collection::vec(proptest::collection::vec((0..=4i32, 0..=4i32), resources), processes),
+            )
+        })
+        .prop_map(|(available, rows)| {
+            let max = rows.iter().map(|row| row.iter().map(|&(a, b)| a.max(b)).collect()).collect();
+            let allocated = rows.iter().map(|row| row.iter().map(|&(a, b)| a.min(b)).collect()).collect();
+            SystemState::from_matrices(available, max, allocated).unwrap()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn split_state_agrees_with_system_state((state, steps) in small_state().prop_flat_map(|state| {
+            let (processes, resources) = (state.process_count(), state.resource_count());
+            (Just(state), proptest::collection::vec((0..processes, proptest::collection::vec(0..=3i32, resources), proptest::bool::weighted(0.2)), 1..20))
+        })) {
+            let split = SplitState::from_state(&state);
+            let mut state = state;
+            prop_assert_eq!(split.safe_check(), safe_check(&state));
+            for (pid, request, release) in steps {
+                if release {
+                    split.release_resource(pid);
+                    release_resource(&mut state, pid);
+                } else {
+                    prop_assert_eq!(split.request_resource(pid, &request), request_resource(&mut state, pid, &request));
+                }
+                prop_assert!(same_matrices(&split, &state));
+                prop_assert_eq!(split.safe_check(), safe_check(&state));
+            }
+        }
+    }
+}
@@ -0,0 +1,1549 @@
+// Authors: Victor Vu
+// File: state.rs
+// Description: System state and the core Banker's Algorithm routines
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::cache::{safe_check_cached, SafeCache};
+use crate::claim_edge::claim_graph_acyclic;
+use crate::error::{BankersError, BatchError, MergeError};
+use crate::input::StateJson;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)] // Derive traits for printing, copy and (de)serialization of enum
+pub enum ProcessState { // Enum of the lifecycle states of a process
+    Running, // still requesting resources
+    Blocked, // last request was denied
+    Completed, // got everything it needed and released it
+    Crashed, // died before completing, its resources were reclaimed
+}
+
+impl ProcessState {
+    pub fn symbol(&self) -> char { // single character shown in the process table
+        match self {
+            ProcessState::Running => 'R',
+            ProcessState::Blocked => 'B',
+            ProcessState::Completed => 'C',
+            ProcessState::Crashed => 'X',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)] // Derive traits for printing, copy, hashing and (de)serialization of enum
+pub enum ResourceKind { // Enum of how allocating a resource affects its availability
+    #[default]
+    Exclusive, // allocated units are taken out of available until released
+    Shareable, // allocating doesn't use anything up, available is the amount any process can hold at once
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)] // Derive traits for printing, copy, an empty system and (de)serialization of struct
+#[serde(try_from = "StateJson", into = "StateJson")] // go through the JSON schema so need is always validated
+pub struct SystemState { // Struct to hold the state of the system (structs must use camel case)
+    pub(crate) available: Vec<i32>, // available resources
+    pub(crate) max: Vec<Vec<i32>>, // max resources each process can request
+    pub(crate) allocated: Vec<Vec<i32>>, // resources currently allocated to each process
+    pub(crate) need: Vec<Vec<i32>>, // resources each process still needs
+    pub(crate) process_states: Vec<ProcessState>, // lifecycle state of each process
+    pub(crate) request_log: Vec<Vec<(Vec<i32>, bool)>>, // every request of each process and whether it was granted (not saved to JSON)
+    pub(crate) request_matrix: Vec<Vec<i32>>, // outstanding denied request of each process, zero once granted (not saved to JSON)
+    pub(crate) prerequisites: Vec<Vec<usize>>, // processes that must complete before each process is granted anything (not saved to JSON)
+    pub(crate) priorities: Vec<u32>, // priority of each process, higher is more important
+    pub(crate) resource_kinds: Vec<ResourceKind>, // whether each resource is exclusive or shareable
+    pub(crate) step_budgets: Vec<Option<usize>>, // requests each process may make before it is terminated, None runs until it finishes
+    pub(crate) weights: Vec<u32>, // share of the turns each process gets with weighted scheduling
+    pub(crate) capacity: Vec<i32>, // total units of each resource, only changes when units are added or removed
+}
+
+impl SystemState {
+    pub fn from_matrices(available: Vec<i32>, max: Vec<Vec<i32>>, allocated: Vec<Vec<i32>>) -> Result<SystemState, BankersError> { // Build a state with every process running, need is max - allocated
+        let resource_amount = available.len(); // number of resources comes from available
+        let process_amount = max.len(); // number of processes comes from max
+        if allocated.len() != process_amount { // Check allocated has a row per process
+            return Err(BankersError::DimensionMismatch { context: "allocated".to_string(), expected: process_amount, found: allocated.len() });
+        }
+        for i in 0..process_amount { // Check every row has a column per resource
+            if max[i].len() != resource_amount {
+                return Err(BankersError::DimensionMismatch { context: format!("max[{}]", i), expected: resource_amount, found: max[i].len() });
+            }
+            if allocated[i].len() != resource_amount {
+                return Err(BankersError::DimensionMismatch { context: format!("allocated[{}]", i), expected: resource_amount, found: allocated[i].len() });
+            }
+            if let Some(j) = (0..resource_amount).find(|&j| allocated[i][j] > max[i][j]) { // Check no process holds more than its max
+                return Err(BankersError::AllocatedExceedsMax { process: i, resource: j, allocated: allocated[i][j], max: max[i][j] });
+            }
+        }
+
+        let need = max.iter().zip(&allocated).map(|(m, a)| m.iter().zip(a).map(|(m, a)| m - a).collect()).collect(); // calculate needed resources
+        let mut state = SystemState { available, max, allocated, need,
+            process_states: vec![ProcessState::Running; process_amount], // mark all processes as running
+            request_log: vec![Vec::new(); process_amount], // no requests yet
+            request_matrix: vec![vec![0; resource_amount]; process_amount], // nothing outstanding
+            prerequisites: vec![Vec::new(); process_amount], // no dependencies
+            priorities: vec![0; process_amount], // every process is equally important
+            resource_kinds: vec![ResourceKind::Exclusive; resource_amount], // resources are exclusive unless marked shareable
+            step_budgets: vec![None; process_amount], // no process is cut short
+            weights: vec![1; process_amount], // equal turns
+            capacity: Vec::new(), // counted below
+        };
+        state.recount_capacity();
+        if !is_consistent(&state) { // catches the remaining negative amounts
+            return Err(BankersError::Parse("state has negative amounts".to_string()));
+        }
+        Ok(state)
+    }
+
+    pub fn available(&self) -> &[i32] { // available resources
+        &self.available
+    }
+
+    pub fn max(&self, process_id: usize) -> &[i32] { // max resources the process can request
+        &self.max[process_id]
+    }
+
+    pub fn allocated(&self, process_id: usize) -> &[i32] { // resources currently allocated to the process
+        &self.allocated[process_id]
+    }
+
+    pub fn need(&self, process_id: usize) -> &[i32] { // resources the process still needs
+        &self.need[process_id]
+    }
+
+    pub fn process_state(&self, process_id: usize) -> ProcessState { // lifecycle state of the process
+        self.process_states[process_id]
+    }
+
+    pub fn resource_kind(&self, resource_id: usize) -> ResourceKind { // whether the resource is exclusive or shareable
+        self.resource_kinds[resource_id]
+    }
+
+    pub fn set_resource_kind(&mut self, resource_id: usize, kind: ResourceKind) -> Result<(), BankersError> { // Function to mark a resource exclusive or shareable, only while none of it is allocated
+        if let Some(i) = (0..self.process_count()).find(|&i| self.allocated[i][resource_id] > 0) {
+            return Err(BankersError::Config(format!("R{} can't change kind while P{} holds some of it", resource_id, i)));
+        }
+        self.resource_kinds[resource_id] = kind;
+        Ok(())
+    }
+
+    pub(crate) fn consumed(&self, resource_id: usize, amount: i32) -> i32 { // units an allocation of amount takes out of available
+        match self.resource_kinds[resource_id] {
+            ResourceKind::Exclusive => amount,
+            ResourceKind::Shareable => 0,
+        }
+    }
+
+    pub(crate) fn held(&self, process_id: usize, resource_id: usize) -> i32 { // units available gets back when the process releases everything
+        self.consumed(resource_id, self.allocated[process_id][resource_id])
+    }
+
+    pub fn priority(&self, process_id: usize) -> u32 { // priority of the process, higher is more important
+        self.priorities[process_id]
+    }
+
+    pub fn set_priority(&mut self, process_id: usize, priority: u32) { // Function to change the priority of a process
+        self.priorities[process_id] = priority;
+    }
+
+    pub fn weight(&self, process_id: usize) -> u32 { // share of the turns the process gets with weighted scheduling
+        self.weights[process_id]
+    }
+
+    pub fn set_weight(&mut self, process_id: usize, weight: u32) { // Function to change how many turns a process gets, 0 only runs it when nothing else is waiting
+        self.weights[process_id] = weight;
+    }
+
+    pub fn step_budget(&self, process_id: usize) -> Option<usize> { // requests the process may make in total, None if unlimited
+        self.step_budgets[process_id]
+    }
+
+    pub fn set_step_budget(&mut self, process_id: usize, budget: Option<usize>) { // Function to limit how many requests a process may make
+        self.step_budgets[process_id] = budget;
+    }
+
+    pub fn budget_exhausted(&self, process_id: usize) -> bool { // true once the process made as many requests as its budget allows
+        self.step_budgets[process_id].is_some_and(|budget| self.request_log[process_id].len() >= budget)
+    }
+
+    pub fn immediately_runnable(&self) -> Vec<usize> { // unfinished processes whose whole remaining need fits in available, the first step of the safety check
+        (0..self.process_count())
+            .filter(|&i| !self.is_finished(i) && self.need[i].iter().zip(&self.available).all(|(n, a)| n <= a))
+            .collect()
+    }
+
+    pub fn blocked(&self) -> Vec<usize> { // unfinished processes that can't finish from available alone, unlike blocked_processes this ignores the last request
+        let runnable = self.immediately_runnable();
+        (0..self.process_count()).filter(|&i| !self.is_finished(i) && !runnable.contains(&i)).collect()
+    }
+
+    pub fn outstanding_request(&self, process_id: usize) -> &[i32] { // denied request the process is waiting on, zero if none
+        &self.request_matrix[process_id]
+    }
+
+    pub fn is_complete(&self, process_id: usize) -> bool { // the process got everything it needed and released it
+        self.process_states[process_id] == ProcessState::Completed
+    }
+
+    pub fn resource_count(&self) -> usize { // number of resource types
+        self.available.len()
+    }
+
+    pub fn process_count(&self) -> usize { // number of processes
+        self.max.len()
+    }
+
+    pub fn is_finished(&self, process_id: usize) -> bool { // completed or crashed, the process won't request anything again
+        matches!(self.process_states[process_id], ProcessState::Completed | ProcessState::Crashed)
+    }
+
+    fn processes_in(&self, wanted: ProcessState) -> impl Iterator<Item = usize> + '_ { // indices of processes in the given state
+        (0..self.process_count()).filter(move |&i| self.process_states[i] == wanted)
+    }
+
+    pub fn running_processes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.processes_in(ProcessState::Running)
+    }
+
+    pub fn blocked_processes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.processes_in(ProcessState::Blocked)
+    }
+
+    pub fn completed_processes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.processes_in(ProcessState::Completed)
+    }
+
+    pub fn crashed_processes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.processes_in(ProcessState::Crashed)
+    }
+
+    pub fn total_capacity(&self) -> Vec<i32> { // total units of each resource, available plus allocated
+        self.capacity.clone()
+    }
+
+    pub(crate) fn recount_capacity(&mut self) { // Function to count the total units again after available or the allocations were set directly
+        self.capacity = (0..self.resource_count()).map(|j| self.available[j] + (0..self.process_count()).map(|i| self.held(i, j)).sum::<i32>()).collect();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)] // Derive traits for printing and copy of struct
+pub struct ProcessView<'a> { // Struct bundling the rows of one process
+    pub pid: usize, // process index
+    pub max: &'a [i32], // max resources the process can request
+    pub allocated: &'a [i32], // resources currently allocated to the process
+    pub need: &'a [i32], // resources the process still needs
+    pub state: ProcessState, // lifecycle state of the process
+    pub completed: bool, // the process completed
+}
+
+pub fn processes(state: &SystemState) -> impl Iterator<Item = ProcessView<'_>> { // Function to walk the processes without zipping the matrices by hand
+    (0..state.process_count()).map(move |i| ProcessView {
+        pid: i,
+        max: &state.max[i],
+        allocated: &state.allocated[i],
+        need: &state.need[i],
+        state: state.process_states[i],
+        completed: state.process_states[i] == ProcessState::Completed,
+    })
+}
+
+impl fmt::Display for SystemState { // Print the state as the process table
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Now available: {:?}", self.available)?;
+        writeln!(f, "Process Maximum | Allocation | Need (* can finish now)")?;
+        writeln!(f, "--------------------------------------------")?;
+
+        let runnable = self.immediately_runnable();
+        for process in processes(self) { // print each process
+            match process.state {
+                ProcessState::Completed => writeln!(f, "P{} --- completed ---", process.pid)?, // print completed if process is done
+                ProcessState::Crashed => writeln!(f, "P{} --- crashed ---", process.pid)?,
+                state => {
+                    let marker = if runnable.contains(&process.pid) { " *" } else { "" }; // its whole remaining need fits in available
+                    writeln!(f, "P{} {} {:?} | {:?} | {:?}{}", process.pid, state.symbol(), process.max, process.allocated, process.need, marker)? // print the process state
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SystemState {
+    pub fn compact(&self) -> String { // Function to format the state on one line like avail=[3,1,2] P0 n=[0,1,0] P1 done, for grep and side by side comparison
+        let list = |values: &[i32]| format!("[{}]", values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","));
+        let mut line = format!("avail={}", list(&self.available));
+        for process in processes(self) {
+            match process.state {
+                ProcessState::Completed => line += &format!(" P{} done", process.pid),
+                ProcessState::Crashed => line += &format!(" P{} crashed", process.pid),
+                _ => line += &format!(" P{} n={}", process.pid, list(process.need)),
+            }
+        }
+        line
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)] // Derive traits for printing, copy and comparison of enum
+pub enum SequencePolicy { // Enum of the ways to choose between processes that can finish next
+    LowestIndexFirst, // scan in index order like find_safe_sequence
+    HighestPriorityFirst, // the highest priority process that can finish, ties go to the lower index
+    ShortestNeedFirst, // the process with the smallest total need, ties go to the lower index
+    PriorityInheritance, // like HighestPriorityFirst, but a process holding what a waiting process lacks runs with the waiter's priority
+}
+
+pub fn build_priority_inversion_scenario(resources: usize) -> SystemState { // Function to build the classic priority inversion: low priority P0 holds R0, high priority P2 waits for it and medium priority P1 needs none of it
+    let resources = resources.max(1); // R0 is the contended resource
+    let mut available = vec![1; resources];
+    available[0] = 0; // P0 holds the only unit of R0
+    let mut low = vec![0; resources];
+    low[0] = 1;
+    let medium: Vec<i32> = (0..resources).map(|j| if j == 0 { 0 } else { 1 }).collect(); // everything but R0
+    let mut state = SystemState::from_matrices(available, vec![low.clone(), medium, low.clone()], vec![low, vec![0; resources], vec![0; resources]])
+        .expect("the scenario matrices have matching sizes");
+    state.request_matrix[2][0] = 1; // P2 is waiting for R0
+    for (process_id, priority) in [1, 5, 10].into_iter().enumerate() {
+        state.set_priority(process_id, priority);
+    }
+    state
+}
+
+impl SystemState {
+    pub fn find_safe_sequence_by(&self, policy: SequencePolicy) -> Option<Vec<usize>> { // Function to find a safe sequence, choosing each next process by policy
+        if policy == SequencePolicy::LowestIndexFirst {
+            return find_safe_sequence(self);
+        }
+        let mut work = self.available.clone(); // resources available as processes finish
+        let mut done: Vec<bool> = (0..self.process_count()).map(|i| self.is_finished(i)).collect();
+        let mut sequence = Vec::new();
+        while done.iter().any(|&d| !d) {
+            let candidates = (0..self.process_count()).filter(|&i| !done[i] && self.need[i].iter().zip(&work).all(|(n, w)| n <= w));
+            let next = match policy { // min_by_key keeps the first of equal keys, so ties go to the lower index
+                SequencePolicy::HighestPriorityFirst => candidates.min_by_key(|&i| std::cmp::Reverse(self.priorities[i])),
+                SequencePolicy::PriorityInheritance => candidates.min_by_key(|&i| { // the holder inherits the highest priority among the processes it blocks
+                    let inherited = (0..self.process_count())
+                        .filter(|&w| !done[w] && w != i && (0..work.len()).any(|j| self.need[w][j] > work[j] && self.held(i, j) > 0))
+                        .map(|w| self.priorities[w]);
+                    std::cmp::Reverse(inherited.fold(self.priorities[i], u32::max))
+                }),
+                _ => candidates.min_by_key(|&i| self.need[i].iter().sum::<i32>()),
+            }?; // nothing can finish, the state is unsafe
+            for j in 0..work.len() {
+                work[j] += self.held(next, j);
+            }
+            done[next] = true;
+            sequence.push(next);
+        }
+        Some(sequence)
+    }
+}
+
+pub fn find_safe_sequence(state: &SystemState) -> Option<Vec<usize>> { // Function to find an order in which every process can finish
+    let mut cloned_resource = state.available.clone(); // clone the available resources
+    let mut done_process: Vec<bool> = (0..state.max.len()).map(|i| state.is_finished(i)).collect(); // completed and crashed processes are not part of the sequence
+    let mut sequence = Vec::new(); // order in which processes finish
+
+    for _ in 0..state.max.len() { // Loop through the processes
+        let mut found = false; // start set found to false
+        for i in 0..state.max.len() {
+            if !done_process[i] && state.need[i].iter().zip(&cloned_resource).all(|(n, w)| n <= w) { // check if process is not done & need is less than resources
+                for j in 0..cloned_resource.len() {
+                    cloned_resource[j] += state.held(i, j); // add allocated resources to cloned resources
+                }
+                done_process[i] = true; // mark process as done
+                sequence.push(i); // record when the process finishes
+                found = true;
+            }
+        }
+        if !found { // if no process is found, break the loop
+            break;
+        }
+    }
+    if done_process.iter().all(|&f| f) { // safe if all processes are done
+        Some(sequence)
+    } else {
+        None
+    }
+}
+
+fn is_safe_with(state: &SystemState, process_id: usize, delta: &[i32]) -> bool { // Function to run the safety check as if process_id had been granted delta
+    let mut work: Vec<i32> = (0..state.resource_count()).map(|j| state.available[j] - state.consumed(j, delta[j])).collect(); // available after the hypothetical grant
+    let mut done_process: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i)).collect();
+    let need = |i: usize, j: usize| if i == process_id { state.need[i][j] - delta[j] } else { state.need[i][j] }; // overlay the delta instead of cloning
+    let allocated = |i: usize, j: usize| if i == process_id { state.allocated[i][j] + delta[j] } else { state.allocated[i][j] };
+
+    loop {
+        let mut found = false;
+        for i in 0..state.process_count() {
+            if !done_process[i] && (0..work.len()).all(|j| need(i, j) <= work[j]) {
+                for j in 0..work.len() {
+                    work[j] += state.consumed(j, allocated(i, j));
+                }
+                done_process[i] = true;
+                found = true;
+            }
+        }
+        if !found {
+            break;
+        }
+    }
+    done_process.iter().all(|&f| f)
+}
+
+impl SystemState {
+    pub fn validate_request(&self, process_id: usize, request: &[i32]) -> Result<(), BankersError> { // Function to check a request against need and available
+        if process_id >= self.process_count() {
+            return Err(BankersError::InvalidProcess(process_id));
+        }
+        if self.is_finished(process_id) { // request_resource refuses these too
+            return Err(BankersError::AlreadyFinished(process_id));
+        }
+        if request.len() != self.resource_count() {
+            return Err(BankersError::DimensionMismatch { context: "request".to_string(), expected: self.resource_count(), found: request.len() });
+        }
+        for j in 0..request.len() {
+            if request[j] < 0 {
+                return Err(BankersError::NegativeAmount { resource: j, amount: request[j] });
+            }
+            if request[j] > self.need[process_id][j] {
+                return Err(BankersError::ExceedsNeed { process: process_id, resource: j, requested: request[j], need: self.need[process_id][j] });
+            }
+            if request[j] > self.available[j] {
+                return Err(BankersError::ExceedsAvailable { resource: j, requested: request[j], available: self.available[j] });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_request_safe(&self, process_id: usize, request: &[i32]) -> Result<bool, BankersError> { // Function to check if granting a request keeps the system safe, without changing the state
+        self.validate_request(process_id, request)?;
+        Ok(is_safe_with(self, process_id, request))
+    }
+
+    pub fn release_partial(&mut self, process_id: usize, amounts: &[i32]) -> Result<(), BankersError> { // Function to give back part of an allocation, the process keeps running
+        release_partial(self, process_id, amounts)
+    }
+
+    pub fn add_process(&mut self, max: Vec<i32>, allocated: Vec<i32>) -> Result<usize, BankersError> { // Function to admit a new process, its initial allocation comes from available
+        let process_id = self.process_count(); // the new process goes last
+        if max.len() != self.resource_count() { // Check the rows have a value per resource
+            return Err(BankersError::DimensionMismatch { context: format!("P{} max", process_id), expected: self.resource_count(), found: max.len() });
+        }
+        if allocated.len() != self.resource_count() {
+            return Err(BankersError::DimensionMismatch { context: format!("P{} allocated", process_id), expected: self.resource_count(), found: allocated.len() });
+        }
+        for j in 0..max.len() {
+            if allocated[j] < 0 {
+                return Err(BankersError::NegativeAmount { resource: j, amount: allocated[j] });
+            }
+            if allocated[j] > max[j] {
+                return Err(BankersError::AllocatedExceedsMax { process: process_id, resource: j, allocated: allocated[j], max: max[j] });
+            }
+            if allocated[j] > self.available[j] {
+                return Err(BankersError::ExceedsAvailable { resource: j, requested: allocated[j], available: self.available[j] });
+            }
+        }
+
+        let mut admitted = self.clone(); // only keep the new process if the state stays safe
+        for j in 0..max.len() {
+            admitted.available[j] -= self.consumed(j, allocated[j]);
+        }
+        admitted.need.push(max.iter().zip(&allocated).map(|(m, a)| m - a).collect());
+        admitted.max.push(max);
+        admitted.allocated.push(allocated);
+        admitted.process_states.push(ProcessState::Running);
+        admitted.request_log.push(Vec::new());
+        admitted.request_matrix.push(vec![0; self.resource_count()]);
+        admitted.prerequisites.push(Vec::new());
+        admitted.priorities.push(0);
+        admitted.step_budgets.push(None);
+        admitted.weights.push(1);
+        if !safe_check(&admitted) {
+            return Err(BankersError::Unsafe(format!("admitting P{} would leave no safe sequence", process_id)));
+        }
+        *self = admitted;
+        Ok(process_id)
+    }
+
+    pub fn update_max(&mut self, process_id: usize, new_max: &[i32]) -> Result<(), BankersError> { // Function to revise the max claim of a process, need is recomputed
+        if process_id >= self.process_count() {
+            return Err(BankersError::InvalidProcess(process_id));
+        }
+        if new_max.len() != self.resource_count() { // Check the claim has a value per resource
+            return Err(BankersError::DimensionMismatch { context: format!("P{} max", process_id), expected: self.resource_count(), found: new_max.len() });
+        }
+        if self.is_finished(process_id) {
+            return Err(BankersError::AlreadyFinished(process_id));
+        }
+        if let Some(j) = (0..new_max.len()).find(|&j| new_max[j] < self.allocated[process_id][j]) { // a decrease can't go below what is held
+            return Err(BankersError::AllocatedExceedsMax { process: process_id, resource: j, allocated: self.allocated[process_id][j], max: new_max[j] });
+        }
+        let mut revised = self.clone(); // an increase is only kept if the state stays safe
+        revised.max[process_id] = new_max.to_vec();
+        revised.need[process_id] = new_max.iter().zip(&self.allocated[process_id]).map(|(m, a)| m - a).collect();
+        if new_max.iter().zip(&self.max[process_id]).any(|(new, old)| new > old) && !safe_check(&revised) {
+            return Err(BankersError::Unsafe(format!("raising the max of P{} to {:?} would leave no safe sequence", process_id, new_max)));
+        }
+        *self = revised;
+        Ok(())
+    }
+
+    pub fn terminate(&mut self, process_id: usize) -> Result<Vec<i32>, BankersError> { // Function to kill a process, returns what was reclaimed from it
+        if process_id >= self.process_count() {
+            return Err(BankersError::InvalidProcess(process_id));
+        }
+        if self.is_finished(process_id) { // terminating twice is a mistake, not a no-op
+            return Err(BankersError::AlreadyFinished(process_id));
+        }
+        let reclaimed = self.allocated[process_id].clone();
+        reclaim(self, process_id); // need goes back to max so the rows stay consistent
+        self.process_states[process_id] = ProcessState::Completed;
+        Ok(reclaimed)
+    }
+
+    pub fn add_resources(&mut self, deltas: &[i32]) -> Result<(), BankersError> { // Function to hot plug extra units of the existing resources
+        if deltas.len() != self.resource_count() { // Check the deltas have a value per resource
+            return Err(BankersError::DimensionMismatch { context: "added resources".to_string(), expected: self.resource_count(), found: deltas.len() });
+        }
+        if let Some(j) = deltas.iter().position(|&d| d < 0) { // Check nothing is taken away
+            return Err(BankersError::NegativeAmount { resource: j, amount: deltas[j] });
+        }
+        for j in 0..deltas.len() {
+            self.available[j] += deltas[j]; // the totals grow with available, nothing new is allocated
+            self.capacity[j] += deltas[j];
+        }
+        Ok(())
+    }
+
+    pub fn remove_resources(&mut self, deltas: &[i32]) -> Result<(), BankersError> { // Function to take units out of available, only if the system stays safe
+        if deltas.len() != self.resource_count() { // Check the deltas have a value per resource
+            return Err(BankersError::DimensionMismatch { context: "removed resources".to_string(), expected: self.resource_count(), found: deltas.len() });
+        }
+        for j in 0..deltas.len() { // Check everything before changing anything
+            if deltas[j] < 0 {
+                return Err(BankersError::NegativeAmount { resource: j, amount: deltas[j] });
+            }
+            if deltas[j] > self.available[j] { // allocations are never taken away
+                return Err(BankersError::ExceedsAvailable { resource: j, requested: deltas[j], available: self.available[j] });
+            }
+        }
+        for j in 0..deltas.len() {
+            self.available[j] -= deltas[j];
+        }
+        if !safe_check(self) {
+            for j in 0..deltas.len() {
+                self.available[j] += deltas[j]; // put the units back
+            }
+            return Err(BankersError::Unsafe(format!("removing {:?} would leave no safe sequence", deltas)));
+        }
+        for j in 0..deltas.len() {
+            self.capacity[j] -= deltas[j];
+        }
+        Ok(())
+    }
+
+    pub fn add_resource_type(&mut self, total: i32, per_process_max: &[i32]) -> Result<usize, BankersError> { // Function to add a new kind of resource, returns its index
+        let resource_id = self.resource_count(); // the new resource goes last
+        if per_process_max.len() != self.process_count() { // Check there is a max per process
+            return Err(BankersError::DimensionMismatch { context: format!("R{} max", resource_id), expected: self.process_count(), found: per_process_max.len() });
+        }
+        if total < 0 {
+            return Err(BankersError::NegativeAmount { resource: resource_id, amount: total });
+        }
+        if let Some(&max) = per_process_max.iter().find(|&&m| m < 0) {
+            return Err(BankersError::NegativeAmount { resource: resource_id, amount: max });
+        }
+        self.available.push(total); // nothing of it is allocated yet
+        self.capacity.push(total);
+        self.resource_kinds.push(ResourceKind::Exclusive);
+        for i in 0..self.process_count() {
+            self.max[i].push(per_process_max[i]);
+            self.allocated[i].push(0);
+            self.need[i].push(per_process_max[i]);
+            self.request_matrix[i].push(0);
+        }
+        Ok(resource_id)
+    }
+
+    pub fn release_and_reset(&mut self, process_id: usize) { // Function to release everything but keep the process running for another cycle
+        reclaim(self, process_id); // need goes back to max
+        self.process_states[process_id] = ProcessState::Running;
+    }
+
+    pub fn request_batch(&mut self, requests: &[(usize, Vec<i32>)]) -> Result<(), BatchError> { // Function to grant several requests together or none of them
+        let mut batch = self.clone(); // work on a copy so a refused batch leaves the state untouched
+        for (index, (process_id, request)) in requests.iter().enumerate() {
+            batch.validate_request(*process_id, request) // checked against what the earlier requests left
+                .map_err(|error| BatchError::Invalid { index, process: *process_id, error })?;
+            for j in 0..request.len() {
+                batch.available[j] -= self.consumed(j, request[j]);
+                batch.allocated[*process_id][j] += request[j];
+                batch.need[*process_id][j] -= request[j];
+            }
+        }
+        if !safe_check(&batch) { // a single safety check for the whole batch
+            return Err(BatchError::Unsafe);
+        }
+        for (process_id, request) in requests {
+            batch.process_states[*process_id] = ProcessState::Running; // a granted process is running again
+            record_request(&mut batch, *process_id, request, true);
+        }
+        *self = batch;
+        Ok(())
+    }
+}
+
+pub fn safe_check(state: &SystemState) -> bool { // Function to see if the system is in a safe state
+    find_safe_sequence(state).is_some()
+}
+
+pub fn all_complete(state: &SystemState) -> bool { // Function to see if the simulation is over, every process completed or crashed
+    (0..state.process_count()).all(|i| state.is_finished(i))
+}
+
+pub fn format_sequence(sequence: &[usize]) -> String { // Function to print a safe sequence as "P1 → P3 → P0"
+    sequence.iter().map(|i| format!("P{}", i)).collect::<Vec<_>>().join(" → ")
+}
+
+fn is_valid_request(state: &SystemState, process_id: usize, request: &[i32]) -> bool { // Function to check a request names a real, unfinished process and every resource
+    process_id < state.process_count() && !state.is_finished(process_id) && request.len() == state.resource_count() && request.iter().all(|&r| r >= 0) // a completed or crashed process stays finished
+}
+
+fn waiting_on_prerequisites(state: &mut SystemState, process_id: usize) -> bool { // Function to block a process whose prerequisites haven't all completed
+    let waiting = state.prerequisites[process_id].iter().any(|&d| state.process_states[d] != ProcessState::Completed);
+    if waiting {
+        state.process_states[process_id] = ProcessState::Blocked;
+    }
+    waiting
+}
+
+pub fn set_prerequisite(state: &mut SystemState, process: usize, depends_on: usize) { // Function to make a process wait until another one completed
+    if process < state.process_count() && depends_on < state.process_count() && !state.prerequisites[process].contains(&depends_on) {
+        state.prerequisites[process].push(depends_on);
+    }
+}
+
+pub fn clear_prerequisites(state: &mut SystemState, process: usize) { // Function to let a process run without waiting for others
+    if process < state.process_count() {
+        state.prerequisites[process].clear();
+    }
+}
+
+pub fn request_resource(state: &mut SystemState, process_id: usize, request: &[i32]) -> bool { // Function to request resources
+    if !is_valid_request(state, process_id, request) { // a malformed request is denied without touching the state
+        return false;
+    }
+    let granted = !waiting_on_prerequisites(state, process_id) && apply_request(state, process_id, request, SafetyCheck::Matrix);
+    record_request(state, process_id, request, granted);
+    granted
+}
+
+pub fn request_resource_partial(state: &mut SystemState, process_id: usize, request: &[i32]) -> Option<Vec<i32>> { // Function to grant the largest safe scaled down part of a request, None if nothing could be granted
+    if !is_valid_request(state, process_id, request) {
+        return None;
+    }
+    let top = request.iter().copied().max().unwrap_or(0);
+    if top == 0 { // asking for nothing isn't a zero grant, it takes the normal path
+        return request_resource(state, process_id, request).then(|| request.to_vec());
+    }
+    let scaled = (1..=top).rev() // request * scale / top for every scale, largest first
+        .map(|scale| request.iter().map(|&r| r * scale / top).collect::<Vec<i32>>())
+        .find(|part| part.iter().any(|&r| r > 0) && state.is_request_safe(process_id, part).unwrap_or(false));
+    match scaled {
+        Some(part) if request_resource(state, process_id, &part) => Some(part), // granted through the normal path so it is logged and checked again
+        Some(_) => None, // refused anyway, e.g. waiting on a prerequisite
+        None => {
+            request_resource(state, process_id, request); // a zero grant is a denial of the whole request
+            None
+        }
+    }
+}
+
+pub fn request_resource_cached(state: &mut SystemState, process_id: usize, request: &[i32], cache: &mut SafeCache) -> bool { // Function to request resources, looking up the safety check in the cache
+    if !is_valid_request(state, process_id, request) {
+        return false;
+    }
+    let granted = !waiting_on_prerequisites(state, process_id) && apply_request(state, process_id, request, SafetyCheck::Cached(cache));
+    record_request(state, process_id, request, granted);
+    granted
+}
+
+pub fn request_resource_claim_edges(state: &mut SystemState, process_id: usize, request: &[i32]) -> bool { // Function to request resources, deciding safety with the claim edge graph
+    if !is_valid_request(state, process_id, request) {
+        return false;
+    }
+    let granted = !waiting_on_prerequisites(state, process_id) && apply_request(state, process_id, request, SafetyCheck::ClaimEdges);
+    record_request(state, process_id, request, granted);
+    granted
+}
+
+pub fn request_single(state: &mut SystemState, process_id: usize, resource_id: usize, amount: i32) -> bool { // Function to request units of a single resource
+    if resource_id >= state.resource_count() { // there is no such resource
+        return false;
+    }
+    let mut request = vec![0; state.resource_count()]; // zero for every other resource
+    request[resource_id] = amount;
+    request_resource(state, process_id, &request)
+}
+
+fn record_request(state: &mut SystemState, process_id: usize, request: &[i32], granted: bool) { // Function to log a request and track it while it is outstanding
+    state.request_log[process_id].push((request.to_vec(), granted)); // remember the request for post-mortem debugging
+    state.request_matrix[process_id] = if granted { vec![0; request.len()] } else { request.to_vec() };
+}
+
+pub fn request_batch(state: &mut SystemState, requests: &[(usize, Vec<i32>)]) -> bool { // Function to grant a batch of requests as one transaction, everything is rolled back if it fails
+    state.request_batch(requests).is_ok()
+}
+
+pub fn request_without_avoidance(state: &mut SystemState, process_id: usize, request: &[i32]) -> bool { // Function to grant any request that fits in available, without a safety check
+    if !is_valid_request(state, process_id, request) { // a malformed request is denied without touching the state
+        return false;
+    }
+    let granted = !waiting_on_prerequisites(state, process_id)
+        && request.iter().zip(&state.need[process_id]).all(|(r, n)| r <= n) // check request is within need
+        && request.iter().zip(&state.available).all(|(r, a)| r <= a); // check request is within available
+    if granted {
+        for i in 0..request.len() {
+            state.available[i] -= state.consumed(i, request[i]); // subtract request from available resources
+            state.allocated[process_id][i] += request[i]; // add request to allocated resources
+            state.need[process_id][i] -= request[i]; // subtract request from needed resources
+        }
+        state.process_states[process_id] = ProcessState::Running;
+    } else {
+        state.process_states[process_id] = ProcessState::Blocked;
+    }
+    record_request(state, process_id, request, granted);
+    granted
+}
+
+pub fn detect_deadlock(state: &SystemState) -> Vec<usize> { // Function to find the processes whose outstanding requests can never be met
+    let mut work = state.available.clone(); // resources that can be handed out
+    let mut finish: Vec<bool> = (0..state.process_count()).map(|i| state.is_finished(i) || state.allocated[i].iter().all(|&a| a == 0)).collect(); // holding nothing can't be part of a deadlock
+
+    loop {
+        let mut found = false;
+        for i in 0..state.process_count() {
+            if !finish[i] && state.request_matrix[i].iter().zip(&work).all(|(r, w)| r <= w) { // its outstanding request can be met
+                for j in 0..work.len() {
+                    work[j] += state.held(i, j); // assume it finishes and releases everything
+                }
+                finish[i] = true;
+                found = true;
+            }
+        }
+        if !found {
+            break;
+        }
+    }
+    (0..state.process_count()).filter(|&i| !finish[i]).collect() // everything left is deadlocked
+}
+
+enum SafetyCheck<'a> { // Enum of the checks apply_request can run on the tentative grant
+    Matrix, // safe_check
+    Cached(&'a mut SafeCache), // safe_check through the cache
+    ClaimEdges, // no cycle in the claim edge graph, single instance resources only
+}
+
+fn apply_request(state: &mut SystemState, process_id: usize, request: &[i32], check: SafetyCheck) -> bool { // Function to grant a request if it keeps the system safe
+    if request.iter().zip(&state.need[process_id]).any(|(r, n)| r > n) // check if request is greater than need
+        || request.iter().zip(&state.available).any(|(r, a)| r > a) // check if request is greater than available
+    {
+        state.process_states[process_id] = ProcessState::Blocked; // mark process as blocked
+        return false; // false if request exceeds need/available resources
+    }
+
+    for i in 0..request.len() { // loop through the request
+        state.available[i] -= state.consumed(i, request[i]); // subtract request from available resources
+        state.allocated[process_id][i] += request[i]; // add request to allocated resources
+        state.need[process_id][i] -= request[i]; // subtract request from needed resources
+    }
+
+    let safe = match check {
+        SafetyCheck::Matrix => safe_check(state),
+        SafetyCheck::Cached(cache) => safe_check_cached(state, cache),
+        SafetyCheck::ClaimEdges => claim_graph_acyclic(state, process_id, &vec![0; request.len()]), // the grant is already applied
+    };
+    if safe { // check if the system is in a safe state
+        state.process_states[process_id] = ProcessState::Running; // a granted process is running again
+        true
+    } else { // if not in a safe state
+        for i in 0..request.len() {
+            state.available[i] += state.consumed(i, request[i]); // add request back to available resources
+            state.allocated[process_id][i] -= request[i]; // subtract request from allocated resources
+            state.need[process_id][i] += request[i]; // add request back to needed resources
+        }
+        state.process_states[process_id] = ProcessState::Blocked; // mark process as blocked
+        false
+    }
+}
+
+fn reclaim(state: &mut SystemState, process_id: usize) { // Function to return everything a process holds to available
+    for i in 0..state.available.len() {
+        state.available[i] += state.held(process_id, i); // add allocated resources back to available resources
+        state.allocated[process_id][i] = 0; // set allocated resources to 0
+        state.need[process_id][i] = state.max[process_id][i]; // set needed resources to max resources
+    }
+}
+
+pub fn release_resource(state: &mut SystemState, process_id: usize) { // Function to release resources
+    reclaim(state, process_id);
+    state.process_states[process_id] = ProcessState::Completed; // mark process as completed
+}
+
+pub fn reactivate(state: &mut SystemState, process_id: usize) { // Function to let a completed process make a fresh run, need goes back to max
+    reclaim(state, process_id); // a process that didn't finish gives back what it holds first
+    state.request_matrix[process_id] = vec![0; state.resource_count()]; // nothing outstanding from the old run
+    state.process_states[process_id] = ProcessState::Running; // holding nothing with max at most the total keeps a safe state safe
+}
+
+pub fn release_partial(state: &mut SystemState, process: usize, amounts: &[i32]) -> Result<(), BankersError> { // Function to release only part of a process's allocation
+    if process >= state.process_count() {
+        return Err(BankersError::InvalidProcess(process));
+    }
+    if amounts.len() != state.resource_count() {
+        return Err(BankersError::DimensionMismatch { context: "released amounts".to_string(), expected: state.resource_count(), found: amounts.len() });
+    }
+    for r in 0..amounts.len() { // Check everything before changing anything
+        if amounts[r] < 0 {
+            return Err(BankersError::NegativeAmount { resource: r, amount: amounts[r] });
+        }
+        if amounts[r] > state.allocated[process][r] {
+            return Err(BankersError::ReleaseTooMuch { process, resource: r, tried: amounts[r], held: state.allocated[process][r] });
+        }
+    }
+    for r in 0..amounts.len() {
+        state.available[r] += state.consumed(r, amounts[r]); // give the units back
+        state.allocated[process][r] -= amounts[r];
+        state.need[process][r] += amounts[r]; // the process will need them again
+    }
+    Ok(())
+}
+
+pub fn simulate_crash(state: &mut SystemState, process_id: usize) { // Function to crash a process, its resources are reclaimed
+    reclaim(state, process_id);
+    state.process_states[process_id] = ProcessState::Crashed; // mark process as crashed
+}
+
+pub fn is_consistent(state: &SystemState) -> bool { // Function to check the matrices agree with each other
+    let resource_amount = state.available.len();
+    let process_amount = state.max.len();
+    if state.allocated.len() != process_amount || state.need.len() != process_amount || state.process_states.len() != process_amount {
+        return false; // every matrix needs a row per process
+    }
+    if state.available.iter().any(|&a| a < 0) {
+        return false; // available can't be negative
+    }
+    for i in 0..process_amount {
+        if state.max[i].len() != resource_amount || state.allocated[i].len() != resource_amount || state.need[i].len() != resource_amount {
+            return false; // every row needs a column per resource
+        }
+        for j in 0..resource_amount {
+            if state.allocated[i][j] < 0 || state.need[i][j] < 0 || state.need[i][j] != state.max[i][j] - state.allocated[i][j] {
+                return false; // need must be max - allocated and nothing can be negative
+            }
+        }
+    }
+    true
+}
+
+pub fn merge_states(states: &[SystemState]) -> Result<SystemState, BankersError> { // Function to combine states by appending their processes
+    let first = match states.first() {
+        Some(first) => first,
+        None => return Err(BankersError::DimensionMismatch { context: "merged states".to_string(), expected: 1, found: 0 }),
+    };
+    let mut merged = first.clone(); // start from the first state
+
+    for state in &states[1..] { // Append every other state
+        if state.resource_count() != merged.resource_count() { // Check all states have the same resources
+            return Err(BankersError::DimensionMismatch { context: "merged available".to_string(), expected: merged.resource_count(), found: state.resource_count() });
+        }
+        for j in 0..merged.resource_count() {
+            merged.available[j] = merged.available[j].min(state.available[j]); // keep the minimum available
+        }
+        append_processes(&mut merged, state);
+    }
+    merged.recount_capacity(); // the minimum available changed the totals
+    Ok(merged)
+}
+
+pub fn merge(a: &SystemState, b: &SystemState) -> Result<SystemState, MergeError> { // Function to combine two systems into one, pooling their resources
+    if a.resource_count() != b.resource_count() {
+        return Err(MergeError::ResourceCountMismatch { left: a.resource_count(), right: b.resource_count() });
+    }
+    if let Some(resource) = (0..a.resource_count()).find(|&j| a.resource_kinds[j] != b.resource_kinds[j]) {
+        return Err(MergeError::KindMismatch { resource });
+    }
+    let (total_a, total_b) = (a.total_capacity(), b.total_capacity());
+    if let Some(resource) = (0..a.resource_count()).find(|&j| total_a[j].checked_add(total_b[j]).is_none()) {
+        return Err(MergeError::Overflow { resource });
+    }
+    let mut merged = a.clone();
+    for j in 0..merged.resource_count() { // both systems bring their own units, so the totals add up
+        merged.available[j] += b.available[j];
+        merged.capacity[j] += b.capacity[j];
+    }
+    append_processes(&mut merged, b);
+    Ok(merged)
+}
+
+fn append_processes(merged: &mut SystemState, state: &SystemState) { // Function to append the process rows of state after the ones already merged
+    merged.max.extend(state.max.iter().cloned());
+    merged.allocated.extend(state.allocated.iter().cloned());
+    merged.need.extend(state.need.iter().cloned());
+    merged.process_states.extend(state.process_states.iter().cloned());
+    merged.request_log.extend(state.request_log.iter().cloned());
+    merged.request_matrix.extend(state.request_matrix.iter().cloned());
+    let offset = merged.prerequisites.len(); // appended processes move up by the processes already merged
+    merged.prerequisites.extend(state.prerequisites.iter().map(|p| p.iter().map(|&d| d + offset).collect()));
+    merged.priorities.extend(state.priorities.iter().cloned());
+    merged.step_budgets.extend(state.step_budgets.iter().cloned());
+    merged.weights.extend(state.weights.iter().cloned());
+}
+
+pub fn shrink(state: &mut SystemState) -> Vec<usize> { // Function to drop completed process rows, only call while no thread is running
+    let mut mapping = vec![usize::MAX; state.process_count()]; // completed processes map to usize::MAX
+    let mut next_id = 0;
+    for i in 0..state.process_count() {
+        if state.process_states[i] != ProcessState::Completed {
+            mapping[i] = next_id; // surviving processes keep their relative order
+            next_id += 1;
+        }
+    }
+    let old_amount = state.process_count(); // rows are replaced one matrix at a time, so keep the old count
+    let keep = |i: &usize| mapping[*i] != usize::MAX;
+    state.max = (0..old_amount).filter(keep).map(|i| state.max[i].clone()).collect(); // compact every matrix
+    state.allocated = (0..old_amount).filter(keep).map(|i| state.allocated[i].clone()).collect();
+    state.need = (0..old_amount).filter(keep).map(|i| state.need[i].clone()).collect();
+    state.request_log = (0..old_amount).filter(keep).map(|i| state.request_log[i].clone()).collect();
+    state.request_matrix = (0..old_amount).filter(keep).map(|i| state.request_matrix[i].clone()).collect();
+    state.prerequisites = (0..old_amount).filter(keep) // completed prerequisites are met, the others are renumbered
+        .map(|i| state.prerequisites[i].iter().filter(|&&d| mapping[d] != usize::MAX).map(|&d| mapping[d]).collect())
+        .collect();
+    state.priorities = (0..old_amount).filter(keep).map(|i| state.priorities[i]).collect();
+    state.step_budgets = (0..old_amount).filter(keep).map(|i| state.step_budgets[i]).collect();
+    state.weights = (0..old_amount).filter(keep).map(|i| state.weights[i]).collect();
+    state.process_states = (0..old_amount).filter(keep).map(|i| state.process_states[i]).collect();
+    mapping
+}
+
+pub fn diff(before: &SystemState, after: &SystemState) -> Vec<String> { // Function to list what changed between two states, e.g. "P0 allocated R1: 1→2"
+    let mut changes = Vec::new();
+    for j in 0..before.resource_count().min(after.resource_count()) {
+        if before.available[j] != after.available[j] {
+            changes.push(format!("available R{}: {}→{}", j, before.available[j], after.available[j]));
+        }
+    }
+    for i in 0..before.process_count().min(after.process_count()) {
+        if before.process_states[i] != after.process_states[i] {
+            changes.push(format!("P{} state: {}→{}", i, before.process_states[i].symbol(), after.process_states[i].symbol()));
+        }
+        for (name, old, new) in [("allocated", &before.allocated[i], &after.allocated[i]), ("need", &before.need[i], &after.need[i])] {
+            for j in 0..old.len().min(new.len()) {
+                if old[j] != new[j] {
+                    changes.push(format!("P{} {} R{}: {}→{}", i, name, j, old[j], new[j]));
+                }
+            }
+        }
+    }
+    changes
+}
+
+pub fn inject_resources(state: &mut SystemState, delta: Vec<i32>) -> Result<(), BankersError> { // Function to add capacity, e.g. a new server coming online
+    let was_safe = safe_check(state);
+    state.add_resources(&delta)?; // add the new units to available resources
+    if !was_safe && safe_check(state) { // report when the extra capacity makes the system safe again
+        tracing::info!(?delta, capacity = ?state.total_capacity(), "injected resources made the system safe");
+    }
+    Ok(())
+}
+
+pub fn processes_by_need(state: &SystemState) -> Vec<usize> { // Function to list incomplete processes, closest to finishing first
+    let mut processes: Vec<usize> = (0..state.process_count()).filter(|&i| !state.is_finished(i)).collect();
+    processes.sort_by_key(|&i| state.need[i].iter().sum::<i32>()); // stable sort keeps ties in index order
+    processes
+}
+
+pub fn request_log_for(state: &SystemState, process: usize) -> &[(Vec<i32>, bool)] { // Function to get the requests a process made so far
+    &state.request_log[process]
+}
+
+pub fn replay_log(initial: &SystemState, process: usize, log: &[(Vec<i32>, bool)]) -> bool { // Function to replay a process's requests, true if every outcome matches (the other processes stay as in initial)
+    let mut state = initial.clone();
+    for (request, granted) in log {
+        if request_resource(&mut state, process, request) != *granted {
+            return false; // the replay diverged from the recorded run
+        }
+        if *granted && state.need[process].iter().all(|&n| n == 0) { // release like the simulation does
+            release_resource(&mut state, process);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::input::read_input;
+
+    fn data(name: &str) -> String { // Function to get the path of a test input file
+        format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn classic() -> SystemState { // the textbook state with 5 processes and 3 resources
+        SystemState::from_matrices(
+            vec![3, 3, 2],
+            vec![vec![7, 5, 3], vec![3, 2, 2], vec![9, 0, 2], vec![2, 2, 2], vec![4, 3, 3]],
+            vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 2], vec![2, 1, 1], vec![0, 0, 2]],
+        )
+        .unwrap()
+    }
+
+    fn execute(state: &mut SystemState, order: &[usize]) { // Function to run the processes one after another, each asking for its whole need
+        for &i in order {
+            let need = state.need[i].clone();
+            assert!(request_resource(state, i, &need), "P{} can't get its need {:?}", i, need);
+            release_resource(state, i);
+        }
+        assert!(all_complete(state));
+    }
+
+    #[test]
+    fn merging_two_three_process_files_gives_six_consistent_processes() {
+        let cpu = read_input(&data("cpu.txt")).unwrap();
+        let memory = read_input(&data("memory.txt")).unwrap();
+        let merged = merge_states(&[cpu.clone(), memory.clone()]).unwrap();
+        assert_eq!(merged.process_count(), 6);
+        assert!(is_consistent(&merged));
+        assert_eq!(merged.available, vec![3, 2]); // element-wise minimum
+        assert_eq!(merged.max[3], memory.max[0]); // the second file's processes come after the first's
+        assert!(matches!(merge_states(&[cpu, SystemState::from_matrices(vec![1], vec![vec![1]], vec![vec![0]]).unwrap()]), Err(BankersError::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn injecting_two_units_of_r0_unblocks_a_denied_process() {
+        let mut state = SystemState::from_matrices(vec![1, 0], vec![vec![3, 1], vec![1, 0]], vec![vec![0, 1], vec![0, 0]]).unwrap();
+        assert!(!request_resource(&mut state, 0, &[3, 0])); // only 1 unit of R0 is free
+        assert_eq!(state.total_capacity(), vec![1, 1]);
+        inject_resources(&mut state, vec![2, 0]).unwrap();
+        assert_eq!(state.total_capacity(), vec![3, 1]); // the injection is part of the total from now on
+        assert!(request_resource(&mut state, 0, &[3, 0]));
+        assert_eq!(state.total_capacity(), vec![3, 1]); // granting moves units, the total stays
+        assert_eq!(inject_resources(&mut state, vec![1]), Err(BankersError::DimensionMismatch { context: "added resources".to_string(), expected: 2, found: 1 }));
+        assert_eq!(inject_resources(&mut state, vec![-1, 0]), Err(BankersError::NegativeAmount { resource: 0, amount: -1 }));
+    }
+
+    #[test]
+    fn safe_sequence_can_be_executed() {
+        let mut state = classic();
+        let order = find_safe_sequence(&state).unwrap();
+        assert_eq!(order.len(), 5);
+        execute(&mut state, &order);
+    }
+
+    #[test]
+    fn safe_sequence_skips_completed_processes() {
+        let mut state = classic();
+        release_resource(&mut state, 1);
+        let order = find_safe_sequence(&state).unwrap();
+        assert!(!order.contains(&1));
+        assert_eq!(order.len(), 4);
+        execute(&mut state, &order);
+    }
+
+    #[test]
+    fn unsafe_state_has_no_safe_sequence() {
+        let state = SystemState::from_matrices(vec![0], vec![vec![5], vec![2]], vec![vec![1], vec![1]]).unwrap();
+        assert_eq!(find_safe_sequence(&state), None);
+    }
+
+    #[test]
+    fn denied_request_blocks_and_granted_request_runs_again() {
+        let mut state = classic();
+        assert!(!request_resource(&mut state, 0, &[4, 0, 0])); // more than is available
+        assert_eq!(state.process_states[0], ProcessState::Blocked);
+        assert_eq!(state.blocked_processes().collect::<Vec<_>>(), vec![0]);
+        assert!(request_resource(&mut state, 0, &[0, 1, 0]));
+        assert_eq!(state.process_states[0], ProcessState::Running);
+        assert_eq!(state.running_processes().count(), 5);
+    }
+
+    #[test]
+    fn release_completes_and_crash_crashes() {
+        let mut state = classic();
+        release_resource(&mut state, 1);
+        simulate_crash(&mut state, 3);
+        assert_eq!(state.completed_processes().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(state.crashed_processes().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(state.available, vec![7, 4, 3]); // both gave back what they held
+        assert!(safe_check(&state)); // neither is waited for
+    }
+
+    #[test]
+    fn finished_process_stays_finished() {
+        let mut state = classic();
+        release_resource(&mut state, 1);
+        simulate_crash(&mut state, 3);
+        set_prerequisite(&mut state, 1, 0); // waiting on P0 must not turn the completed P1 into a blocked one
+        let before = state.clone();
+        for pid in [1, 3] {
+            assert!(!request_resource(&mut state, pid, &[1, 0, 0]));
+            assert!(!request_resource_claim_edges(&mut state, pid, &[1, 0, 0]));
+            assert!(!request_resource_cached(&mut state, pid, &[1, 0, 0], &mut SafeCache::new(4)));
+            assert_eq!(request_resource_partial(&mut state, pid, &[1, 0, 0]), None);
+        }
+        assert_eq!(state, before); // nothing was granted, logged or blocked
+        assert_eq!(state.process_states[1], ProcessState::Completed);
+        assert_eq!(state.process_states[3], ProcessState::Crashed);
+    }
+
+    #[test]
+    fn replaying_a_recorded_run_gives_the_same_outcomes() {
+        let initial = classic();
+        let mut rng = StdRng::seed_from_u64(3);
+        for pid in [1, 3] { // the processes that can finish while the others keep what they hold
+            let mut state = initial.clone();
+            while !state.is_finished(pid) { // the process runs alone, like replay_log replays it
+                let request: Vec<i32> = state.need[pid].iter().map(|&n| rng.gen_range(0..=n + 1)).collect(); // sometimes more than the need, so some are denied
+                if request_resource(&mut state, pid, &request) && state.need[pid].iter().all(|&n| n == 0) {
+                    release_resource(&mut state, pid);
+                }
+            }
+            let log = request_log_for(&state, pid).to_vec();
+            assert!(log.iter().any(|(_, granted)| *granted) && log.iter().any(|(_, granted)| !*granted));
+            assert!(replay_log(&initial, pid, &log), "P{} diverged", pid);
+            let mut flipped = log.clone();
+            flipped[0].1 = !flipped[0].1;
+            assert!(!replay_log(&initial, pid, &flipped)); // a different outcome is noticed
+        }
+    }
+
+    fn detection_state(available: Vec<i32>, allocated: Vec<Vec<i32>>, requests: Vec<Vec<i32>>) -> SystemState { // Function to build a state for the detection algorithm, max only has to cover allocated plus request
+        let max = allocated.iter().zip(&requests).map(|(a, r)| a.iter().zip(r).map(|(a, r)| a + r).collect()).collect();
+        let mut state = SystemState::from_matrices(available, max, allocated).unwrap();
+        state.request_matrix = requests;
+        state
+    }
+
+    #[test]
+    fn detection_finds_no_deadlock_in_the_textbook_example() {
+        let allocated = vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 3], vec![2, 1, 1], vec![0, 0, 2]];
+        let requests = vec![vec![0, 0, 0], vec![2, 0, 2], vec![0, 0, 0], vec![1, 0, 0], vec![0, 0, 2]];
+        assert_eq!(detect_deadlock(&detection_state(vec![0, 0, 0], allocated, requests)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn detection_finds_the_deadlocked_set_once_p2_asks_for_one_more() {
+        let allocated = vec![vec![0, 1, 0], vec![2, 0, 0], vec![3, 0, 3], vec![2, 1, 1], vec![0, 0, 2]];
+        let requests = vec![vec![0, 0, 0], vec![2, 0, 2], vec![0, 0, 1], vec![1, 0, 0], vec![0, 0, 2]];
+        assert_eq!(detect_deadlock(&detection_state(vec![0, 0, 0], allocated, requests)), vec![1, 2, 3, 4]); // P0 can finish but only frees one unit of B
+    }
+
+    #[test]
+    fn detection_ignores_processes_holding_nothing_and_finished_ones() {
+        let mut state = detection_state(vec![0, 0], vec![vec![1, 0], vec![0, 1], vec![0, 0]], vec![vec![0, 1], vec![1, 0], vec![1, 1]]);
+        assert_eq!(detect_deadlock(&state), vec![0, 1]); // P0 and P1 wait for each other, P2 holds nothing
+        simulate_crash(&mut state, 1);
+        assert_eq!(detect_deadlock(&state), Vec::<usize>::new()); // the crash broke the cycle
+    }
+
+    #[test]
+    fn mismatched_request_length_is_denied_without_touching_the_state() {
+        let mut state = classic();
+        let before = state.clone();
+        assert!(!request_resource(&mut state, 1, &[1, 0])); // too short
+        assert!(!request_resource(&mut state, 1, &[1, 0, 0, 0])); // too long
+        assert!(!request_resource(&mut state, 9, &[1, 0, 0])); // no such process
+        assert_eq!(state, before); // not even logged or blocked
+        assert!(request_resource(&mut state, 1, &[1, 0, 0]));
+    }
+
+    #[test]
+    fn request_single_matches_the_padded_request() {
+        for amount in [0, 1, 2, 3] { // granted, granted, granted, more than the need of 2
+            let (mut single, mut padded) = (classic(), classic());
+            assert_eq!(request_single(&mut single, 1, 1, amount), request_resource(&mut padded, 1, &[0, amount, 0]));
+            assert_eq!(single, padded);
+        }
+        let mut state = classic();
+        assert!(request_single(&mut state, 0, 1, 2)); // the example from the request
+        assert_eq!(state.allocated[0], vec![0, 3, 0]);
+        assert!(!request_single(&mut state, 0, 3, 1)); // there is no R3
+    }
+
+    fn small_state() -> impl Strategy<Value = SystemState> { // Strategy for states with up to 5 processes and 3 resources, some processes finished
+        (1..=5usize, 1..=3usize).prop_flat_map(|(processes, resources)| {
+            (
+                proptest::collection::vec(0..=4i32, resources),
+                proptest::collection::vec(proptest::collection::vec((0..=4i32, 0..=4i32), resources), processes),
+                proptest::collection::vec(proptest::bool::weighted(0.2), processes),
+            )
+        })
+        .prop_map(|(available, rows, finished)| {
+            let max = rows.iter().map(|row| row.iter().map(|&(a, b)| a.max(b)).collect()).collect();
+            let allocated = rows.iter().map(|row| row.iter().map(|&(a, b)| a.min(b)).collect()).collect();
+            let mut state = SystemState::from_matrices(available, max, allocated).unwrap();
+            for (i, &done) in finished.iter().enumerate() {
+                if done {
+                    release_resource(&mut state, i);
+                }
+            }
+            state
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn is_request_safe_agrees_with_mutate_check_rollback((state, pid, request) in small_state().prop_flat_map(|state| {
+            let (processes, resources) = (state.process_count(), state.resource_count());
+            (Just(state), 0..processes, proptest::collection::vec(0..=3i32, resources))
+        })) {
+            let answer = state.is_request_safe(pid, &request);
+            let mut mutated = state.clone();
+            let granted = request_resource(&mut mutated, pid, &request);
+            prop_assert_eq!(answer.unwrap_or(false), granted);
+            if !granted { // the rollback left every matrix as it was
+                prop_assert_eq!(&mutated.available, &state.available);
+                prop_assert_eq!(&mutated.allocated, &state.allocated);
+                prop_assert_eq!(&mutated.need, &state.need);
+            }
+        }
+    }
+
+    #[test]
+    fn releasing_half_an_allocation_lets_another_process_proceed() {
+        let mut state = SystemState::from_matrices(vec![1], vec![vec![5], vec![3]], vec![vec![4], vec![1]]).unwrap();
+        assert!(!request_resource(&mut state, 1, &[2])); // only 1 unit is free
+        release_partial(&mut state, 0, &[2]).unwrap(); // P0 gives back half of its 4 units
+        assert_eq!((state.allocated[0][0], state.need[0][0], state.available[0]), (2, 3, 3));
+        assert!(request_resource(&mut state, 1, &[2]));
+        assert!(!state.is_finished(0)); // P0 keeps running
+        assert_eq!(release_partial(&mut state, 0, &[3]), Err(BankersError::ReleaseTooMuch { process: 0, resource: 0, tried: 3, held: 2 }));
+    }
+
+    #[test]
+    fn shrink_drops_completed_rows() {
+        let mut state = classic();
+        for pid in [1, 3, 4] {
+            let need = state.need[pid].clone();
+            assert!(request_resource(&mut state, pid, &need));
+            release_resource(&mut state, pid);
+        }
+        let mapping = shrink(&mut state);
+        assert_eq!(mapping, vec![0, usize::MAX, 1, usize::MAX, usize::MAX]);
+        assert_eq!(state.process_count(), 2);
+        assert!(is_consistent(&state));
+        assert_eq!(state.max, vec![vec![7, 5, 3], vec![9, 0, 2]]);
+        assert_eq!(state.available, vec![7, 4, 5]);
+        assert_eq!(state.total_capacity(), vec![10, 5, 7]);
+    }
+
+    #[test]
+    fn from_matrices_computes_need_and_starts_every_process_running() {
+        let state = classic();
+        assert_eq!(state.need, vec![vec![7, 4, 3], vec![1, 2, 2], vec![6, 0, 0], vec![0, 1, 1], vec![4, 3, 1]]);
+        assert!(state.process_states.iter().all(|&s| s == ProcessState::Running));
+        assert!(is_consistent(&state));
+    }
+
+    #[test]
+    fn from_matrices_refuses_mismatched_row_counts() {
+        let result = SystemState::from_matrices(vec![1, 1], vec![vec![1, 1], vec![2, 2]], vec![vec![0, 0]]);
+        assert_eq!(result, Err(BankersError::DimensionMismatch { context: "allocated".to_string(), expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn from_matrices_refuses_a_row_with_the_wrong_column_count() {
+        let result = SystemState::from_matrices(vec![1, 1], vec![vec![1, 1], vec![2]], vec![vec![0, 0], vec![0, 0]]);
+        assert_eq!(result, Err(BankersError::DimensionMismatch { context: "max[1]".to_string(), expected: 2, found: 1 }));
+        let result = SystemState::from_matrices(vec![1, 1], vec![vec![1, 1]], vec![vec![0, 0, 0]]);
+        assert_eq!(result, Err(BankersError::DimensionMismatch { context: "allocated[0]".to_string(), expected: 2, found: 3 }));
+    }
+
+    #[test]
+    fn from_matrices_refuses_allocated_above_max() {
+        let result = SystemState::from_matrices(vec![1, 1], vec![vec![1, 1], vec![2, 2]], vec![vec![0, 0], vec![1, 3]]);
+        assert_eq!(result, Err(BankersError::AllocatedExceedsMax { process: 1, resource: 1, allocated: 3, max: 2 }));
+    }
+
+    #[test]
+    fn batch_that_is_only_safe_as_a_whole_is_granted() {
+        let mut state = SystemState::from_matrices(vec![1, 1], vec![vec![2, 0], vec![1, 2]], vec![vec![0, 0], vec![1, 0]]).unwrap();
+        state.set_resource_kind(1, ResourceKind::Shareable).unwrap(); // R1 can be held by everyone at once
+        assert!(!state.is_request_safe(0, &[1, 0]).unwrap()); // alone it leaves nobody able to finish
+        let batch = vec![(0, vec![1, 0]), (1, vec![0, 1])]; // P1's share lets it finish and free the unit of R0 P0 waits for
+        assert_eq!(state.request_batch(&batch), Ok(()));
+        assert_eq!((state.allocated[0].clone(), state.allocated[1].clone()), (vec![1, 0], vec![1, 1]));
+        assert_eq!(state.available, vec![0, 1]);
+        assert!(request_log_for(&state, 0)[0].1 && request_log_for(&state, 1)[0].1);
+    }
+
+    #[test]
+    fn batch_that_is_individually_fine_but_jointly_unsafe_is_rolled_back() {
+        let mut state = classic();
+        assert!(state.is_request_safe(0, &[0, 0, 1]).unwrap());
+        assert!(state.is_request_safe(1, &[0, 0, 1]).unwrap());
+        let before = state.clone();
+        assert_eq!(state.request_batch(&[(0, vec![0, 0, 1]), (1, vec![0, 0, 1])]), Err(BatchError::Unsafe));
+        assert_eq!(state, before); // nothing of the batch was kept
+        assert!(!request_batch(&mut state, &[(0, vec![0, 0, 1]), (1, vec![0, 0, 1])]));
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn batch_validates_cumulatively_and_names_the_first_bad_request() {
+        let mut state = classic();
+        let result = state.request_batch(&[(1, vec![1, 2, 0]), (3, vec![0, 1, 1]), (4, vec![2, 1, 0])]); // only 0 of R1 is left for P4
+        assert_eq!(result, Err(BatchError::Invalid { index: 2, process: 4, error: BankersError::ExceedsAvailable { resource: 1, requested: 1, available: 0 } }));
+        assert_eq!(state, classic());
+    }
+
+    #[test]
+    fn batch_refuses_finished_processes() {
+        let mut state = classic();
+        release_resource(&mut state, 1);
+        simulate_crash(&mut state, 3);
+        let before = state.clone();
+        for pid in [1, 3] {
+            assert_eq!(state.request_batch(&[(0, vec![0, 1, 0]), (pid, vec![1, 0, 0])]), Err(BatchError::Invalid { index: 1, process: pid, error: BankersError::AlreadyFinished(pid) }));
+            assert!(!request_batch(&mut state, &[(pid, vec![1, 0, 0])]));
+        }
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn partial_releases_conserve_units_and_keep_need_within_max() {
+        let mut state = classic();
+        let mut rng = StdRng::seed_from_u64(5);
+        let total = state.total_capacity();
+        for _ in 0..200 {
+            let pid = rng.gen_range(0..state.process_count());
+            if rng.gen_bool(0.5) { // grow the allocations again now and then
+                let request: Vec<i32> = state.need[pid].iter().map(|&n| rng.gen_range(0..=n)).collect();
+                request_resource(&mut state, pid, &request);
+            } else {
+                let amounts: Vec<i32> = state.allocated[pid].iter().map(|&a| rng.gen_range(0..=a)).collect();
+                let states = state.process_states.clone();
+                state.release_partial(pid, &amounts).unwrap();
+                assert_eq!(state.process_states, states); // a partial release completes nothing
+            }
+            let mut recounted = state.clone();
+            recounted.recount_capacity();
+            assert_eq!(recounted.total_capacity(), total); // no unit was created or lost
+            assert!(is_consistent(&state));
+            assert!((0..state.process_count()).all(|i| state.need[i].iter().zip(&state.max[i]).all(|(n, m)| n <= m)));
+        }
+        assert_eq!(state.release_partial(0, &[-1, 0, 0]), Err(BankersError::NegativeAmount { resource: 0, amount: -1 }));
+    }
+
+    #[test]
+    fn reset_after_a_cycle_restores_the_initial_process_rows() {
+        let initial = SystemState::from_matrices(vec![3, 2], vec![vec![2, 1], vec![3, 2]], vec![vec![0, 0], vec![0, 0]]).unwrap();
+        let mut state = initial.clone();
+        for cycle in 0..2 {
+            assert!(request_resource(&mut state, 0, &[1, 1]));
+            assert!(request_resource(&mut state, 0, &[1, 0]));
+            state.release_and_reset(0);
+            assert_eq!(state.max[0], initial.max[0], "cycle {}", cycle);
+            assert_eq!(state.allocated[0], initial.allocated[0]);
+            assert_eq!(state.need[0], initial.need[0]);
+            assert_eq!(state.process_states[0], ProcessState::Running); // ready for the next cycle
+            assert_eq!(state.available, initial.available);
+        }
+        assert_eq!(request_log_for(&state, 0).len(), 4); // the history of both cycles is kept
+    }
+
+    #[test]
+    fn unsafe_admission_is_rejected_and_a_safe_one_appends_a_row() {
+        let mut state = classic();
+        let before = state.clone();
+        assert_eq!(state.add_process(vec![4, 4, 4], vec![3, 3, 2]), Err(BankersError::Unsafe("admitting P5 would leave no safe sequence".to_string()))); // takes everything available
+        assert_eq!(state, before);
+        assert!(matches!(state.add_process(vec![1, 1], vec![0, 0]), Err(BankersError::DimensionMismatch { .. })));
+        assert_eq!(state.add_process(vec![1, 1, 1], vec![2, 0, 0]), Err(BankersError::AllocatedExceedsMax { process: 5, resource: 0, allocated: 2, max: 1 }));
+        assert_eq!(state.add_process(vec![5, 5, 5], vec![4, 0, 0]), Err(BankersError::ExceedsAvailable { resource: 0, requested: 4, available: 3 }));
+        assert_eq!(state, before);
+        assert_eq!(state.add_process(vec![2, 1, 1], vec![1, 0, 0]), Ok(5));
+        assert_eq!((state.process_count(), state.need[5].clone(), state.available.clone()), (6, vec![1, 1, 1], vec![2, 3, 2]));
+        assert_eq!(state.total_capacity(), before.total_capacity()); // the initial allocation comes out of available
+        assert!(is_consistent(&state) && safe_check(&state));
+    }
+
+    #[test]
+    fn default_is_an_empty_consistent_system() {
+        let state = SystemState::default();
+        assert_eq!((state.process_count(), state.resource_count()), (0, 0));
+        assert!(is_consistent(&state));
+        assert_eq!(find_safe_sequence(&state), Some(Vec::new()));
+    }
+
+    #[test]
+    fn building_up_from_default_adds_resources_and_processes() {
+        let mut state = SystemState::default();
+        assert_eq!(state.add_resource_type(3, &[]), Ok(0));
+        assert_eq!(state.add_resource_type(2, &[]), Ok(1));
+        assert_eq!(state.add_process(vec![2, 1], vec![1, 0]), Ok(0));
+        assert_eq!(state.add_process(vec![3, 2], vec![0, 1]), Ok(1));
+        let built = SystemState::from_matrices(vec![2, 1], vec![vec![2, 1], vec![3, 2]], vec![vec![1, 0], vec![0, 1]]).unwrap();
+        assert_eq!(state, built); // the same as building it in one go
+        assert!(safe_check(&state));
+    }
+
+    #[test]
+    fn terminate_returns_exactly_what_available_gains() {
+        let mut state = classic();
+        for pid in [2, 0] {
+            let before = state.available.clone();
+            let reclaimed = state.terminate(pid).unwrap();
+            assert_eq!(reclaimed, classic().allocated[pid]);
+            assert_eq!(state.available, before.iter().zip(&reclaimed).map(|(a, r)| a + r).collect::<Vec<_>>());
+            assert_eq!(state.process_states[pid], ProcessState::Completed);
+            assert!(is_consistent(&state));
+        }
+        assert_eq!(state.terminate(2), Err(BankersError::AlreadyFinished(2))); // not a silent no-op
+        assert_eq!(state.terminate(7), Err(BankersError::InvalidProcess(7)));
+    }
+
+    #[test]
+    fn adding_units_flips_an_unsafe_state_to_safe() {
+        let mut state = SystemState::from_matrices(vec![0, 1], vec![vec![2, 1], vec![2, 2]], vec![vec![1, 0], vec![1, 1]]).unwrap();
+        assert!(!safe_check(&state)); // both wait for a second unit of R0
+        assert_eq!(state.add_resources(&[1]), Err(BankersError::DimensionMismatch { context: "added resources".to_string(), expected: 2, found: 1 }));
+        assert_eq!(state.add_resources(&[0, -1]), Err(BankersError::NegativeAmount { resource: 1, amount: -1 }));
+        state.add_resources(&[1, 0]).unwrap();
+        assert!(safe_check(&state));
+        assert_eq!(state.total_capacity(), vec![3, 2]);
+    }
+
+    #[test]
+    fn adding_a_resource_type_keeps_every_width_consistent() {
+        let mut state = classic();
+        assert_eq!(state.add_resource_type(4, &[1, 2, 3, 4, 0]), Ok(3));
+        assert!(is_consistent(&state));
+        assert!(state.max.iter().chain(&state.allocated).chain(&state.need).chain(&state.request_matrix).all(|row| row.len() == 4));
+        assert_eq!((state.available[3], state.resource_kinds.len(), state.total_capacity()[3]), (4, 4, 4));
+        assert!(safe_check(&state));
+        assert!(matches!(state.add_resource_type(1, &[1]), Err(BankersError::DimensionMismatch { .. })));
+        let mut short = SystemState::from_matrices(vec![1], vec![vec![1], vec![1]], vec![vec![0], vec![0]]).unwrap();
+        short.add_resource_type(0, &[2, 0]).unwrap(); // P0 claims 2 of a resource with no units
+        assert!(!safe_check(&short));
+        short.add_resources(&[0, 2]).unwrap();
+        assert!(safe_check(&short)); // the new type flips back to safe once it has units
+    }
+
+    #[test]
+    fn removing_units_fails_three_ways_and_succeeds_once() {
+        let mut state = classic();
+        let before = state.clone();
+        assert_eq!(state.remove_resources(&[4, 0, 0]), Err(BankersError::ExceedsAvailable { resource: 0, requested: 4, available: 3 })); // too large
+        assert_eq!(state.remove_resources(&[0, 0, 2]), Err(BankersError::Unsafe("removing [0, 0, 2] would leave no safe sequence".to_string()))); // P1 and P3 need R2
+        assert!(matches!(state.remove_resources(&[1, 0]), Err(BankersError::DimensionMismatch { .. }))); // wrong width
+        assert_eq!(state, before); // every failure restored the original values
+        state.remove_resources(&[1, 0, 0]).unwrap();
+        assert_eq!(state.available, vec![2, 3, 2]);
+        assert_eq!(state.total_capacity(), vec![9, 5, 7]);
+        assert!(safe_check(&state));
+    }
+
+    #[test]
+    fn chain_a_b_c_lets_c_start_only_after_a_and_b_completed() {
+        let (a, b, c) = (0, 1, 2);
+        let mut state = SystemState::from_matrices(vec![3], vec![vec![1], vec![1], vec![1]], vec![vec![0], vec![0], vec![0]]).unwrap();
+        set_prerequisite(&mut state, b, a);
+        set_prerequisite(&mut state, c, b);
+        assert!(!request_resource(&mut state, c, &[1])); // A and B are still running
+        assert_eq!(state.process_states[c], ProcessState::Blocked);
+        assert!(request_resource(&mut state, a, &[1]));
+        release_resource(&mut state, a);
+        assert!(!request_resource(&mut state, c, &[1])); // B hasn't completed yet
+        assert!(request_resource(&mut state, b, &[1]));
+        release_resource(&mut state, b);
+        assert!(request_resource(&mut state, c, &[1]));
+        assert_eq!(state.process_states[c], ProcessState::Running);
+    }
+
+    #[test]
+    fn cleared_prerequisites_no_longer_block() {
+        let mut state = SystemState::from_matrices(vec![1], vec![vec![1], vec![1]], vec![vec![0], vec![0]]).unwrap();
+        set_prerequisite(&mut state, 1, 0);
+        set_prerequisite(&mut state, 1, 0); // added once
+        assert_eq!(state.prerequisites[1], vec![0]);
+        clear_prerequisites(&mut state, 1);
+        assert!(request_resource(&mut state, 1, &[1]));
+    }
+
+    #[test]
+    fn update_max_rejects_an_unsafe_increase_and_a_decrease_below_allocation() {
+        let mut state = classic();
+        let before = state.clone();
+        assert_eq!(state.update_max(3, &[2, 5, 2]), Err(BankersError::Unsafe("raising the max of P3 to [2, 5, 2] would leave no safe sequence".to_string()))); // nobody could free enough R1
+        assert_eq!(state.update_max(2, &[2, 0, 2]), Err(BankersError::AllocatedExceedsMax { process: 2, resource: 0, allocated: 3, max: 2 })); // P2 holds 3 of R0
+        assert_eq!(state, before);
+        state.update_max(2, &[3, 0, 2]).unwrap(); // down to what it holds
+        assert_eq!(state.need[2], vec![0, 0, 0]);
+        state.update_max(3, &[2, 3, 2]).unwrap(); // a safe increase
+        assert_eq!(state.need[3], vec![0, 2, 1]);
+        assert!(is_consistent(&state));
+    }
+
+    #[test]
+    fn default_state_is_vacuously_safe() {
+        let state = SystemState::default();
+        assert!(safe_check(&state));
+        assert!(state.available.is_empty() && state.max.is_empty() && state.process_states.is_empty());
+        assert!(all_complete(&state)); // no process is left to run
+    }
+
+    #[test]
+    fn priority_policy_picks_a_different_valid_order() {
+        let mut state = classic();
+        state.set_priority(4, 5);
+        let by_index = state.find_safe_sequence_by(SequencePolicy::LowestIndexFirst).unwrap();
+        let by_priority = state.find_safe_sequence_by(SequencePolicy::HighestPriorityFirst).unwrap();
+        assert_eq!(by_index, vec![1, 3, 4, 0, 2]);
+        assert_eq!(by_priority, vec![1, 4, 3, 0, 2]); // P4 runs as soon as it fits, ahead of P3
+        assert_eq!(state.find_safe_sequence_by(SequencePolicy::ShortestNeedFirst).unwrap(), vec![3, 1, 2, 4, 0]);
+        execute(&mut state.clone(), &by_index);
+        execute(&mut state, &by_priority);
+    }
+
+    #[test]
+    fn runnable_and_blocked_leave_out_finished_processes() {
+        let mut state = classic();
+        assert_eq!(state.immediately_runnable(), vec![1, 3]);
+        assert_eq!(state.blocked(), vec![0, 2, 4]);
+        release_resource(&mut state, 1);
+        assert_eq!(state.immediately_runnable(), vec![3, 4]); // P1's release lets P4 fit, P1 itself is gone
+        assert_eq!(state.blocked(), vec![0, 2]);
+        execute(&mut state, &[3, 4, 0, 2]);
+        assert!(state.immediately_runnable().is_empty() && state.blocked().is_empty());
+        let empty = SystemState::default();
+        assert!(empty.immediately_runnable().is_empty() && empty.blocked().is_empty());
+    }
+
+    #[test]
+    fn partial_grants_give_all_part_or_none_of_a_request() {
+        let mut state = classic();
+        assert_eq!(request_resource_partial(&mut state, 1, &[1, 0, 2]), Some(vec![1, 0, 2])); // safe as a whole
+        assert_eq!(request_resource_partial(&mut state, 0, &[0, 2, 0]), Some(vec![0, 1, 0])); // the textbook unsafe request after P1's grant, half of it is safe
+        assert_eq!(state.allocated[0], vec![0, 2, 0]);
+        assert_eq!(state.available, vec![2, 2, 0]);
+        let mut state = SystemState::from_matrices(vec![1], vec![vec![2], vec![2]], vec![vec![1], vec![0]]).unwrap();
+        let before = state.clone();
+        assert_eq!(request_resource_partial(&mut state, 1, &[1]), None); // any unit to P1 leaves P0 unable to finish
+        assert_eq!((&state.available, &state.allocated), (&before.available, &before.allocated));
+        assert_eq!(request_resource_partial(&mut state, 2, &[1]), None); // no such process
+    }
+
+    #[test]
+    fn priority_inversion_delays_p2_unless_p0_inherits_its_priority() {
+        let state = build_priority_inversion_scenario(2);
+        assert_eq!(state.available, vec![0, 1]);
+        assert_eq!(state.outstanding_request(2), &[1, 0]);
+        assert_eq!(state.find_safe_sequence_by(SequencePolicy::HighestPriorityFirst), Some(vec![1, 0, 2])); // medium P1 runs ahead of the holder
+        assert_eq!(state.find_safe_sequence_by(SequencePolicy::PriorityInheritance), Some(vec![0, 2, 1])); // P0 runs with P2's priority
+        execute(&mut state.clone(), &[1, 0, 2]);
+        execute(&mut state.clone(), &[0, 2, 1]);
+        let single = build_priority_inversion_scenario(0); // R0 is always there
+        assert_eq!(single.resource_count(), 1);
+        assert_eq!(single.find_safe_sequence_by(SequencePolicy::PriorityInheritance), Some(vec![0, 2, 1]));
+    }
+}
@@ -0,0 +1,202 @@
+// Authors: Victor Vu
+// File: stats.rs
+// Description: Statistics collected while the simulation runs
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq)] // Derive traits for printing and copy of struct
+pub struct SimStats { // Struct to hold the statistics of a run
+    pub requests: usize, // number of requests made
+    pub granted: usize, // number of requests granted
+    pub denied: usize, // number of requests denied
+    pub converted: usize, // denied requests followed by a granted smaller suggestion
+    pub safety_denied: usize, // denied requests that fit need and available, refused only because they would be unsafe
+    pub completed_at: Vec<Option<usize>>, // request step at which each process completed
+    pub cycles: Vec<usize>, // full acquire and release cycles finished by each process
+    pub min_margin: Option<i32>, // smallest safety margin seen, tracked with --margins
+    pub preempted: Vec<usize>, // times each process was chosen as a victim by deadlock recovery
+    pub request_sizes: BTreeMap<usize, usize>, // number of requests of each total size, keyed by the units asked for across every resource
+}
+
+const HISTOGRAM_BUCKETS: usize = 10; // most rows of the request size histogram
+const HISTOGRAM_WIDTH: usize = 40; // characters of the longest bar
+
+impl SimStats {
+    pub fn new(process_amount: usize) -> Self { // statistics for a run with the given number of processes
+        SimStats { completed_at: vec![None; process_amount], cycles: vec![0; process_amount], ..SimStats::default() }
+    }
+
+    pub fn record_completion(&mut self, process_id: usize) { // Function to remember the step a process completed at
+        if self.completed_at.len() <= process_id {
+            self.completed_at.resize(process_id + 1, None);
+        }
+        self.completed_at[process_id] = Some(self.requests);
+    }
+
+    pub fn completion_order(&self) -> String { // Function to print the processes in the order they completed
+        let mut completed: Vec<(usize, usize)> = self.completed_at.iter().enumerate()
+            .filter_map(|(i, step)| step.map(|step| (step, i)))
+            .collect();
+        completed.sort(); // earliest step first
+        let order: Vec<String> = completed.iter().map(|(step, i)| format!("P{} (step {})", i, step)).collect();
+        format!("Completion order: {}", if order.is_empty() { "none".to_string() } else { order.join(", ") })
+    }
+
+    pub fn record_cycle(&mut self, process_id: usize) -> usize { // Function to count a finished cycle, returns the cycles done so far
+        if self.cycles.len() <= process_id {
+            self.cycles.resize(process_id + 1, 0);
+        }
+        self.cycles[process_id] += 1;
+        self.cycles[process_id]
+    }
+
+    pub fn cycle_counts(&self) -> String { // Function to print the cycles each process finished
+        let counts: Vec<String> = self.cycles.iter().enumerate().map(|(i, c)| format!("P{} {}", i, c)).collect();
+        format!("Cycles: {}", counts.join(", "))
+    }
+
+    pub fn record_preemption(&mut self, process_id: usize) { // Function to count a process losing resources to deadlock recovery
+        if self.preempted.len() <= process_id {
+            self.preempted.resize(process_id + 1, 0);
+        }
+        self.preempted[process_id] += 1;
+    }
+
+    pub fn victim_counts(&self) -> String { // Function to print how often each victim was preempted, most first
+        let mut victims: Vec<(usize, usize)> = self.preempted.iter().enumerate().filter(|(_, &c)| c > 0).map(|(i, &c)| (c, i)).collect();
+        victims.sort_by_key(|&(c, i)| (std::cmp::Reverse(c), i));
+        let counts: Vec<String> = victims.iter().map(|(c, i)| format!("P{} {}x", i, c)).collect();
+        format!("Preemption victims: {}", if counts.is_empty() { "none".to_string() } else { counts.join(", ") })
+    }
+
+    pub fn record_margin(&mut self, margin: i32) { // Function to remember the smallest safety margin of the run
+        self.min_margin = Some(self.min_margin.map_or(margin, |m| m.min(margin)));
+    }
+
+    pub fn record(&mut self, granted: bool) { // Function to count a request outcome
+        self.requests += 1;
+        if granted {
+            self.granted += 1;
+        } else {
+            self.denied += 1;
+        }
+    }
+
+    pub fn record_request_size(&mut self, request: &[i32]) { // Function to count a request by the total units it asks for
+        let total = request.iter().map(|&r| r.max(0) as usize).sum::<usize>();
+        *self.request_sizes.entry(total).or_insert(0) += 1; // one entry per total seen, however large the total
+    }
+
+    pub fn request_histogram(&self) -> String { // Function to draw how many requests asked for each total, grouped into at most HISTOGRAM_BUCKETS ranges
+        let largest = self.request_sizes.keys().next_back().copied().unwrap_or(0);
+        let width = largest / HISTOGRAM_BUCKETS + 1; // totals per bucket, so 0..=largest fits in HISTOGRAM_BUCKETS rows
+        let mut counts = vec![0; largest / width + 1];
+        for (&total, &count) in &self.request_sizes {
+            counts[total / width] += count;
+        }
+        let buckets: Vec<(String, usize)> = counts.iter().enumerate()
+            .map(|(b, &count)| {
+                let (low, high) = (b * width, (b * width + width - 1).min(largest));
+                (if low == high { low.to_string() } else { format!("{}-{}", low, high) }, count)
+            })
+            .collect();
+        let most = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        if most == 0 {
+            return "Request sizes: none".to_string();
+        }
+        let label_width = buckets.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let bars: Vec<String> = buckets.iter()
+            .map(|(label, count)| {
+                let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(most)); // any request gets at least one #
+                format!("{:>w$} | {}", label, format!("{} {}", bar, count).trim_start(), w = label_width)
+            })
+            .collect();
+        format!("Request sizes (total units asked for):\n{}", bars.join("\n"))
+    }
+
+    pub fn safety_denial_rate(&self) -> f64 { // Function to get the fraction of requests the banker refused only for safety
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.safety_denied as f64 / self.requests as f64
+    }
+}
+
+impl fmt::Display for SimStats { // Print the statistics as a short summary
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Requests: {} | Granted: {} | Denied: {}", self.requests, self.granted, self.denied)?;
+        write!(f, " | Denied as unsafe: {} ({:.1}%)", self.safety_denied, self.safety_denial_rate() * 100.0)?;
+        if self.converted > 0 { // only smart retry converts denials
+            write!(f, " | Converted to partial grants: {}", self.converted)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)] // Derive traits for printing and copy of struct
+pub struct DurationStats { // Struct to hold the min, max and average of a series of durations
+    pub count: u32, // number of durations recorded
+    pub total: Duration, // sum of every duration
+    pub min: Option<Duration>, // shortest duration, None before the first one
+    pub max: Duration, // longest duration
+}
+
+impl DurationStats {
+    pub fn record(&mut self, duration: Duration) { // Function to add one duration
+        self.count += 1;
+        self.total += duration;
+        self.min = Some(self.min.map_or(duration, |min| min.min(duration)));
+        self.max = self.max.max(duration);
+    }
+
+    pub fn average(&self) -> Option<Duration> { // Function to get the mean duration, None if nothing was recorded
+        (self.count > 0).then(|| self.total / self.count)
+    }
+}
+
+impl fmt::Display for DurationStats { // Print as "min 1.2µs, avg 3.4µs, max 56.7µs"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min, self.average()) {
+            (Some(min), Some(average)) => write!(f, "min {:.1?}, avg {:.1?}, max {:.1?}", min, average, self.max),
+            _ => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)] // Derive traits for printing and copy of struct
+pub struct LockTimes { // Struct to hold how long the worker threads held and waited for the system state lock
+    pub held: DurationStats, // from acquiring the lock to releasing it
+    pub waited: DurationStats, // from asking for the lock to acquiring it
+}
+
+impl fmt::Display for LockTimes { // Print the hold and wait times on one line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "State lock held: {} | Waited: {} | Acquired {} times", self.held, self.waited, self.waited.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_histogram_groups_totals_into_at_most_ten_rows() {
+        let mut stats = SimStats::new(1);
+        assert_eq!(stats.request_histogram(), "Request sizes: none");
+        for request in [[0, 1], [1, 1], [2, 1], [3, 0]] {
+            stats.record_request_size(&request);
+        }
+        assert_eq!(stats.request_histogram(), format!("Request sizes (total units asked for):\n0 | 0\n1 | {} 1\n2 | {} 1\n3 | {} 2", "#".repeat(20), "#".repeat(20), "#".repeat(40)));
+        stats.record_request_size(&[i32::MAX, i32::MAX]); // one huge request adds one entry instead of growing a row per total
+        assert_eq!(stats.request_sizes.len(), 4);
+        let histogram = stats.request_histogram();
+        let rows: Vec<&str> = histogram.lines().skip(1).collect();
+        assert_eq!(rows.len(), HISTOGRAM_BUCKETS);
+        assert!(rows[0].trim_start().starts_with("0-429496729 | ") && rows[0].ends_with(" 4"));
+        assert!(rows[9].trim_start().starts_with("3865470570-4294967294 | ") && rows[9].ends_with(" 1"));
+        assert_eq!(rows.iter().map(|row| row.rsplit(' ').next().unwrap().parse::<usize>().unwrap_or(0)).sum::<usize>(), 5);
+    }
+}
@@ -0,0 +1,58 @@
+// Authors: Victor Vu
+// File: wasm.rs
+// Description: JavaScript bindings for the system state, built with the wasm feature and wasm-pack
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use serde::de::DeserializeOwned;
+use wasm_bindgen::prelude::*;
+use crate::state::{self, SystemState};
+
+#[wasm_bindgen]
+pub struct BankersState { // JavaScript class wrapping a system state
+    state: SystemState, // the wrapped state
+}
+
+fn from_js<T: DeserializeOwned>(value: JsValue, name: &str) -> Result<T, JsError> { // Function to convert a JavaScript array, naming the argument that didn't fit
+    serde_wasm_bindgen::from_value(value).map_err(|e| JsError::new(&format!("{}: {}", name, e)))
+}
+
+impl BankersState {
+    fn check_process(&self, pid: usize) -> Result<(), JsError> { // Function to throw for a process that doesn't exist
+        if pid >= self.state.process_count() {
+            return Err(JsError::new(&format!("P{} doesn't exist", pid)));
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl BankersState {
+    #[wasm_bindgen(constructor)]
+    pub fn new(available: JsValue, max: JsValue, allocated: JsValue) -> Result<BankersState, JsError> { // arrays of numbers, max and allocated one row per process
+        let state = SystemState::from_matrices(from_js(available, "available")?, from_js(max, "max")?, from_js(allocated, "allocated")?)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(BankersState { state })
+    }
+
+    pub fn request_resource(&mut self, pid: usize, request: JsValue) -> Result<bool, JsError> { // granted only if the state stays safe
+        self.check_process(pid)?;
+        let request: Vec<i32> = from_js(request, "request")?;
+        if request.len() != self.state.resource_count() {
+            return Err(JsError::new(&format!("request has {} values but there are {} resources", request.len(), self.state.resource_count())));
+        }
+        Ok(state::request_resource(&mut self.state, pid, &request))
+    }
+
+    pub fn release(&mut self, pid: usize) -> Result<(), JsError> { // everything the process holds goes back to available
+        self.check_process(pid)?;
+        state::release_resource(&mut self.state, pid);
+        Ok(())
+    }
+
+    pub fn safe_check(&self) -> Result<JsValue, JsError> { // a safe sequence as an array, null if the state is unsafe
+        match state::find_safe_sequence(&self.state) {
+            Some(sequence) => serde_wasm_bindgen::to_value(&sequence).map_err(|e| JsError::new(&e.to_string())),
+            None => Ok(JsValue::NULL),
+        }
+    }
+}
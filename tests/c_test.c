@@ -0,0 +1,39 @@
+// Authors: Victor Vu
+// File: c_test.c
+// Description: Lifecycle of a state through the C interface, compiled and run by tests/ffi_c.rs
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#include <stdio.h>
+#include <stdbool.h>
+#include "bankers.h"
+
+#define CHECK(condition) do { if (!(condition)) { fprintf(stderr, "line %d: %s\n", __LINE__, #condition); return 1; } } while (0)
+
+int main(void) {
+    const int32_t available[3] = {3, 3, 2}; // the textbook state
+    const int32_t max[5][3] = {{7, 5, 3}, {3, 2, 2}, {9, 0, 2}, {2, 2, 2}, {4, 3, 3}};
+    const int32_t allocated[5][3] = {{0, 1, 0}, {2, 0, 0}, {3, 0, 2}, {2, 1, 1}, {0, 0, 2}};
+    const int32_t request[3] = {1, 0, 2};
+    const int32_t unsafe_request[3] = {3, 3, 0};
+
+    SystemState *state = bankers_state_create(5, 3);
+    CHECK(state != NULL);
+    CHECK(bankers_set_available(state, available, 3));
+    CHECK(!bankers_set_available(state, available, 2)); // wrong length
+    for (size_t pid = 0; pid < 5; pid++) {
+        CHECK(bankers_set_process(state, pid, max[pid], allocated[pid], 3));
+    }
+    CHECK(!bankers_set_process(state, 5, max[0], allocated[0], 3)); // no such process
+    CHECK(bankers_safe(state));
+    CHECK(bankers_request(state, 1, request, 3)); // the textbook grant for P1
+    CHECK(!bankers_request(state, 4, unsafe_request, 3)); // more than available
+    CHECK(bankers_safe(state));
+    bankers_release(state, 1);
+    CHECK(!bankers_request(state, 1, request, 3)); // a completed process asks for nothing
+    CHECK(bankers_safe(state));
+    CHECK(!bankers_safe(NULL));
+    bankers_destroy(state);
+    bankers_destroy(NULL); // ignored
+    puts("c_test passed");
+    return 0;
+}
@@ -0,0 +1,222 @@
+// Authors: Victor Vu
+// File: cli.rs
+// Description: Integration tests running the simulation binary on the inputs in tests/data
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+use std::process::{Command, Output};
+
+fn data(name: &str) -> String { // Function to get the path of a test input
+    format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn bankers() -> Command { // Function to start the binary without any BANKERS_ variables from the environment
+    let mut command = Command::new(env!("CARGO_BIN_EXE_vu_bankers_algo"));
+    for (key, _) in std::env::vars().filter(|(key, _)| key.starts_with("BANKERS_") || key == "RUST_LOG") {
+        command.env_remove(key);
+    }
+    command
+}
+
+fn stdout_without_timings(output: &Output) -> String { // Function to drop the lines that measure wall clock time
+    String::from_utf8_lossy(&output.stdout).lines().filter(|line| !line.starts_with("State lock held")).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn seed_from_environment_matches_seed_flag() {
+    let common = [data("classic.txt"), "--workers".to_string(), "1".to_string(), "--sleep-ms".to_string(), "0".to_string()]; // one worker keeps the output order fixed
+    let from_flag = bankers().args(&common).args(["--seed", "42"]).output().unwrap();
+    let from_env = bankers().args(&common).env("BANKERS_SEED", "42").output().unwrap();
+    assert!(from_flag.status.success(), "{}", String::from_utf8_lossy(&from_flag.stderr));
+    assert!(stdout_without_timings(&from_flag).contains("All processes have finished."));
+    assert_eq!(from_flag.status.code(), from_env.status.code());
+    assert_eq!(stdout_without_timings(&from_flag), stdout_without_timings(&from_env));
+}
+
+#[test]
+fn seed_flag_overrides_environment() {
+    let common = [data("classic.txt"), "--workers".to_string(), "1".to_string(), "--sleep-ms".to_string(), "0".to_string()];
+    let flag_only = bankers().args(&common).args(["--seed", "7"]).output().unwrap();
+    let both = bankers().args(&common).args(["--seed", "7"]).env("BANKERS_SEED", "42").output().unwrap();
+    assert_eq!(stdout_without_timings(&flag_only), stdout_without_timings(&both));
+}
+
+fn exit_code(args: &[&str]) -> (Option<i32>, String) { // Function to run the binary and return its exit code and stderr
+    let output = bankers().args(args).output().unwrap();
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn safe_input_exits_0() {
+    assert_eq!(exit_code(&[&data("classic.txt"), "--sleep-ms", "0", "--seed", "1"]).0, Some(0));
+}
+
+#[test]
+fn allocation_above_max_exits_1() {
+    let (code, stderr) = exit_code(&[&data("exceeds_max.txt"), "--sleep-ms", "0"]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains("P0 holds 3 of R0 but its max is 2"), "{}", stderr);
+}
+
+#[test]
+fn negative_allocation_exits_1() {
+    assert_eq!(exit_code(&[&data("negative.txt"), "--sleep-ms", "0"]).0, Some(1));
+}
+
+#[test]
+fn missing_file_and_unknown_flag_exit_1() {
+    assert_eq!(exit_code(&[&data("does_not_exist.txt")]).0, Some(1));
+    assert_eq!(exit_code(&[&data("classic.txt"), "--no-such-flag"]).0, Some(1));
+}
+
+#[test]
+fn unsafe_input_exits_2() {
+    let (code, stderr) = exit_code(&[&data("unsafe.txt"), "--sleep-ms", "0"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("Initial state is unsafe"), "{}", stderr);
+}
+
+#[test]
+fn watchdog_abort_exits_3() {
+    assert_eq!(exit_code(&[&data("classic.txt"), "--sleep-ms", "0", "--workers", "1", "--seed", "1", "--watchdog", "1"]).0, Some(3)); // the first denial trips it
+}
+
+#[test]
+fn worker_panic_exits_4() {
+    let output = bankers().args([&data("classic.txt"), "--sleep-ms", "0", "--seed", "1"]).env("BANKERS_FAIL_WORKER", "1").output().unwrap(); // honored by debug builds, which the tests run
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("A worker thread panicked, the simulation did not finish."));
+}
+
+#[test]
+fn help_documents_the_exit_codes() {
+    let output = bankers().arg("--help").output().unwrap();
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    for line in ["0  initial state is safe", "1  invalid input", "2  initial state is unsafe", "3  the watchdog", "4  internal error", "5  interrupted"] {
+        assert!(help.contains(line), "missing '{}'", line);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_exits_5_with_a_partial_report() {
+    let child = bankers().args([&data("classic.txt"), "--sleep-ms", "100", "--seed", "1"]).stdout(std::process::Stdio::piped()).spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300)); // let the workers start
+    assert!(Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap().success());
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Interrupted, partial report:"));
+}
+
+#[cfg(unix)]
+#[test]
+fn sigusr1_writes_a_state_dump() {
+    let dir = std::env::temp_dir().join(format!("bankers_dump_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = bankers().args([&data("classic.txt"), "--sleep-ms", "100", "--seed", "1"]).current_dir(&dir).stdout(std::process::Stdio::null()).spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300)); // let the handler get installed
+    assert!(Command::new("kill").args(["-USR1", &child.id().to_string()]).status().unwrap().success());
+    let dump = dir.join("state_dump_1.txt");
+    for _ in 0..50 { // the next worker holding the lock writes it
+        if dump.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    child.wait().unwrap();
+    let contents = std::fs::read_to_string(&dump).expect("no dump was written");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(contents.contains("Completed:"), "{}", contents);
+}
+
+#[test]
+fn dry_run_uses_the_normal_exit_codes_so_unsafe_is_2_not_1() {
+    let safe = bankers().args([&data("classic.txt"), "--dry-run"]).output().unwrap();
+    assert_eq!(safe.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&safe.stdout).lines().any(|line| line == "SAFE"));
+    let unsafe_state = bankers().args([&data("unsafe.txt"), "--dry-run"]).output().unwrap();
+    assert_eq!(unsafe_state.status.code(), Some(2)); // same code as an unsafe input without --dry-run
+    assert!(String::from_utf8_lossy(&unsafe_state.stdout).lines().any(|line| line == "UNSAFE"));
+    assert_eq!(exit_code(&[&data("exceeds_max.txt"), "--dry-run"]).0, Some(1)); // 1 stays for input that can't be read
+}
+
+#[test]
+fn runnable_count_is_only_printed_with_verbose() {
+    let common = [data("classic.txt"), "--workers".to_string(), "1".to_string(), "--sleep-ms".to_string(), "0".to_string(), "--seed".to_string(), "1".to_string()];
+    let quiet = bankers().args(&common).output().unwrap();
+    let verbose = bankers().args(&common).arg("--verbose").output().unwrap();
+    assert!(!String::from_utf8_lossy(&quiet.stdout).contains("can run to completion right now"));
+    assert!(String::from_utf8_lossy(&verbose.stdout).contains("can run to completion right now"));
+}
+
+fn summary_count(stdout: &str, label: &str) -> i32 { // Function to read one count from the Requests: summary line
+    let summary = stdout.lines().find(|line| line.starts_with("Requests: ")).unwrap();
+    summary.split(" | ").find_map(|part| part.strip_prefix(label)).unwrap().split(' ').next().unwrap().parse().unwrap()
+}
+
+#[test]
+fn smart_retry_counts_each_request_once() {
+    let output = bankers().args([&data("classic.txt"), "--workers", "1", "--sleep-ms", "0", "--seed", "1", "--smart-retry"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let count = |label: &str| summary_count(&stdout, label);
+    assert_eq!(count("Requests: "), count("Granted: ") + count("Denied: "));
+    let sizes: i32 = stdout.lines().skip_while(|line| !line.starts_with("Request sizes")).skip(1).take_while(|line| line.contains(" | ") && !line.starts_with("State lock")).map(|line| line.rsplit(' ').next().unwrap().parse::<i32>().unwrap()).sum();
+    assert_eq!(sizes, count("Requests: ")); // one size per request, even when a denial was converted
+}
+
+#[test]
+fn clamped_requests_fit_what_is_available() {
+    for seed in ["1", "2", "3", "4", "5"] {
+        let output = bankers().args([&data("classic.txt"), "--workers", "1", "--sleep-ms", "0", "--seed", seed, "--clamp"]).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let amounts = |list: &str| -> Vec<i32> { list.trim_matches(|c| c == '[' || c == ']').split(", ").map(|n| n.parse().unwrap()).collect() };
+        let mut available = Vec::new();
+        for line in stdout.lines() {
+            if let Some(list) = line.strip_prefix("Now available: ") {
+                available = amounts(list);
+            } else if let Some(rest) = line.split_once(": Requesting ").map(|(_, rest)| rest) {
+                let request = amounts(rest.split(" ...").next().unwrap());
+                assert!(request.iter().zip(&available).all(|(r, a)| r <= a), "seed {}: {}", seed, line); // one worker, so available is still what was printed before
+            }
+        }
+    }
+}
+
+#[test]
+fn clamped_requests_are_only_denied_as_unsafe() {
+    for seed in ["1", "2", "3", "4", "5"] {
+        let output = bankers().args([&data("classic.txt"), "--workers", "1", "--sleep-ms", "0", "--seed", seed, "--clamp"]).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(summary_count(&stdout, "Denied: "), summary_count(&stdout, "Denied as unsafe: "), "seed {}", seed); // no request asked for more than available
+    }
+}
+
+#[test]
+fn weighted_scheduling_leaves_weight_0_until_nothing_else_is_waiting() {
+    for seed in ["1", "2", "3"] {
+        let output = bankers().args([&data("weighted.json"), "--weighted", "--workers", "1", "--sleep-ms", "0", "--seed", seed]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        let first_p0 = lines.iter().position(|line| line.starts_with("Process 0: Requesting")).unwrap();
+        for process in 1..5 {
+            let released = lines.iter().position(|line| line.starts_with(&format!("Process {}: has all resources", process))).unwrap();
+            assert!(released < first_p0, "seed {}: P0 ran before P{} finished", seed, process); // weight 0 is only taken once the others are gone
+        }
+    }
+}
+
+#[test]
+fn split_lock_run_finishes_every_process() {
+    let output = bankers().args([&data("classic.txt"), "--split-lock", "--sleep-ms", "0", "--seed", "1", "--clamp"]).output().unwrap(); // a worker per process, all requesting at once
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("All processes have finished."));
+    assert_eq!(summary_count(&stdout, "Requests: "), summary_count(&stdout, "Granted: ") + summary_count(&stdout, "Denied: "));
+    assert_eq!(summary_count(&stdout, "Denied: "), summary_count(&stdout, "Denied as unsafe: ")); // clamped requests always fit
+    assert_eq!(stdout.lines().find(|line| line.starts_with("Completion order: ")).unwrap().matches("(step").count(), 5);
+    let (code, stderr) = exit_code(&[&data("classic.txt"), "--split-lock", "--smart-retry"]);
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains("--split-lock can't be combined with --smart-retry"), "{}", stderr);
+}
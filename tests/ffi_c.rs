@@ -0,0 +1,26 @@
+// Authors: Victor Vu
+// File: ffi_c.rs
+// Description: Compiles tests/c_test.c against the C interface and runs it, only with the ffi feature
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#![cfg(feature = "ffi")]
+use std::process::Command;
+
+#[test]
+fn c_program_runs_the_whole_lifecycle() {
+    let deps = std::env::current_exe().unwrap().parent().unwrap().to_path_buf(); // target/debug/deps, where the cdylib is built
+    let binary = std::env::temp_dir().join(format!("bankers_c_test_{}", std::process::id()));
+    let compiled = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(format!("{}/tests/c_test.c", env!("CARGO_MANIFEST_DIR")))
+        .arg(format!("-I{}", env!("OUT_DIR"))) // build.rs writes bankers.h there
+        .arg(format!("-L{}", deps.display()))
+        .args(["-lvu_bankers_algo", "-o"])
+        .arg(&binary)
+        .output()
+        .unwrap();
+    assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+    let run = Command::new(&binary).env("LD_LIBRARY_PATH", &deps).env("DYLD_LIBRARY_PATH", &deps).output().unwrap();
+    std::fs::remove_file(&binary).ok();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "c_test passed\n");
+}
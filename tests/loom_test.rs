@@ -0,0 +1,81 @@
+// Authors: Victor Vu
+// File: loom_test.rs
+// Description: Model checked concurrent requests against one locked SystemState and SplitState, run with RUSTFLAGS="--cfg loom" cargo test --test loom_test
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#![cfg(loom)]
+use loom::sync::{Arc, Mutex};
+use loom::thread;
+use vu_bankers_algo::{is_consistent, request_resource, safe_check, SplitState, SystemState};
+
+fn three_by_two() -> SystemState { // Function to build a safe state with 3 processes and 2 resources, P0 and P1 can't both get their request
+    SystemState::from_matrices(vec![2, 2], vec![vec![3, 2], vec![2, 3], vec![1, 1]], vec![vec![1, 0], vec![0, 1], vec![0, 0]]).unwrap()
+}
+
+#[test]
+fn two_concurrent_requests_on_one_locked_state_are_decided_one_after_the_other() {
+    loom::model(|| {
+        let requests = [(0, vec![2, 1]), (1, vec![1, 2])];
+        let state = Arc::new(Mutex::new(three_by_two()));
+        let handles: Vec<_> = requests
+            .iter()
+            .cloned()
+            .map(|(pid, request)| {
+                let state = state.clone();
+                thread::spawn(move || request_resource(&mut state.lock().unwrap(), pid, &request)) // the check and the grant under one lock
+            })
+            .collect();
+        let granted: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let state = state.lock().unwrap();
+        assert!(is_consistent(&state));
+        assert!(safe_check(&state));
+        assert_eq!(granted.iter().filter(|&&g| g).count(), 1, "whichever runs first is granted and leaves too little for the other");
+        let first = granted.iter().position(|&g| g).unwrap();
+        let mut expected = three_by_two(); // the same request made alone
+        assert!(request_resource(&mut expected, requests[first].0, &requests[first].1));
+        assert_eq!(state.available(), expected.available());
+        assert!((0..3).all(|pid| state.allocated(pid) == expected.allocated(pid) && state.need(pid) == expected.need(pid)));
+    });
+}
+
+#[test]
+fn two_concurrent_requests_keep_the_state_safe() {
+    loom::model(|| {
+        let initial = three_by_two();
+        let split = Arc::new(SplitState::from_state(&initial));
+        let handles: Vec<_> = [(0, vec![2, 1]), (1, vec![1, 2])]
+            .into_iter()
+            .map(|(pid, request)| {
+                let split = split.clone();
+                thread::spawn(move || split.request_resource(pid, &request))
+            })
+            .collect();
+        let granted: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let state = split.to_state();
+        assert!(safe_check(&state) && is_consistent(&state));
+        assert_eq!(state.total_capacity(), initial.total_capacity()); // nothing was lost or made up
+        assert!(granted.iter().any(|&g| g), "one of the requests always fits");
+        assert!(!granted.iter().all(|&g| g), "both grants together would leave available at -1");
+    });
+}
+
+#[test]
+fn request_racing_a_release_sees_the_release_or_not_at_all() {
+    loom::model(|| {
+        let initial = three_by_two();
+        let split = Arc::new(SplitState::from_state(&initial));
+        let releaser = {
+            let split = split.clone();
+            thread::spawn(move || split.release_resource(0))
+        };
+        let requester = {
+            let split = split.clone();
+            thread::spawn(move || split.request_resource(2, &[1, 1]))
+        };
+        releaser.join().unwrap();
+        assert!(requester.join().unwrap()); // safe before and after the release
+        let state = split.to_state();
+        assert!(safe_check(&state) && is_consistent(&state));
+        assert_eq!(state.available(), &[2, 1]); // [2, 2] plus the [1, 0] P0 released minus the [1, 1] P2 got
+    });
+}
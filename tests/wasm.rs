@@ -0,0 +1,48 @@
+// Authors: Victor Vu
+// File: wasm.rs
+// Description: Tests of the JavaScript bindings, run with wasm-pack test --headless --chrome -- --features wasm
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+use vu_bankers_algo::wasm::BankersState;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn js(value: impl serde::Serialize) -> JsValue { // Function to convert a Rust array the way JavaScript would pass it
+    serde_wasm_bindgen::to_value(&value).unwrap()
+}
+
+fn classic() -> BankersState { // the textbook state with 5 processes and 3 resources
+    BankersState::new(
+        js([3, 3, 2]),
+        js([[7, 5, 3], [3, 2, 2], [9, 0, 2], [2, 2, 2], [4, 3, 3]]),
+        js([[0, 1, 0], [2, 0, 0], [3, 0, 2], [2, 1, 1], [0, 0, 2]]),
+    )
+    .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn safe_check_returns_the_sequence_for_a_safe_state() {
+    let sequence = classic().safe_check().unwrap();
+    assert!(!sequence.is_null());
+    assert_eq!(serde_wasm_bindgen::from_value::<Vec<usize>>(sequence).unwrap(), vec![1, 3, 4, 0, 2]);
+}
+
+#[wasm_bindgen_test]
+fn safe_check_returns_null_for_an_unsafe_state() {
+    let state = BankersState::new(js([0]), js([[5], [2]]), js([[1], [1]])).unwrap();
+    assert!(state.safe_check().unwrap().is_null());
+}
+
+#[wasm_bindgen_test]
+fn requests_and_releases_go_through_the_safety_check() {
+    let mut state = classic();
+    assert!(state.request_resource(1, js([1, 0, 2])).unwrap());
+    assert!(!state.request_resource(0, js([0, 2, 0])).unwrap()); // the textbook unsafe request
+    state.release(1).unwrap();
+    assert!(!state.safe_check().unwrap().is_null());
+    assert!(state.request_resource(5, js([0, 0, 0])).is_err()); // no such process
+    assert!(state.request_resource(0, js([1, 0])).is_err()); // wrong length
+}
@@ -0,0 +1,42 @@
+// Authors: Victor Vu
+// File: index.js
+// Description: Drives the browser demo through the BankersState class from the wasm feature
+// Copyright (C) 2024 Victor V. Vu
+// License: GNU GPL v3 - See https://www.gnu.org/licenses/gpl-3.0.en.html
+import init, { BankersState } from "./pkg/vu_bankers_algo.js";
+
+await init(); // load the wasm module
+
+const state = new BankersState(
+  [3, 3, 2], // available
+  [[7, 5, 3], [3, 2, 2], [9, 0, 2], [2, 2, 2], [4, 3, 3]], // max
+  [[0, 1, 0], [2, 0, 0], [3, 0, 2], [2, 1, 1], [0, 0, 2]], // allocated
+);
+const log = document.getElementById("log");
+
+function show(line) { // Function to append a line and the current safe sequence to the log
+  const sequence = state.safe_check(); // null if the state is unsafe
+  log.textContent += `${line}\nSafe sequence: ${sequence === null ? "none" : sequence.map((i) => `P${i}`).join(" → ")}\n\n`;
+}
+
+document.getElementById("ask").addEventListener("click", () => {
+  const pid = Number(document.getElementById("pid").value);
+  const request = document.getElementById("request").value.trim().split(/\s+/).map(Number);
+  try {
+    show(`P${pid} asks for [${request}]: ${state.request_resource(pid, request) ? "granted" : "denied"}`);
+  } catch (e) { // unknown process or wrong number of values
+    show(`P${pid}: ${e.message}`);
+  }
+});
+
+document.getElementById("release").addEventListener("click", () => {
+  const pid = Number(document.getElementById("pid").value);
+  try {
+    state.release(pid);
+    show(`P${pid} released everything`);
+  } catch (e) {
+    show(`P${pid}: ${e.message}`);
+  }
+});
+
+show("Initial state");